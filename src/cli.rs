@@ -0,0 +1,174 @@
+//! Command line arguments of the `etl_evm` binary.
+use clap::{Parser, ValueEnum};
+
+#[derive(Parser, Debug)]
+#[command(name = "etl_evm")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+#[derive(Parser, Debug)]
+pub enum Commands {
+    #[command(name = "get_uniswapv2_event_csv")]
+    GetUniv2Event(Univ2EventArgs),
+    #[command(name = "subscribe_uniswapv2_event_csv")]
+    SubscribeUniv2Event(SubscribeUniv2EventArgs),
+    #[command(name = "subscribe_uniswapv2_event_db")]
+    SubscribeUniv2EventDb(SubscribeUniv2EventDbArgs),
+    #[command(name = "subscribe_uniswapv2_create_mq")]
+    SubscribeUniv2EventMq(SubscribeUniv2EventMqArgs),
+    #[command(name = "subscribe_uniswapv2_pending")]
+    SubscribeUniv2Pending(SubscribeUniv2PendingArgs),
+    #[command(name = "transform_uniswapv2_log_csv")]
+    TransformUniv2Log(TransformUniv2LogArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct Univ2EventArgs {
+    #[arg(long)]
+    pub http_url: Option<String>,
+    #[arg(long)]
+    pub from_block: Option<u64>,
+    #[arg(long)]
+    pub to_block: Option<u64>,
+    /// RFC3339 time or YYYY-MM-DD date, resolved to the first block at or after it.
+    #[arg(long, conflicts_with = "from_block")]
+    pub from_time: Option<String>,
+    /// RFC3339 time or YYYY-MM-DD date (whole day), resolved to the last block at or before it.
+    #[arg(long, conflicts_with = "to_block")]
+    pub to_time: Option<String>,
+    #[arg(long)]
+    pub router_address: Option<String>,
+    #[arg(long)]
+    pub output_dir: Option<String>,
+    /// Decode the router call of each swap's transaction (sender, path, amountOutMin, deadline).
+    #[arg(long)]
+    pub enrich_intent: bool,
+    /// Add token symbols, decimals and the pair name to Mint, Burn and Swap records.
+    #[arg(long)]
+    pub enrich_tokens: bool,
+    /// Add mid prices before and after, price impact and slippage to Swap records.
+    #[arg(long)]
+    pub enrich_impact: bool,
+    /// Only write records for which EVENT=EXPR holds, e.g. `swap=amount_usd > 10000`, repeatable.
+    #[arg(long)]
+    pub filter: Vec<String>,
+    /// `[chains.<name>]` sections to run, repeatable; `all` runs every configured chain.
+    #[arg(long)]
+    pub chain: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SubscribeUniv2EventArgs {
+    #[arg(long)]
+    pub ws_url: Option<String>,
+    #[arg(long)]
+    pub pair_address: Vec<String>,
+    #[arg(long)]
+    pub output_dir: Option<String>,
+    /// Decode the router call of each swap's transaction (sender, path, amountOutMin, deadline).
+    #[arg(long)]
+    pub enrich_intent: bool,
+    /// Add token symbols, decimals and the pair name to Mint, Burn and Swap records.
+    #[arg(long)]
+    pub enrich_tokens: bool,
+    /// Add mid prices before and after, price impact and slippage to Swap records.
+    #[arg(long)]
+    pub enrich_impact: bool,
+    /// Only write records for which EVENT=EXPR holds, e.g. `swap=amount_usd > 10000`, repeatable.
+    #[arg(long)]
+    pub filter: Vec<String>,
+    /// `[chains.<name>]` sections to run, repeatable; `all` runs every configured chain.
+    #[arg(long)]
+    pub chain: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SubscribeUniv2EventDbArgs {
+    #[arg(long)]
+    pub ws_url: Option<String>,
+    #[arg(long)]
+    pub pair_address: Vec<String>,
+    #[arg(long)]
+    pub auth_token: Option<String>,
+    #[arg(long)]
+    pub write_url: Option<String>,
+    /// Decode the router call of each swap's transaction (sender, path, amountOutMin, deadline).
+    #[arg(long)]
+    pub enrich_intent: bool,
+    /// Add token symbols, decimals and the pair name to Mint, Burn and Swap records.
+    #[arg(long)]
+    pub enrich_tokens: bool,
+    /// Add mid prices before and after, price impact and slippage to Swap records.
+    #[arg(long)]
+    pub enrich_impact: bool,
+    /// Only write records for which EVENT=EXPR holds, e.g. `swap=amount_usd > 10000`, repeatable.
+    #[arg(long)]
+    pub filter: Vec<String>,
+    /// `[chains.<name>]` sections to run, repeatable; `all` runs every configured chain.
+    #[arg(long)]
+    pub chain: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SubscribeUniv2EventMqArgs {
+    #[arg(long)]
+    pub ws_url: Option<String>,
+    #[arg(long)]
+    pub router_address: Option<String>,
+    #[arg(long)]
+    pub broker_url: Option<String>,
+    /// Score the token risk of new pairs, pushing each one after its first buy or this many blocks.
+    #[arg(long)]
+    pub risk_blocks: Option<u64>,
+    /// Only write records for which EVENT=EXPR holds, e.g. `swap=amount_usd > 10000`, repeatable.
+    #[arg(long)]
+    pub filter: Vec<String>,
+    /// `[chains.<name>]` sections to run, repeatable; `all` runs every configured chain.
+    #[arg(long)]
+    pub chain: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PendingSink {
+    Csv,
+    Mq,
+}
+
+#[derive(Parser, Debug)]
+pub struct SubscribeUniv2PendingArgs {
+    #[arg(long)]
+    pub ws_url: Option<String>,
+    #[arg(long)]
+    pub router_address: Vec<String>,
+    #[arg(long, value_enum, default_value_t = PendingSink::Csv)]
+    pub sink: PendingSink,
+    #[arg(long)]
+    pub output_dir: Option<String>,
+    #[arg(long)]
+    pub broker_url: Option<String>,
+    /// Only write records for which EVENT=EXPR holds, e.g. `swap=amount_usd > 10000`, repeatable.
+    #[arg(long)]
+    pub filter: Vec<String>,
+    /// `[chains.<name>]` sections to run, repeatable; `all` runs every configured chain.
+    #[arg(long)]
+    pub chain: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct TransformUniv2LogArgs {
+    /// Saved `eth_getLogs` output: JSON array, JSON-RPC response or JSONL, repeatable.
+    #[arg(long, required = true)]
+    pub input: Vec<String>,
+    /// JSON array of pairs with token decimals, required to transform Swap logs.
+    #[arg(long)]
+    pub pair_metadata: Option<String>,
+    #[arg(long)]
+    pub chain_id: Option<u64>,
+    #[arg(long)]
+    pub output_dir: Option<String>,
+    /// Only write records for which EVENT=EXPR holds, e.g. `swap=amount_usd > 10000`, repeatable.
+    #[arg(long)]
+    pub filter: Vec<String>,
+}
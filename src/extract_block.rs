@@ -1,35 +1,137 @@
+use crate::extract_fixture::{RecordingTransport, ReplayTransport};
 use alloy::providers::{DynProvider, Provider, ProviderBuilder, WsConnect};
+use alloy::rpc::client::{BuiltInConnectionString, RpcClient};
 use alloy::rpc::types::eth::Block;
 use alloy::rpc::types::eth::Header;
-use eyre::Result;
+use alloy::rpc::types::eth::Transaction;
+use alloy::transports::{BoxTransport, Transport};
+use eyre::{Context, Result};
 use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
 
 pub struct EvmBlock {
     pub provider: DynProvider,
+    pub chain_id: u64,
+}
+
+/// Timestamp -> first block at or after it, persisted between runs so repeated
+/// date based reports skip the binary search.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BlockTimeCache {
+    pub boundaries: BTreeMap<u64, u64>,
+}
+
+impl BlockTimeCache {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = std::fs::read_to_string(path).context("Failed to read block time cache")?;
+        serde_json::from_str(&data).context("Failed to parse block time cache")
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, data).context("Failed to write block time cache")
+    }
 }
 
 impl EvmBlock {
+    /// Honours `ETL_RPC_FIXTURE` so a whole command can be recorded or replayed offline.
     pub async fn new(url: &str) -> Result<Self> {
+        match std::env::var("ETL_RPC_FIXTURE") {
+            Ok(fixture_path) => Self::new_with_fixture(url, Path::new(&fixture_path)).await,
+            Err(_) => Self::connect(url).await,
+        }
+    }
+
+    async fn connect(url: &str) -> Result<Self> {
         if url.starts_with("ws://") || url.starts_with("wss://") {
             let ws_connect = WsConnect::new(url);
             let ws_provider = ProviderBuilder::new().connect_ws(ws_connect).await?;
+            let chain_id = ws_provider.get_chain_id().await?;
             Ok(Self {
                 provider: ws_provider.erased(),
+                chain_id,
             })
         } else {
             let http_url = url.parse()?;
             let http_provider = ProviderBuilder::new().connect_http(http_url);
+            let chain_id = http_provider.get_chain_id().await?;
             Ok(Self {
                 provider: http_provider.erased(),
+                chain_id,
             })
         }
     }
 
+    pub async fn from_transport(transport: BoxTransport) -> Result<Self> {
+        let provider = ProviderBuilder::new().connect_client(RpcClient::new(transport, false));
+        let chain_id = provider.get_chain_id().await?;
+        Ok(Self {
+            provider: provider.erased(),
+            chain_id,
+        })
+    }
+
+    /// Talks to `url` and saves every JSON-RPC request/response pair into `fixture_path`.
+    pub async fn new_recording(url: &str, fixture_path: &Path) -> Result<Self> {
+        let inner = url
+            .parse::<BuiltInConnectionString>()?
+            .connect_boxed()
+            .await?;
+        let transport = RecordingTransport::new(inner, fixture_path)?;
+        Self::from_transport(transport.boxed()).await
+    }
+
+    /// Serves responses from `fixture_path` only; unrecorded requests fail.
+    pub async fn new_replay(fixture_path: &Path) -> Result<Self> {
+        let transport = ReplayTransport::new(fixture_path)?;
+        Self::from_transport(transport.boxed()).await
+    }
+
+    /// Picks the provider from `ETL_RPC_MODE`: `record` refreshes the fixture from `url`,
+    /// `live` ignores it, otherwise the fixture is replayed and must exist.
+    pub async fn new_with_fixture(url: &str, fixture_path: &Path) -> Result<Self> {
+        match std::env::var("ETL_RPC_MODE").as_deref() {
+            Ok("record") => Self::new_recording(url, fixture_path).await,
+            Ok("live") => Self::connect(url).await,
+            _ if fixture_path.exists() => Self::new_replay(fixture_path).await,
+            _ => Err(eyre::eyre!(
+                "RPC fixture {:?} not found; record it with ETL_RPC_MODE=record or set ETL_RPC_MODE=live",
+                fixture_path
+            )),
+        }
+    }
+
+    pub fn ensure_chain_id(&self, expected: Option<u64>) -> Result<()> {
+        match expected {
+            Some(expected) if expected != self.chain_id => Err(eyre::eyre!(
+                "eth_chainId returned {}, but config expects chain {}",
+                self.chain_id,
+                expected
+            )),
+            _ => Ok(()),
+        }
+    }
+
     pub async fn subscribe_block_header(&self) -> Result<impl StreamExt<Item = Header>> {
         let sub = self.provider.subscribe_blocks().await?;
         Ok(sub.into_stream())
     }
 
+    pub async fn subscribe_pending_transactions(
+        &self,
+    ) -> Result<impl StreamExt<Item = Transaction>> {
+        let sub = self.provider.subscribe_full_pending_transactions().await?;
+        Ok(sub.into_stream())
+    }
+
     pub async fn get_latest_block_number(&self) -> Result<u64> {
         let latest_block_number = self.provider.get_block_number().await?;
         Ok(latest_block_number)
@@ -44,16 +146,80 @@ impl EvmBlock {
             .unwrap();
         Ok(block_raw_data)
     }
+
+    pub async fn get_block_timestamp(&self, block_number: u64) -> Result<u64> {
+        let block = self
+            .provider
+            .get_block_by_number(block_number.into())
+            .await?
+            .ok_or_else(|| eyre::eyre!("Block {} not found", block_number))?;
+        Ok(block.header.timestamp)
+    }
+
+    /// Binary search for the first block with `timestamp >= target`.
+    /// Returns `None` when the target is later than the latest block.
+    pub async fn get_block_by_timestamp(
+        &self,
+        target: u64,
+        cache: &mut BlockTimeCache,
+    ) -> Result<Option<u64>> {
+        if let Some(block_number) = cache.boundaries.get(&target) {
+            return Ok(Some(*block_number));
+        }
+
+        let latest_block_number = self.get_latest_block_number().await?;
+        if self.get_block_timestamp(latest_block_number).await? < target {
+            return Ok(None);
+        }
+
+        // Earlier resolutions bound the search window: block timestamps never decrease.
+        let mut low = cache
+            .boundaries
+            .range(..target)
+            .next_back()
+            .map_or(0, |(_, block)| *block);
+        let mut high = cache
+            .boundaries
+            .range(target..)
+            .next()
+            .map_or(latest_block_number, |(_, block)| *block)
+            .min(latest_block_number);
+
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.get_block_timestamp(mid).await? < target {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        cache.boundaries.insert(target, low);
+        Ok(Some(low))
+    }
+
+    /// Last block with `timestamp <= target`, clamped to the latest block.
+    pub async fn get_last_block_by_timestamp(
+        &self,
+        target: u64,
+        cache: &mut BlockTimeCache,
+    ) -> Result<u64> {
+        match self.get_block_by_timestamp(target + 1, cache).await? {
+            Some(block_number) => Ok(block_number.saturating_sub(1)),
+            None => self.get_latest_block_number().await,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::init::AppConfig;
-    
+
     use log::info;
 
     #[tokio::test]
+    #[ignore = "subscribes to a live WebSocket node"]
     async fn test_evm_block_ws() -> Result<()> {
         let app_config = AppConfig::new().unwrap();
         let log_level = app_config.init_log().unwrap();
@@ -88,7 +254,12 @@ mod tests {
         info!("app_config.log_level : {:?}", log_level);
         info!("app_config.eth: {:#?}", app_config.eth);
 
-        let evm_block = EvmBlock::new(&app_config.eth.http_url).await.unwrap();
+        let evm_block = EvmBlock::new_with_fixture(
+            &app_config.eth.http_url,
+            std::path::Path::new("data/fixtures/test_evm_block_http.json"),
+        )
+        .await
+        .unwrap();
         let new_block_number = evm_block.get_latest_block_number().await.unwrap();
         info!("get_latest_block_number : {:?}", new_block_number);
         let new_block_data = evm_block
@@ -106,4 +277,33 @@ mod tests {
         );
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_evm_block_by_timestamp() -> Result<()> {
+        let app_config = AppConfig::new().unwrap();
+        let log_level = app_config.init_log().unwrap();
+        info!("app_config.log_level : {:?}", log_level);
+
+        let evm_block = EvmBlock::new_with_fixture(
+            &app_config.eth.http_url,
+            std::path::Path::new("data/fixtures/test_evm_block_by_timestamp.json"),
+        )
+        .await?;
+        let mut cache = BlockTimeCache::default();
+        // 2025-07-01T00:00:00Z - 2025-07-01T23:59:59Z
+        let from_block = evm_block
+            .get_block_by_timestamp(1751328000, &mut cache)
+            .await?
+            .unwrap();
+        let to_block = evm_block
+            .get_last_block_by_timestamp(1751414399, &mut cache)
+            .await?;
+        info!("2025-07-01 block range: {} - {}", from_block, to_block);
+        assert!(evm_block.get_block_timestamp(from_block).await? >= 1751328000);
+        assert!(evm_block.get_block_timestamp(from_block - 1).await? < 1751328000);
+        assert!(evm_block.get_block_timestamp(to_block).await? <= 1751414399);
+        assert!(evm_block.get_block_timestamp(to_block + 1).await? > 1751414399);
+        info!("block time cache: {:?}", cache);
+        Ok(())
+    }
 }
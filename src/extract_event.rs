@@ -1,15 +1,21 @@
-use alloy::primitives::{Address, B256, Uint, keccak256};
+use crate::transform_event::{decimal_ratio, to_exact_amount};
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, B256, U256, Uint, keccak256};
 use alloy::providers::{DynProvider, Provider};
 use alloy::rpc::types::{Filter, Log};
 use alloy::sol;
 use alloy::sol_types::{SolEvent, SolValue};
+use bigdecimal::BigDecimal;
 use eyre::Result;
 use futures_util::StreamExt;
 use std::collections::HashMap;
 
+pub const PAIR_CREATED_EVENT_SIGNATURE: B256 = UniswapV2Factory::PairCreated::SIGNATURE_HASH;
 pub const MINT_EVENT_SIGNATURE: B256 = UniswapV2Pair::Mint::SIGNATURE_HASH;
 pub const BURN_EVENT_SIGNATURE: B256 = UniswapV2Pair::Burn::SIGNATURE_HASH;
 pub const SWAP_EVENT_SIGNATURE: B256 = UniswapV2Pair::Swap::SIGNATURE_HASH;
+pub const SYNC_EVENT_SIGNATURE: B256 = UniswapV2Pair::Sync::SIGNATURE_HASH;
+pub const TRANSFER_EVENT_SIGNATURE: B256 = UniswapV2Pair::Transfer::SIGNATURE_HASH;
 
 sol!(
     #[allow(missing_docs)]
@@ -91,18 +97,20 @@ pub struct UniswapV2MultiPair {
     pub provider: DynProvider,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct UniswapV2TokenPair {
     pub pair_address: Address,
     pub token0: TokenInfo,
     pub token1: TokenInfo,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TokenInfo {
     pub address: Address,
     pub decimals: u8,
+    #[serde(default)]
     pub symbol: String,
+    #[serde(default)]
     pub block_number: Option<u64>,
 }
 
@@ -153,6 +161,13 @@ impl UniswapV2 {
         Ok(sub.into_stream())
     }
 
+    /// Swap logs of every pair on the chain, including pairs created after subscribing.
+    pub async fn subscribe_swaps(&self) -> Result<impl StreamExt<Item = Log>> {
+        let filter = Filter::new().event_signature(SWAP_EVENT_SIGNATURE);
+        let sub = self.provider.subscribe_logs(&filter).await?;
+        Ok(sub.into_stream())
+    }
+
     pub async fn get_pair_liquidity(
         &self,
         pair_address: Address,
@@ -312,24 +327,68 @@ impl UniswapV2Tokens {
         })
     }
 
-    pub async fn get_price(&self) -> Result<(f64, f64, u32)> {
-        let reserves = self.pair_caller.getReserves().call().await?;
-        let reserve0 = reserves._reserve0.to::<u128>();
-        let reserve1 = reserves._reserve1.to::<u128>();
+    pub async fn get_price(&self) -> Result<(BigDecimal, BigDecimal, u32)> {
+        self.get_price_at(BlockId::latest()).await
+    }
+
+    pub async fn get_price_at(&self, block: BlockId) -> Result<(BigDecimal, BigDecimal, u32)> {
+        let reserves = self.pair_caller.getReserves().block(block).call().await?;
+        let reserve0 = U256::from(reserves._reserve0);
+        let reserve1 = U256::from(reserves._reserve1);
         let block_timestamp = reserves._blockTimestampLast;
 
-        if reserve0 == 0 || reserve1 == 0 {
+        if reserve0.is_zero() || reserve1.is_zero() {
             return Err(eyre::eyre!("Insufficient reserves"));
         }
 
-        let price0 = (reserve1 as f64 / 10f64.powi(self.token1_decimals as i32))
-            / (reserve0 as f64 / 10f64.powi(self.token0_decimals as i32));
-
-        let price1 = 1.0 / price0;
+        let reserve0 = to_exact_amount(reserve0, self.token0_decimals);
+        let reserve1 = to_exact_amount(reserve1, self.token1_decimals);
+        let price0 = decimal_ratio(&reserve1, &reserve0);
+        let price1 = decimal_ratio(&reserve0, &reserve1);
 
         Ok((price0, price1, block_timestamp))
     }
 
+    /// Sync logs plus LP token mints and burns (Transfer from or to the zero address), or
+    /// every LP Transfer with `all_transfers`.
+    pub async fn get_liquidity_event(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        all_transfers: bool,
+    ) -> Result<Vec<Log>> {
+        let filter = Filter::new()
+            .address(self.pair_address)
+            .from_block(from_block)
+            .to_block(to_block);
+        let sync_filter = filter.clone().event_signature(SYNC_EVENT_SIGNATURE);
+        if all_transfers {
+            let mut logs = self.provider.get_logs(&sync_filter).await?;
+            let transfer_filter = filter.event_signature(TRANSFER_EVENT_SIGNATURE);
+            logs.extend(self.provider.get_logs(&transfer_filter).await?);
+            logs.sort_by_key(|log| (log.block_number, log.log_index));
+            return Ok(logs);
+        }
+        let lp_mint_filter = filter
+            .clone()
+            .event_signature(TRANSFER_EVENT_SIGNATURE)
+            .topic1(B256::ZERO);
+        let lp_burn_filter = filter
+            .event_signature(TRANSFER_EVENT_SIGNATURE)
+            .topic2(B256::ZERO);
+
+        let mut logs = self.provider.get_logs(&sync_filter).await?;
+        logs.extend(self.provider.get_logs(&lp_mint_filter).await?);
+        // The locked MINIMUM_LIQUIDITY transfer goes from and to the zero address.
+        for log in self.provider.get_logs(&lp_burn_filter).await? {
+            if log.topics().get(1) != Some(&B256::ZERO) {
+                logs.push(log);
+            }
+        }
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+        Ok(logs)
+    }
+
     pub async fn subscribe_swap_event(&self) -> Result<impl StreamExt<Item = Log>> {
         let swap_event_signature =
             keccak256(b"Swap(address,uint256,uint256,uint256,uint256,address)");
@@ -394,6 +453,23 @@ impl UniswapV2Tokens {
             .map_err(|e| eyre::eyre!("get_mint_event error: {}", e))
     }
 
+    /// Logs of the pair with any of the event `signatures`, in chain order.
+    pub async fn get_events(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        signatures: &[B256],
+    ) -> Result<Vec<Log>> {
+        let filter = Filter::new()
+            .event_signature(signatures.to_vec())
+            .address(self.pair_address)
+            .from_block(from_block)
+            .to_block(to_block);
+        let mut logs = self.provider.get_logs(&filter).await?;
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+        Ok(logs)
+    }
+
     pub async fn get_all_event(
         &self,
         from_block: u64,
@@ -435,6 +511,26 @@ impl UniswapV2Tokens {
     }
 }
 
+impl UniswapV2TokenPair {
+    pub fn from_tokens(tokens: &UniswapV2Tokens) -> Self {
+        Self {
+            pair_address: tokens.pair_address,
+            token0: TokenInfo {
+                address: tokens.token0_address,
+                decimals: tokens.token0_decimals,
+                symbol: tokens.token0_symbol.clone(),
+                block_number: None,
+            },
+            token1: TokenInfo {
+                address: tokens.token1_address,
+                decimals: tokens.token1_decimals,
+                symbol: tokens.token1_symbol.clone(),
+                block_number: None,
+            },
+        }
+    }
+}
+
 impl UniswapV2MultiPair {
     pub async fn new(provider: DynProvider, pair_addresses: Vec<Address>) -> Result<Self> {
         let mut pairs = HashMap::new();
@@ -450,22 +546,7 @@ impl UniswapV2MultiPair {
         let results = futures::future::try_join_all(init_tasks).await?;
 
         for (pair_addr, tokens) in results {
-            let pair_token = UniswapV2TokenPair {
-                pair_address: pair_addr,
-                token0: TokenInfo {
-                    address: tokens.token0_address,
-                    decimals: tokens.token0_decimals,
-                    symbol: tokens.token0_symbol,
-                    block_number: None,
-                },
-                token1: TokenInfo {
-                    address: tokens.token1_address,
-                    decimals: tokens.token1_decimals,
-                    symbol: tokens.token1_symbol,
-                    block_number: None,
-                },
-            };
-            pairs.insert(pair_addr, pair_token);
+            pairs.insert(pair_addr, UniswapV2TokenPair::from_tokens(&tokens));
         }
 
         Ok(Self {
@@ -475,18 +556,41 @@ impl UniswapV2MultiPair {
         })
     }
 
-    pub async fn subscribe_all_events(&self) -> Result<impl StreamExt<Item = Log>> {
-        let mint_sig = MINT_EVENT_SIGNATURE;
-        let burn_sig = BURN_EVENT_SIGNATURE;
-        let swap_sig = SWAP_EVENT_SIGNATURE;
-
+    /// Logs with the given event `signatures`, e.g. those of a `TransformerRegistry`. With
+    /// `liquidity`, Sync and LP token Transfer logs are included as well.
+    pub async fn subscribe_all_events(
+        &self,
+        mut signatures: Vec<B256>,
+        liquidity: bool,
+    ) -> Result<impl StreamExt<Item = Log> + use<>> {
+        if liquidity {
+            signatures.extend([SYNC_EVENT_SIGNATURE, TRANSFER_EVENT_SIGNATURE]);
+        }
+        signatures.sort();
+        signatures.dedup();
         let filter = Filter::new()
-            .event_signature(vec![mint_sig.into(), burn_sig.into(), swap_sig.into()])
+            .event_signature(signatures)
             .address(self.pair_addresses.clone());
 
         let sub = self.provider.subscribe_logs(&filter).await?;
         Ok(sub.into_stream())
     }
+    /// Reserves and LP total supply at `block_number`, or at the latest block.
+    pub async fn get_liquidity_state(
+        &self,
+        pair_address: Address,
+        block_number: Option<u64>,
+    ) -> Result<(U256, U256, U256)> {
+        let pair_caller = UniswapV2Pair::new(pair_address, self.provider.clone());
+        let block = block_number.map_or(BlockId::latest(), BlockId::number);
+        let reserves = pair_caller.getReserves().block(block).call().await?;
+        let total_supply = pair_caller.totalSupply().block(block).call().await?;
+        Ok((
+            U256::from(reserves._reserve0),
+            U256::from(reserves._reserve1),
+            total_supply,
+        ))
+    }
 }
 
 #[cfg(test)]
@@ -503,7 +607,12 @@ mod tests {
         let log_level = app_config.init_log().unwrap();
         info!("app_config: {:#?}", app_config);
 
-        let evm_block = EvmBlock::new(&app_config.eth.http_url).await.unwrap();
+        let evm_block = EvmBlock::new_with_fixture(
+            &app_config.eth.http_url,
+            std::path::Path::new("data/fixtures/test_uniswap_v2.json"),
+        )
+        .await
+        .unwrap();
 
         let router_addr = Address::from_str("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D").unwrap();
         let uniswap_v2 = UniswapV2::new(evm_block.provider.clone(), router_addr).await;
@@ -550,20 +659,25 @@ mod tests {
         let log_level = app_config.init_log().unwrap();
         info!("app_config: {:#?}", app_config);
 
-        let evm_block = EvmBlock::new(&app_config.eth.ws_url).await?;
+        let evm_block = EvmBlock::new_with_fixture(
+            &app_config.eth.http_url,
+            std::path::Path::new("data/fixtures/test_uniswap_v2_tokens.json"),
+        )
+        .await?;
         let pair_address = Address::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc").unwrap();
         let uniswap_v2_tokens = UniswapV2Tokens::new(pair_address, evm_block.provider.clone())
             .await
             .unwrap();
         info!("uniswap_v2_tokens: {:#?}", uniswap_v2_tokens);
 
-        let from_block = 10008555;
-        let to_block = 10008566;
+        let from_block = 22921717;
+        let to_block = 22921721;
 
         let swap_even_log = uniswap_v2_tokens
             .get_swap_event(from_block, to_block)
             .await?;
         info!("get_swap_event: {:#?}", swap_even_log);
+        assert_eq!(swap_even_log.len(), 1);
 
         let burn_even_log = uniswap_v2_tokens
             .get_burn_event(from_block, to_block)
@@ -574,6 +688,7 @@ mod tests {
             .get_mint_event(from_block, to_block)
             .await?;
         info!("get_mint_event: {:#?}", mint_even_log);
+        assert!(burn_even_log.is_empty() && mint_even_log.is_empty());
 
         let all_event_log = uniswap_v2_tokens
             .get_all_event(from_block, to_block)
@@ -593,7 +708,11 @@ mod tests {
         let log_level = app_config.init_log().unwrap();
         info!("app_config: {:#?}", app_config);
 
-        let evm_block = EvmBlock::new(&app_config.eth.http_url).await?;
+        let evm_block = EvmBlock::new_with_fixture(
+            &app_config.eth.http_url,
+            std::path::Path::new("data/fixtures/test_uniswap_v2_multi_pair.json"),
+        )
+        .await?;
         let pair_addresses = app_config
             .uniswap_v2
             .pair_address
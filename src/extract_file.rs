@@ -0,0 +1,180 @@
+use crate::extract_event::UniswapV2TokenPair;
+use alloy::primitives::Address;
+use alloy::rpc::types::Log;
+use eyre::{Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Reads logs saved from `eth_getLogs`: a JSON array, a full JSON-RPC response
+/// (`{"result": [...]}`), or JSONL with one log (or one array of logs) per line.
+pub fn read_log_file(path: &Path) -> Result<Vec<Log>> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read log file {:?}", path))?;
+
+    let values = match serde_json::from_str::<Value>(&data) {
+        Ok(value) => vec![value],
+        Err(_) => data
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("Invalid JSON on line {} of {:?}", i + 1, path))
+            })
+            .collect::<Result<Vec<_>>>()?,
+    };
+
+    let mut logs = Vec::new();
+    for value in values {
+        let value = match value {
+            Value::Object(mut map) if map.contains_key("result") => map.remove("result").unwrap(),
+            value => value,
+        };
+        match value {
+            Value::Array(items) => {
+                for item in items {
+                    logs.push(parse_log(item, path)?);
+                }
+            }
+            item => logs.push(parse_log(item, path)?),
+        }
+    }
+    Ok(logs)
+}
+
+fn parse_log(value: Value, path: &Path) -> Result<Log> {
+    let log: Log =
+        serde_json::from_value(value).with_context(|| format!("Invalid log in {:?}", path))?;
+    // The transforms unwrap these; dumps from older nodes often lack blockTimestamp.
+    if log.block_number.is_none() || log.transaction_hash.is_none() {
+        return Err(eyre::eyre!(
+            "Log in {:?} is missing blockNumber or transactionHash",
+            path
+        ));
+    }
+    if log.block_timestamp.is_none() {
+        return Err(eyre::eyre!(
+            "Log in block {} of {:?} is missing blockTimestamp",
+            log.block_number.unwrap(),
+            path
+        ));
+    }
+    Ok(log)
+}
+
+/// Pair metadata is a JSON array of `UniswapV2TokenPair`, as written by `get_uniswapv2_event_csv`.
+pub fn read_pair_metadata(path: &Path) -> Result<HashMap<Address, UniswapV2TokenPair>> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read pair metadata {:?}", path))?;
+    let pairs: Vec<UniswapV2TokenPair> =
+        serde_json::from_str(&data).context("Failed to parse pair metadata")?;
+    Ok(pairs
+        .into_iter()
+        .map(|pair| (pair.pair_address, pair))
+        .collect())
+}
+
+pub fn write_pair_metadata(
+    path: &Path,
+    pairs: &HashMap<Address, UniswapV2TokenPair>,
+) -> Result<()> {
+    let mut pairs = pairs.values().collect::<Vec<_>>();
+    pairs.sort_by_key(|pair| pair.pair_address);
+    let data = serde_json::to_string_pretty(&pairs)?;
+    std::fs::write(path, data).context("Failed to write pair metadata")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract_event::SWAP_EVENT_SIGNATURE;
+    use crate::transform_event::transform_swap_event;
+    use alloy::primitives::{U256, address};
+    use log::info;
+
+    fn swap_log_json() -> Value {
+        serde_json::json!({
+            "address": "0xb4e16d0168e52d35cacd2c6185b44281ec28c9dc",
+            "topics": [
+                SWAP_EVENT_SIGNATURE.to_string(),
+                "0x0000000000000000000000007a250d5630b4cf539739df2c5dacb4c659f2488d",
+                "0x00000000000000000000000000000000000000000000000000000000000000aa"
+            ],
+            "data": format!(
+                "0x{:064x}{:064x}{:064x}{:064x}",
+                1_000_000_000u128, 0, 0, 400_000_000_000_000_000u128
+            ),
+            "blockNumber": "0x15c4b3a",
+            "blockTimestamp": "0x68632d80",
+            "transactionHash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "transactionIndex": "0x1",
+            "blockHash": "0x2222222222222222222222222222222222222222222222222222222222222222",
+            "logIndex": "0x3",
+            "removed": false
+        })
+    }
+
+    #[test]
+    fn test_read_log_file() -> Result<()> {
+        let dir = std::env::temp_dir().join("etl_evm_test_read_log_file");
+        std::fs::create_dir_all(&dir)?;
+
+        let rpc_file = dir.join("logs.json");
+        let rpc_response =
+            serde_json::json!({"jsonrpc": "2.0", "id": 1, "result": [swap_log_json()]});
+        std::fs::write(&rpc_file, rpc_response.to_string())?;
+        let jsonl_file = dir.join("logs.jsonl");
+        std::fs::write(
+            &jsonl_file,
+            format!("{}\n\n{}\n", swap_log_json(), swap_log_json()),
+        )?;
+
+        let logs = read_log_file(&rpc_file)?;
+        assert_eq!(logs.len(), 1);
+        assert_eq!(read_log_file(&jsonl_file)?.len(), 2);
+
+        let events = transform_swap_event(&logs, 1, 6, 18)?;
+        info!("events: {:#?}", events);
+        assert_eq!(events[0].block_number, 22825786);
+        assert_eq!(events[0].amount0_in, U256::from(1_000_000_000));
+        assert_eq!(events[0].token0_amounts, bigdecimal::BigDecimal::from(1000));
+        assert_eq!(
+            events[0].receiver_address,
+            address!("0x00000000000000000000000000000000000000aa")
+        );
+
+        let mut missing_timestamp = swap_log_json();
+        missing_timestamp
+            .as_object_mut()
+            .unwrap()
+            .remove("blockTimestamp");
+        std::fs::write(&rpc_file, Value::Array(vec![missing_timestamp]).to_string())?;
+        assert!(read_log_file(&rpc_file).is_err());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_pair_metadata() -> Result<()> {
+        let path = std::env::temp_dir().join("etl_evm_test_pair_metadata.json");
+        std::fs::write(
+            &path,
+            r#"[{
+                "pair_address": "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc",
+                "token0": {"address": "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", "decimals": 6, "symbol": "USDC"},
+                "token1": {"address": "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "decimals": 18}
+            }]"#,
+        )?;
+        let pairs = read_pair_metadata(&path)?;
+        let pair = &pairs[&address!("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")];
+        assert_eq!((pair.token0.decimals, pair.token1.decimals), (6, 18));
+        assert_eq!(pair.token0.symbol, "USDC");
+
+        write_pair_metadata(&path, &pairs)?;
+        assert_eq!(read_pair_metadata(&path)?.len(), 1);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
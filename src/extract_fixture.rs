@@ -0,0 +1,245 @@
+use alloy::rpc::json_rpc::{
+    ErrorPayload, RequestPacket, Response, ResponsePacket, ResponsePayload, SerializedRequest,
+};
+use alloy::transports::{BoxTransport, TransportError, TransportErrorKind, TransportFut};
+use eyre::{Context, Result};
+use log::debug;
+use serde_json::{Value, value::RawValue};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::task::{Context as TaskContext, Poll};
+use tower::Service;
+
+/// JSON-RPC request/response pairs keyed by `method` + serialized params.
+/// Each value is either `{"result": ...}` or `{"error": {...}}`.
+pub type RpcFixture = BTreeMap<String, Value>;
+
+pub fn load_fixture(path: &Path) -> Result<RpcFixture> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read RPC fixture {:?}", path))?;
+    serde_json::from_str(&data).context("Failed to parse RPC fixture")
+}
+
+pub fn save_fixture(path: &Path, fixture: &RpcFixture) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let data = serde_json::to_string_pretty(fixture)?;
+    std::fs::write(path, data).context("Failed to write RPC fixture")
+}
+
+fn fixture_key(req: &SerializedRequest) -> String {
+    let params = req.params().map_or("null", |params| params.get());
+    if req.method() != "eth_getLogs" {
+        return format!("{}:{}", req.method(), params);
+    }
+    // Filter addresses and topic alternatives are sets, serialized in no fixed order.
+    let mut params: Value = serde_json::from_str(params).unwrap_or(Value::Null);
+    let filters = params.as_array_mut().into_iter().flatten();
+    for (field, value) in filters.filter_map(Value::as_object_mut).flatten() {
+        let sets = match (field.as_str(), value) {
+            ("address", address) => vec![address],
+            ("topics", Value::Array(topics)) => topics.iter_mut().collect(),
+            _ => continue,
+        };
+        for values in sets.into_iter().filter_map(Value::as_array_mut) {
+            values.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+        }
+    }
+    format!("{}:{}", req.method(), params)
+}
+
+fn requests(packet: &RequestPacket) -> Vec<&SerializedRequest> {
+    match packet {
+        RequestPacket::Single(req) => vec![req],
+        RequestPacket::Batch(reqs) => reqs.iter().collect(),
+    }
+}
+
+/// Forwards requests to a live transport and writes every response into a fixture file.
+#[derive(Clone)]
+pub struct RecordingTransport {
+    inner: BoxTransport,
+    path: PathBuf,
+    fixture: Arc<Mutex<RpcFixture>>,
+}
+
+// Fixtures being recorded, by path, so transports recording into one file add to the same map.
+static RECORDINGS: LazyLock<Mutex<HashMap<PathBuf, Arc<Mutex<RpcFixture>>>>> =
+    LazyLock::new(Default::default);
+
+impl RecordingTransport {
+    pub fn new(inner: BoxTransport, path: &Path) -> Result<Self> {
+        let mut recordings = RECORDINGS.lock().unwrap();
+        let fixture = match recordings.get(path) {
+            Some(fixture) => fixture.clone(),
+            None => {
+                // Keep earlier recordings so several tests can share one fixture file.
+                let fixture = if path.exists() {
+                    load_fixture(path)?
+                } else {
+                    RpcFixture::new()
+                };
+                let fixture = Arc::new(Mutex::new(fixture));
+                recordings.insert(path.to_path_buf(), fixture.clone());
+                fixture
+            }
+        };
+        Ok(Self {
+            inner,
+            path: path.to_path_buf(),
+            fixture,
+        })
+    }
+
+    fn record(&self, keys: &HashMap<String, String>, packet: &ResponsePacket) -> Result<()> {
+        let responses = match packet {
+            ResponsePacket::Single(resp) => vec![resp],
+            ResponsePacket::Batch(resps) => resps.iter().collect(),
+        };
+        let mut fixture = self.fixture.lock().unwrap();
+        for resp in responses {
+            let Some(key) = keys.get(&resp.id.to_string()) else {
+                continue;
+            };
+            let entry = match &resp.payload {
+                ResponsePayload::Success(result) => {
+                    serde_json::json!({ "result": serde_json::from_str::<Value>(result.get())? })
+                }
+                ResponsePayload::Failure(error) => serde_json::json!({ "error": error }),
+            };
+            fixture.insert(key.clone(), entry);
+        }
+        save_fixture(&self.path, &fixture)
+    }
+}
+
+impl Service<RequestPacket> for RecordingTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: RequestPacket) -> Self::Future {
+        let keys = requests(&req)
+            .into_iter()
+            .map(|r| (r.id().to_string(), fixture_key(r)))
+            .collect::<HashMap<_, _>>();
+        let this = self.clone();
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let resp = fut.await?;
+            this.record(&keys, &resp)
+                .map_err(|e| TransportErrorKind::custom_str(&e.to_string()))?;
+            Ok(resp)
+        })
+    }
+}
+
+/// Serves responses from a fixture file recorded by `RecordingTransport`, without any network.
+#[derive(Clone)]
+pub struct ReplayTransport {
+    fixture: Arc<RpcFixture>,
+}
+
+impl ReplayTransport {
+    pub fn new(path: &Path) -> Result<Self> {
+        Ok(Self::from_fixture(load_fixture(path)?))
+    }
+
+    pub fn from_fixture(fixture: RpcFixture) -> Self {
+        Self {
+            fixture: Arc::new(fixture),
+        }
+    }
+
+    fn respond(&self, req: &SerializedRequest) -> Result<Response, TransportError> {
+        let key = fixture_key(req);
+        debug!("replay {}", key);
+        let entry = self
+            .fixture
+            .get(&key)
+            .ok_or_else(|| TransportErrorKind::custom_str(&format!("No fixture for {}", key)))?;
+        let payload = if let Some(error) = entry.get("error") {
+            let error: ErrorPayload = serde_json::from_value(error.clone())
+                .map_err(|e| TransportErrorKind::custom_str(&e.to_string()))?;
+            ResponsePayload::Failure(error)
+        } else {
+            let result = entry.get("result").cloned().unwrap_or(Value::Null);
+            let raw = RawValue::from_string(result.to_string())
+                .map_err(|e| TransportErrorKind::custom_str(&e.to_string()))?;
+            ResponsePayload::Success(raw)
+        };
+        Ok(Response {
+            id: req.id().clone(),
+            payload,
+        })
+    }
+}
+
+impl Service<RequestPacket> for ReplayTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: RequestPacket) -> Self::Future {
+        let resp = match &req {
+            RequestPacket::Single(r) => self.respond(r).map(ResponsePacket::Single),
+            RequestPacket::Batch(reqs) => reqs
+                .iter()
+                .map(|r| self.respond(r))
+                .collect::<Result<Vec<_>, _>>()
+                .map(ResponsePacket::Batch),
+        };
+        Box::pin(async move { resp })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract_block::EvmBlock;
+    use alloy::providers::Provider;
+    use alloy::transports::Transport;
+    use alloy::transports::mock::{Asserter, MockTransport};
+
+    #[tokio::test]
+    async fn test_record_and_replay() -> Result<()> {
+        let path = std::env::temp_dir().join("etl_evm_test_record_and_replay.json");
+        let _ = std::fs::remove_file(&path);
+
+        let asserter = Asserter::new();
+        asserter.push_success(&"0x1");
+        asserter.push_success(&"0x15c4b3a");
+        let recording = RecordingTransport::new(MockTransport::new(asserter).boxed(), &path)?;
+        // A second transport recording into the same file must not drop the first one's entries.
+        let other_asserter = Asserter::new();
+        other_asserter.push_success(&"0x1");
+        other_asserter.push_success(&"0x3b9aca00");
+        let other = RecordingTransport::new(MockTransport::new(other_asserter).boxed(), &path)?;
+        let evm_block = EvmBlock::from_transport(recording.boxed()).await?;
+        let block_number = evm_block.get_latest_block_number().await?;
+        let other_block = EvmBlock::from_transport(other.boxed()).await?;
+        other_block.provider.get_gas_price().await?;
+
+        let fixture = load_fixture(&path)?;
+        assert_eq!(fixture.len(), 3);
+
+        let evm_block = EvmBlock::new_replay(&path).await?;
+        assert_eq!(evm_block.chain_id, 1);
+        assert_eq!(evm_block.get_latest_block_number().await?, block_number);
+        assert_eq!(block_number, 22825786);
+        assert!(evm_block.get_block_timestamp(1).await.is_err());
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
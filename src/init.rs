@@ -1,9 +1,11 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
 use config::{Config, File};
 use eyre::{Context, Result};
 use log::LevelFilter;
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct AppConfig {
     pub eth: EthCfg,
     pub log: Option<LogCfg>,
@@ -11,44 +13,219 @@ pub struct AppConfig {
     pub uniswap_v2: UniV2Cfg,
     pub csv: CsvCfg,
     pub mq: MqCfg,
+    pub enrich: Option<EnrichCfg>,
+    pub output: Option<OutputCfg>,
+    pub pricing: Option<PricingCfg>,
+    pub candles: Option<CandleCfg>,
+    pub liquidity: Option<LiquidityCfg>,
+    pub fees: Option<FeeCfg>,
+    pub mev: Option<MevCfg>,
+    pub wallets: Option<WalletCfg>,
+    pub positions: Option<PositionCfg>,
+    pub filter: Option<FilterCfg>,
+    pub risk: Option<RiskCfg>,
+    pub chains: Option<BTreeMap<String, ChainCfg>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct EthCfg {
     pub http_url: String,
     pub ws_url: String,
+    /// Expected `eth_chainId`; the connection is rejected on mismatch.
+    pub chain_id: Option<u64>,
 }
 
-#[derive(Debug, Deserialize)]
+/// One `[chains.<name>]` section: endpoints and DEX contracts of a single chain.
+/// Block range fields fall back to `[uniswap_v2]`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChainCfg {
+    pub chain_id: u64,
+    pub http_url: String,
+    pub ws_url: String,
+    pub router_address: String,
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub pair_address: Option<Vec<String>>,
+    pub watch_router_address: Option<Vec<String>>,
+    pub pricing: Option<PricingCfg>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct LogCfg {
     pub level: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct TsdbCfg {
     pub query_url: String,
     pub write_url: String,
     pub auth_token: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct UniV2Cfg {
     pub router_address: String,
     pub from_block: u64,
     pub to_block: u64,
     pub pair_address: Option<Vec<String>>,
+    pub watch_router_address: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct CsvCfg {
     pub output_dir: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct MqCfg {
     pub broker_url: String,
 }
 
+/// Formatting of written values, shared by CSV and Influx output.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutputCfg {
+    /// Fractional digits of exact decimal columns (normalized amounts and prices).
+    #[serde(default = "default_decimal_scale")]
+    pub decimal_scale: i64,
+    /// `epoch_seconds`, `epoch_millis`, `rfc3339` or a strftime pattern for timestamp columns.
+    #[serde(default = "default_time_format")]
+    pub time_format: String,
+    /// `UTC`, `local` or a fixed offset such as `+08:00`.
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+}
+
+fn default_time_format() -> String {
+    crate::transform_event::DEFAULT_TIME_FORMAT.to_string()
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn default_decimal_scale() -> i64 {
+    18
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EnrichCfg {
+    /// Fetch each swap's transaction and decode the router call (sender, path, amountOutMin, deadline).
+    #[serde(default)]
+    pub router_intent: bool,
+    /// Add token symbols, decimals and a `SYMBOL0/SYMBOL1` pair name to Mint, Burn and Swap records.
+    #[serde(default)]
+    pub token_metadata: bool,
+    /// Add constant-product mid prices, price impact and slippage to Swap records.
+    #[serde(default)]
+    pub price_impact: bool,
+}
+
+/// Reference pairs (e.g. WETH/USDC) that anchor USD prices; every other token is
+/// priced through a pair with an already priced token.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PricingCfg {
+    pub reference_pairs: Vec<String>,
+    /// Tokens valued at exactly 1 USD.
+    pub stablecoins: Vec<String>,
+}
+
+/// OHLCV candles built from swaps. They always go to the command's own sink (CSV or
+/// TSDB); `tsdb` and `mq` additionally send them to `[tsdb]` / `[mq]`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CandleCfg {
+    pub intervals: Vec<String>,
+    /// How long after its end a window still accepts late or reorged swaps.
+    #[serde(default = "default_late_window_secs")]
+    pub late_window_secs: u64,
+    #[serde(default)]
+    pub tsdb: bool,
+    #[serde(default)]
+    pub mq: bool,
+}
+
+fn default_late_window_secs() -> u64 {
+    300
+}
+
+/// Per-pair reserves, LP supply and TVL, written on every change and, with
+/// `snapshot_interval` (e.g. `1h`), at every interval boundary.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LiquidityCfg {
+    pub snapshot_interval: Option<String>,
+}
+
+/// Daily swap fees and LP APR per pair; also turns on liquidity tracking for the TVL.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeeCfg {
+    #[serde(default = "default_fee_bps")]
+    pub default_fee_bps: u32,
+    /// Fee in basis points by factory address, for forks that charge a different fee.
+    #[serde(default)]
+    pub factory_fee_bps: HashMap<String, u32>,
+    #[serde(default = "default_apr_window_days")]
+    pub apr_window_days: usize,
+}
+
+/// Sandwich, cyclic arbitrage and JIT liquidity detection, written next to the swaps;
+/// `mq` additionally pushes the flags to `[mq]`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MevCfg {
+    #[serde(default)]
+    pub mq: bool,
+}
+
+/// Per-wallet daily volume and realized PnL from swaps, plus a top-traders report;
+/// also turns on router intent so router trades are attributed to the sending EOA.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalletCfg {
+    #[serde(default = "default_top_traders")]
+    pub top_traders: usize,
+}
+
+/// LP positions per provider with impermanent loss; also turns on liquidity tracking
+/// for the pool state. `follow_transfers` moves positions along LP transfers between holders.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PositionCfg {
+    #[serde(default)]
+    pub follow_transfers: bool,
+}
+
+/// Filter expressions by event type (`create`, `mint`, `burn`, `swap`, `pending` or a
+/// custom record type), e.g. `swap = 'amount_usd > 10000 && token0_symbol == "WETH"'`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FilterCfg {
+    #[serde(flatten)]
+    pub expressions: BTreeMap<String, String>,
+}
+
+/// Token risk of new pairs (fee-on-transfer, honeypot, owner blacklist and mint), scored
+/// before `subscribe_uniswapv2_create_mq` pushes them. A pair is pushed once a buy of its
+/// token was checked or `observe_blocks` blocks after its creation.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RiskCfg {
+    #[serde(default = "default_observe_blocks")]
+    pub observe_blocks: u64,
+    /// Tokens not analyzed, e.g. stablecoins; the router's WETH never is.
+    #[serde(default)]
+    pub trusted_tokens: Vec<String>,
+}
+
+fn default_observe_blocks() -> u64 {
+    20
+}
+
+fn default_fee_bps() -> u32 {
+    30
+}
+
+fn default_apr_window_days() -> usize {
+    7
+}
+
+fn default_top_traders() -> usize {
+    100
+}
+
 impl AppConfig {
     pub fn new() -> Result<Self> {
         let config_path = "data/etl.toml";
@@ -63,6 +240,78 @@ impl AppConfig {
 
         Ok(app_config)
     }
+    /// Expands the config into one config per selected chain.
+    /// No names keeps the top level `[eth]`/`[uniswap_v2]`; `all` selects every `[chains.*]`.
+    pub fn select_chains(self, names: &[String]) -> Result<Vec<Self>> {
+        if names.is_empty() {
+            return Ok(vec![self]);
+        }
+        let chains = self
+            .chains
+            .clone()
+            .ok_or_else(|| eyre::eyre!("No [chains] section in config"))?;
+        let selected: Vec<(&String, &ChainCfg)> = if names.iter().any(|n| n == "all") {
+            chains.iter().collect()
+        } else {
+            names
+                .iter()
+                .map(|name| {
+                    chains
+                        .get_key_value(name)
+                        .ok_or_else(|| eyre::eyre!("Chain '{}' not found in [chains]", name))
+                })
+                .collect::<Result<_>>()?
+        };
+
+        Ok(selected
+            .into_iter()
+            .map(|(_, chain)| {
+                let mut config = self.clone();
+                config.eth = EthCfg {
+                    http_url: chain.http_url.clone(),
+                    ws_url: chain.ws_url.clone(),
+                    chain_id: Some(chain.chain_id),
+                };
+                config.uniswap_v2 = UniV2Cfg {
+                    router_address: chain.router_address.clone(),
+                    from_block: chain.from_block.unwrap_or(self.uniswap_v2.from_block),
+                    to_block: chain.to_block.unwrap_or(self.uniswap_v2.to_block),
+                    pair_address: chain.pair_address.clone(),
+                    watch_router_address: chain.watch_router_address.clone(),
+                };
+                // Token addresses differ per chain, so top-level pricing is never inherited.
+                config.pricing = chain.pricing.clone();
+                config
+            })
+            .collect())
+    }
+
+    pub fn token_metadata_enabled(&self) -> bool {
+        self.enrich.as_ref().is_some_and(|e| e.token_metadata)
+    }
+
+    pub fn price_impact_enabled(&self) -> bool {
+        self.enrich.as_ref().is_some_and(|e| e.price_impact)
+    }
+
+    pub fn router_intent_enabled(&self) -> bool {
+        self.enrich.as_ref().is_some_and(|e| e.router_intent) || self.wallets.is_some()
+    }
+
+    /// Adds `--filter EVENT=EXPR` arguments, replacing the `[filter]` entry of the same event.
+    pub fn add_filters(&mut self, filters: &[String]) -> Result<()> {
+        for filter in filters {
+            let (event_type, expression) = filter
+                .split_once('=')
+                .ok_or_else(|| eyre::eyre!("Expected EVENT=EXPR, got '{}'", filter))?;
+            self.filter
+                .get_or_insert_with(FilterCfg::default)
+                .expressions
+                .insert(event_type.trim().to_string(), expression.trim().to_string());
+        }
+        Ok(())
+    }
+
     pub fn init_log(&self) -> Result<LevelFilter> {
         let log_level = match &self.log {
             Some(log_cfg) => match log_cfg.level.to_lowercase().as_str() {
@@ -75,7 +324,10 @@ impl AppConfig {
             },
             None => LevelFilter::Info,
         };
-        env_logger::Builder::new().filter_level(log_level).init();
+        // Tests call this once each within one process; only the first logger sticks.
+        let _ = env_logger::Builder::new()
+            .filter_level(log_level)
+            .try_init();
         Ok(log_level)
     }
     pub fn from_file(file_path: &str) -> Result<Self> {
@@ -87,17 +339,19 @@ impl AppConfig {
             .context("Failed to read config file")
     }
 
-    pub fn from_get_cli(args: &crate::Univ2EventArgs) -> Result<Self> {
+    pub fn from_get_cli(args: &crate::cli::Univ2EventArgs) -> Result<Self> {
         Ok(Self {
             eth: EthCfg {
                 http_url: args.http_url.clone().unwrap_or_default(),
                 ws_url: "".to_string(),
+                chain_id: None,
             },
             uniswap_v2: UniV2Cfg {
                 router_address: args.router_address.clone().unwrap_or_default(),
                 from_block: args.from_block.unwrap_or(0),
                 to_block: args.to_block.unwrap_or(0),
                 pair_address: None,
+                watch_router_address: None,
             },
             csv: CsvCfg {
                 output_dir: args.output_dir.clone().unwrap_or_else(|| "./".into()),
@@ -111,20 +365,38 @@ impl AppConfig {
             mq: MqCfg {
                 broker_url: String::new(),
             },
+            enrich: Some(EnrichCfg {
+                router_intent: args.enrich_intent,
+                token_metadata: args.enrich_tokens,
+                price_impact: args.enrich_impact,
+            }),
+            output: None,
+            pricing: None,
+            candles: None,
+            liquidity: None,
+            fees: None,
+            mev: None,
+            wallets: None,
+            positions: None,
+            filter: None,
+            risk: None,
+            chains: None,
         })
     }
 
-    pub fn from_subscribe_cli(args: &crate::SubscribeUniv2EventArgs) -> Result<Self> {
+    pub fn from_subscribe_cli(args: &crate::cli::SubscribeUniv2EventArgs) -> Result<Self> {
         Ok(Self {
             eth: EthCfg {
                 ws_url: args.ws_url.clone().unwrap(),
                 http_url: String::new(),
+                chain_id: None,
             },
             uniswap_v2: UniV2Cfg {
                 pair_address: Some(args.pair_address.clone()),
                 router_address: String::new(),
                 from_block: 0,
                 to_block: 0,
+                watch_router_address: None,
             },
             csv: CsvCfg {
                 output_dir: args.output_dir.clone().unwrap_or_else(|| "./".to_string()),
@@ -138,19 +410,37 @@ impl AppConfig {
             mq: MqCfg {
                 broker_url: String::new(),
             },
+            enrich: Some(EnrichCfg {
+                router_intent: args.enrich_intent,
+                token_metadata: args.enrich_tokens,
+                price_impact: args.enrich_impact,
+            }),
+            output: None,
+            pricing: None,
+            candles: None,
+            liquidity: None,
+            fees: None,
+            mev: None,
+            wallets: None,
+            positions: None,
+            filter: None,
+            risk: None,
+            chains: None,
         })
     }
-    pub fn from_subscribe_db_cli(args: &crate::SubscribeUniv2EventDbArgs) -> Result<Self> {
+    pub fn from_subscribe_db_cli(args: &crate::cli::SubscribeUniv2EventDbArgs) -> Result<Self> {
         Ok(Self {
             eth: EthCfg {
                 ws_url: args.ws_url.clone().unwrap(),
                 http_url: String::new(),
+                chain_id: None,
             },
             uniswap_v2: UniV2Cfg {
                 pair_address: Some(args.pair_address.clone()),
                 router_address: String::new(),
                 from_block: 0,
                 to_block: 0,
+                watch_router_address: None,
             },
             csv: CsvCfg {
                 output_dir: String::new(),
@@ -164,20 +454,79 @@ impl AppConfig {
             mq: MqCfg {
                 broker_url: String::new(),
             },
+            enrich: Some(EnrichCfg {
+                router_intent: args.enrich_intent,
+                token_metadata: args.enrich_tokens,
+                price_impact: args.enrich_impact,
+            }),
+            output: None,
+            pricing: None,
+            candles: None,
+            liquidity: None,
+            fees: None,
+            mev: None,
+            wallets: None,
+            positions: None,
+            filter: None,
+            risk: None,
+            chains: None,
+        })
+    }
+
+    pub fn from_subscribe_pending_cli(args: &crate::cli::SubscribeUniv2PendingArgs) -> Result<Self> {
+        Ok(Self {
+            eth: EthCfg {
+                ws_url: args.ws_url.clone().unwrap(),
+                http_url: String::new(),
+                chain_id: None,
+            },
+            uniswap_v2: UniV2Cfg {
+                router_address: args.router_address[0].clone(),
+                pair_address: None,
+                from_block: 0,
+                to_block: 0,
+                watch_router_address: Some(args.router_address.clone()),
+            },
+            csv: CsvCfg {
+                output_dir: args.output_dir.clone().unwrap_or_else(|| "./".to_string()),
+            },
+            log: None,
+            tsdb: TsdbCfg {
+                query_url: String::new(),
+                write_url: String::new(),
+                auth_token: String::new(),
+            },
+            mq: MqCfg {
+                broker_url: args.broker_url.clone().unwrap_or_default(),
+            },
+            enrich: None,
+            output: None,
+            pricing: None,
+            candles: None,
+            liquidity: None,
+            fees: None,
+            mev: None,
+            wallets: None,
+            positions: None,
+            filter: None,
+            risk: None,
+            chains: None,
         })
     }
 
-    pub fn from_subscribe_mq_cli(args: &crate::SubscribeUniv2EventMqArgs) -> Result<Self> {
+    pub fn from_subscribe_mq_cli(args: &crate::cli::SubscribeUniv2EventMqArgs) -> Result<Self> {
         Ok(Self {
             eth: EthCfg {
                 ws_url: args.ws_url.clone().unwrap(),
                 http_url: String::new(),
+                chain_id: None,
             },
             uniswap_v2: UniV2Cfg {
                 router_address: args.router_address.clone().unwrap(),
                 pair_address: None,
                 from_block: 0,
                 to_block: 0,
+                watch_router_address: None,
             },
             csv: CsvCfg {
                 output_dir: String::new(),
@@ -191,15 +540,102 @@ impl AppConfig {
             mq: MqCfg {
                 broker_url: args.broker_url.clone().unwrap(),
             },
+            enrich: None,
+            output: None,
+            pricing: None,
+            candles: None,
+            liquidity: None,
+            fees: None,
+            mev: None,
+            wallets: None,
+            positions: None,
+            filter: None,
+            risk: args.risk_blocks.map(|observe_blocks| RiskCfg {
+                observe_blocks,
+                trusted_tokens: Vec::new(),
+            }),
+            chains: None,
         })
     }
 }
 
+/// Parses `--from-time`/`--to-time` values into unix seconds (UTC).
+/// Accepts unix seconds, RFC3339, `%Y-%m-%d %H:%M:%S` and `%Y-%m-%d`; a bare date
+/// maps to the start of the day, or to its last second when `end_of_day` is set.
+pub fn parse_time_arg(value: &str, end_of_day: bool) -> Result<u64> {
+    let value = value.trim();
+    if let Ok(timestamp) = value.parse::<u64>() {
+        return Ok(timestamp);
+    }
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return u64::try_from(date_time.timestamp()).context("Time is before 1970");
+    }
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        return u64::try_from(date_time.and_utc().timestamp()).context("Time is before 1970");
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .with_context(|| format!("Invalid time '{}', expected RFC3339 or YYYY-MM-DD", value))?;
+    let time = if end_of_day {
+        NaiveTime::from_hms_opt(23, 59, 59).unwrap()
+    } else {
+        NaiveTime::MIN
+    };
+    u64::try_from(date.and_time(time).and_utc().timestamp()).context("Time is before 1970")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use log::info;
 
+    #[test]
+    fn test_select_chains() {
+        let app_config: AppConfig = Config::builder()
+            .add_source(File::with_name("data/etl_example.toml"))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        let default_chain = app_config.clone().select_chains(&[]).unwrap();
+        assert_eq!(default_chain.len(), 1);
+        assert_eq!(default_chain[0].eth.chain_id, None);
+
+        let base = app_config
+            .clone()
+            .select_chains(&["base".to_string()])
+            .unwrap();
+        assert_eq!(base[0].eth.chain_id, Some(8453));
+        assert_eq!(
+            base[0].uniswap_v2.router_address,
+            "0x4752ba5DBc23f44D87826276BF6Fd6b1C372aD24"
+        );
+
+        let all = app_config
+            .clone()
+            .select_chains(&["all".to_string()])
+            .unwrap();
+        info!("all chains: {:#?}", all);
+        assert_eq!(all.len(), app_config.chains.as_ref().unwrap().len());
+        assert!(app_config.select_chains(&["unknown".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_parse_time_arg() {
+        assert_eq!(parse_time_arg("2025-07-01", false).unwrap(), 1751328000);
+        assert_eq!(parse_time_arg("2025-07-01", true).unwrap(), 1751414399);
+        assert_eq!(
+            parse_time_arg("2025-07-01T08:00:00+08:00", false).unwrap(),
+            1751328000
+        );
+        assert_eq!(
+            parse_time_arg("2025-07-01 00:00:10", false).unwrap(),
+            1751328010
+        );
+        assert_eq!(parse_time_arg("1751328000", true).unwrap(), 1751328000);
+        assert!(parse_time_arg("07/01/2025", false).is_err());
+    }
+
     #[test]
     fn test_app_config() {
         let app_config = AppConfig::new().unwrap();
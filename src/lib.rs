@@ -0,0 +1,27 @@
+pub mod cli;
+pub mod extract_block;
+pub mod extract_event;
+pub mod extract_file;
+pub mod extract_fixture;
+pub mod init;
+pub mod load_block;
+pub mod load_event;
+pub mod pipeline;
+#[cfg(test)]
+mod test_util;
+pub mod to_mq;
+pub mod transform_block;
+pub mod transform_candle;
+pub mod transform_event;
+pub mod transform_fee;
+pub mod transform_filter;
+pub mod transform_impact;
+pub mod transform_liquidity;
+pub mod transform_mev;
+pub mod transform_position;
+pub mod transform_price;
+pub mod transform_registry;
+pub mod transform_risk;
+pub mod transform_route;
+pub mod transform_tx;
+pub mod transform_wallet;
@@ -1,4 +1,5 @@
 use crate::transform_block::BlockTemp;
+use crate::transform_event::serialize_timestamp;
 use csv::Writer;
 use eyre::{Context, Result};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
@@ -11,7 +12,8 @@ pub struct BlockTable {
     pub block_number: u64,
     pub tx_count: usize,
     pub miner: String,
-    pub date_time: String,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub date_time: u64,
 }
 
 pub struct BlockTableFile {
@@ -83,8 +85,6 @@ impl BlockTableTsdb {
 mod tests {
     use super::*;
     use crate::{extract_block::EvmBlock, init::AppConfig, transform_block::transform_block};
-    use chrono::{DateTime, Local, Utc};
-    
     use log::info;
 
     #[test]
@@ -94,7 +94,7 @@ mod tests {
             block_number: 1,
             tx_count: 1,
             miner: "0x1234567890123456789012345678901234567890".to_string(),
-            date_time: "2023-01-01 00:00:00".to_string(),
+            date_time: 1672531200,
         };
         csv_file.write_block(&block).unwrap();
     }
@@ -105,7 +105,12 @@ mod tests {
         let log_level = app_config.init_log().unwrap();
         info!("app_config: {:#?}", app_config);
 
-        let rpc_client = EvmBlock::new(&app_config.eth.http_url).await.unwrap();
+        let rpc_client = EvmBlock::new_with_fixture(
+            &app_config.eth.http_url,
+            std::path::Path::new("data/fixtures/test_block_table_file.json"),
+        )
+        .await
+        .unwrap();
         let new_block_number = rpc_client.get_latest_block_number().await.unwrap();
         info!("get_latest_block_number: {:?}", new_block_number);
 
@@ -120,31 +125,32 @@ mod tests {
             let transformed_block = transform_block(block).unwrap();
             info!("transformed_block: {:#?}", transformed_block);
 
-            let date_time =
-                DateTime::<Utc>::from_timestamp(transformed_block.timestamp as i64, 0).unwrap();
-            let local_date_time = date_time.with_timezone(&Local);
-
             let block = BlockTable {
                 block_number: transformed_block.block_number,
                 tx_count: transformed_block.transactions_len,
                 miner: transformed_block.miner,
-                date_time: local_date_time.to_string(),
+                date_time: transformed_block.timestamp,
             };
             csv_file.write_block(&block).unwrap();
         }
     }
 
     #[tokio::test]
+    #[ignore = "writes to a running TSDB"]
     async fn test_block_table_tsdb() {
         let app_config = AppConfig::new().unwrap();
         let log_level = app_config.init_log().unwrap();
         info!("app_config: {:#?}", app_config);
 
-        let rpc_client = EvmBlock::new(&app_config.eth.http_url).await.unwrap();
+        let rpc_client = EvmBlock::new_with_fixture(
+            &app_config.eth.http_url,
+            std::path::Path::new("data/fixtures/test_block_table_tsdb.json"),
+        )
+        .await
+        .unwrap();
         let new_block_number = rpc_client.get_latest_block_number().await.unwrap();
         info!("get_latest_block_number: {:?}", new_block_number);
 
-
         let auth_token = "apiv3_di3lJBckgHFT2cJc5VLkKwsWsVEwI3XZsefjifwwLNR8kruGfhazhZ3tGBvIPZIquaFlbnqHJgTDdaLUFgIzrw";
         let write_url = "http://tsdb:8181/api/v3/write_lp?db=evm";
         let tsdb_client = BlockTableTsdb::new(auth_token);
@@ -156,8 +162,11 @@ mod tests {
         if let Some(block) = new_block_data.as_ref() {
             info!("get_block_data Block.Header: {:#?}", block.header);
             let transformed_block = transform_block(block).unwrap();
-            info!("transformed_block: {:#?}", transformed_block);   
-            let response = tsdb_client.write_block(write_url, &transformed_block).await.unwrap();
+            info!("transformed_block: {:#?}", transformed_block);
+            let response = tsdb_client
+                .write_block(write_url, &transformed_block)
+                .await
+                .unwrap();
             info!("write_block: {:?}", response);
         }
     }
@@ -1,16 +1,84 @@
+use crate::to_mq::Mq;
+use crate::transform_candle::CandleEvent;
 use crate::transform_event::{BurnEvent, MintEvent, PairCreatedEvent, SwapEvent};
-use csv::Writer;
+use crate::transform_fee::FeeEvent;
+use crate::transform_liquidity::LiquidityEvent;
+use crate::transform_mev::MevEvent;
+use crate::transform_position::PositionEvent;
+use crate::transform_registry::CustomRecord;
+use crate::transform_route::RouteEvent;
+use crate::transform_tx::RouterCallEvent;
+use crate::transform_wallet::WalletEvent;
+use csv::{ReaderBuilder, StringRecord, Writer, WriterBuilder};
 use eyre::{Context, Result};
+use log::{info, warn};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use reqwest::Client;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 pub struct PairsTableTsdb {
     pub influx_client: Client,
 }
 
 pub struct PairsTableFile {
+    filename: String,
     csv_writer: Writer<File>,
+    // Written once, at the top of a new file.
+    headers: Option<StringRecord>,
+    // Keys of every row in the file, see `RowKey`, also kept in `<file>.keys`.
+    written: HashSet<String>,
+    keys_writer: File,
+    // Keys of rows not flushed yet; they go to `<file>.keys` after the rows themselves.
+    pending_keys: Vec<String>,
+}
+
+/// Identity of an output row. A row whose key is already in the file is not written again,
+/// so keys are built from typed values and don't change with `[output]` formatting.
+pub trait RowKey {
+    fn row_key(&self) -> String;
+}
+
+/// Writes candles to every configured destination.
+pub struct CandleSink {
+    pub csv: Option<PairsTableFile>,
+    pub tsdb: Option<(PairsTableTsdb, String)>,
+    pub mq: Option<(Mq, String)>,
+}
+
+/// Writes MEV flags to every configured destination.
+pub struct MevSink {
+    pub csv: Option<PairsTableFile>,
+    pub tsdb: Option<(PairsTableTsdb, String)>,
+    pub mq: Option<(Mq, String)>,
+}
+
+/// Records of a live subscription, written as a CSV row or an Influx line.
+pub trait SinkRecord: serde::Serialize + RowKey {
+    fn to_influx_line(&self) -> String;
+}
+
+/// Where a live subscription writes its records: `CsvEventSink` or `TsdbEventSink`.
+// Subscriptions run on the caller's task, so the futures don't need to be `Send`.
+#[allow(async_fn_in_trait)]
+pub trait EventSink {
+    /// Writes `events` of one `kind`, e.g. `swap` or `liquidity`.
+    async fn write<E: SinkRecord>(&mut self, kind: &str, events: &[E]) -> Result<()>;
+    async fn write_custom(&mut self, record: &dyn CustomRecord) -> Result<()>;
+}
+
+/// One CSV file per kind of record, `file_name(kind)`, opened on its first write.
+pub struct CsvEventSink {
+    file_name: Box<dyn Fn(&str) -> PathBuf>,
+    files: HashMap<String, PairsTableFile>,
+}
+
+/// Every kind of record as Influx lines to one TSDB.
+pub struct TsdbEventSink {
+    tsdb: PairsTableTsdb,
+    write_url: String,
 }
 
 impl PairsTableTsdb {
@@ -59,56 +127,514 @@ impl PairsTableTsdb {
 }
 
 impl PairsTableFile {
+    /// Opens `filename` for appending. The keys of the rows already in the file are loaded
+    /// from `<file>.keys`, so a record written again, e.g. after a restart over an
+    /// overlapping range, is skipped. A file without that index, or one whose columns turn
+    /// out to differ from the rows written now, is moved aside first, see `rotate_file`.
     pub fn new(filename: &str) -> Result<Self> {
-        //let file = File::create(filename).context("Failed to create file")?;
-        let file = File::options()
-            .create(true)
-            .append(true)
-            .open(filename)
-            .context("Failed to open file")?;
+        let keys_filename = keys_filename(filename);
+        let mut headers = match ReaderBuilder::new().has_headers(false).from_path(filename) {
+            Ok(mut reader) => reader
+                .records()
+                .next()
+                .transpose()
+                .context("Failed to read existing file")?,
+            Err(_) => None,
+        };
+        if headers.is_some() && !Path::new(&keys_filename).exists() {
+            rotate_file(filename)?;
+            headers = None;
+        }
+        let written = match std::fs::read_to_string(&keys_filename) {
+            Ok(keys) => keys.lines().map(str::to_string).collect(),
+            Err(_) => HashSet::new(),
+        };
+        let (csv_writer, keys_writer) = open_file(filename)?;
+
+        Ok(Self {
+            filename: filename.to_string(),
+            csv_writer,
+            headers,
+            written,
+            keys_writer,
+            pending_keys: Vec::new(),
+        })
+    }
 
-        let mut writer = Writer::from_writer(file);
+    fn write_row<T: serde::Serialize + RowKey>(&mut self, event: &T) -> Result<()> {
+        let (headers, record) = csv_row(event)?;
+        self.write_record(headers, record, event.row_key())
+    }
 
-        Ok(Self { csv_writer: writer })
+    fn write_record(
+        &mut self,
+        headers: StringRecord,
+        record: StringRecord,
+        key: String,
+    ) -> Result<()> {
+        if self.headers.as_ref() != Some(&headers) {
+            if self.headers.is_some() {
+                self.flush()?;
+                rotate_file(&self.filename)?;
+                (self.csv_writer, self.keys_writer) = open_file(&self.filename)?;
+                self.written.clear();
+            }
+            self.csv_writer.write_record(&headers)?;
+            self.headers = Some(headers);
+        }
+        if self.written.insert(key.clone()) {
+            self.csv_writer
+                .write_record(&record)
+                .context("Failed to write event data")?;
+            self.pending_keys.push(key);
+        }
+        Ok(())
+    }
+
+    // Rows first: after a crash in between, a row is written twice rather than lost.
+    fn flush(&mut self) -> Result<()> {
+        self.csv_writer.flush()?;
+        for key in self.pending_keys.drain(..) {
+            writeln!(self.keys_writer, "{}", key)?;
+        }
+        Ok(())
     }
 
     pub fn write_pair_created_event(&mut self, events: &[PairCreatedEvent]) -> Result<()> {
         for event in events {
-            self.csv_writer
-                .serialize(event)
-                .context("Failed to write event data")?;
+            self.write_row(event)?;
         }
-        self.csv_writer.flush();
+        self.flush()?;
         Ok(())
     }
     pub fn write_mint_event(&mut self, events: &[MintEvent]) -> Result<()> {
         for event in events {
-            self.csv_writer
-                .serialize(event)
-                .context("Failed to write event data")?;
+            self.write_row(event)?;
         }
-        self.csv_writer.flush();
+        self.flush()?;
         Ok(())
     }
     pub fn write_burn_event(&mut self, events: &[BurnEvent]) -> Result<()> {
         for event in events {
-            self.csv_writer
-                .serialize(event)
-                .context("Failed to write event data")?;
+            self.write_row(event)?;
         }
-        self.csv_writer.flush();
+        self.flush()?;
         Ok(())
     }
     pub fn write_swap_event(&mut self, events: &[SwapEvent]) -> Result<()> {
         for event in events {
-            self.csv_writer
-                .serialize(event)
-                .context("Failed to write event data")?;
-        self.csv_writer.flush();
+            self.write_row(event)?;
+        self.flush()?;
+        }
+        Ok(())
+    }
+    pub fn write_route_event(&mut self, events: &[RouteEvent]) -> Result<()> {
+        for event in events {
+            self.write_row(event)?;
+        }
+        self.flush()?;
+        Ok(())
+    }
+    pub fn write_router_call_event(&mut self, events: &[RouterCallEvent]) -> Result<()> {
+        for event in events {
+            self.write_row(event)?;
+        }
+        self.flush()?;
+        Ok(())
+    }
+    pub fn write_candle_event(&mut self, events: &[CandleEvent]) -> Result<()> {
+        for event in events {
+            self.write_row(event)?;
+        }
+        self.flush()?;
+        Ok(())
+    }
+
+    pub fn write_fee_event(&mut self, events: &[FeeEvent]) -> Result<()> {
+        for event in events {
+            self.write_row(event)?;
+        }
+        self.flush()?;
+        Ok(())
+    }
+
+    pub fn write_wallet_event(&mut self, events: &[WalletEvent]) -> Result<()> {
+        for event in events {
+            self.write_row(event)?;
+        }
+        self.flush()?;
+        Ok(())
+    }
+
+    pub fn write_position_event(&mut self, events: &[PositionEvent]) -> Result<()> {
+        for event in events {
+            self.write_row(event)?;
         }
+        self.flush()?;
         Ok(())
     }
 
+    pub fn write_mev_event(&mut self, events: &[MevEvent]) -> Result<()> {
+        for event in events {
+            self.write_row(event)?;
+        }
+        self.flush()?;
+        Ok(())
+    }
+
+    pub fn write_liquidity_event(&mut self, events: &[LiquidityEvent]) -> Result<()> {
+        for event in events {
+            self.write_row(event)?;
+        }
+        self.flush()?;
+        Ok(())
+    }
+
+    pub fn write_events<T: serde::Serialize + RowKey>(&mut self, events: &[T]) -> Result<()> {
+        for event in events {
+            self.write_row(event)?;
+        }
+        self.flush()?;
+        Ok(())
+    }
+
+    pub fn write_custom_record(&mut self, record: &dyn CustomRecord) -> Result<()> {
+        let (headers, row) = record.to_csv_row()?;
+        self.write_record(headers, row, record.row_key()?)?;
+        self.flush()?;
+        Ok(())
+    }
+}
+
+/// Header and values of the CSV row of `event`.
+pub fn csv_row<T: serde::Serialize>(event: &T) -> Result<(StringRecord, StringRecord)> {
+    let mut buffer = Writer::from_writer(Vec::new());
+    buffer
+        .serialize(event)
+        .context("Failed to write event data")?;
+    let buffer = buffer
+        .into_inner()
+        .map_err(|e| eyre::eyre!("Failed to write event data: {}", e))?;
+    let mut reader = ReaderBuilder::new().from_reader(buffer.as_slice());
+    let headers = reader.headers()?.clone();
+    let record = reader.records().next().transpose()?.unwrap_or_default();
+    Ok((headers, record))
+}
+
+fn keys_filename(filename: &str) -> String {
+    format!("{}.keys", filename)
+}
+
+fn open_file(filename: &str) -> Result<(Writer<File>, File)> {
+    let open = |filename: &str| {
+        File::options()
+            .create(true)
+            .append(true)
+            .open(filename)
+            .context("Failed to open file")
+    };
+    let csv_writer = WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(open(filename)?);
+    Ok((csv_writer, open(&keys_filename(filename))?))
+}
+
+/// Moves `filename` and its key index aside as `<name>.<yymmddHHMMSS>.<ext>`, for files
+/// whose rows can't be appended to: other columns, e.g. from an older version, or no index.
+fn rotate_file(filename: &str) -> Result<()> {
+    let path = Path::new(filename);
+    let suffix = chrono::Local::now().format("%y%m%d%H%M%S");
+    let rotated = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) => path.with_file_name(format!(
+            "{}.{}.{}",
+            stem.to_string_lossy(),
+            suffix,
+            extension.to_string_lossy()
+        )),
+        _ => path.with_extension(suffix.to_string()),
+    };
+    std::fs::rename(path, &rotated).context("Failed to rotate file")?;
+    if Path::new(&keys_filename(filename)).exists() {
+        std::fs::rename(
+            keys_filename(filename),
+            keys_filename(&rotated.to_string_lossy()),
+        )?;
+    }
+    warn!(
+        "Moved {} aside to {:?}: its columns differ or it has no key index",
+        filename, rotated
+    );
+    Ok(())
+}
+
+// Rows read from the chain are identified by (chain, tx_hash, log_index), derived rows by
+// what they describe. Timestamps are the raw seconds, whatever `time_format` is.
+impl RowKey for PairCreatedEvent {
+    fn row_key(&self) -> String {
+        format!(
+            "{}|{}|{}",
+            self.chain_id, self.transaction_hash, self.log_index
+        )
+    }
+}
+
+impl RowKey for MintEvent {
+    fn row_key(&self) -> String {
+        format!(
+            "{}|{}|{}",
+            self.chain_id, self.transaction_hash, self.log_index
+        )
+    }
+}
+
+impl RowKey for BurnEvent {
+    fn row_key(&self) -> String {
+        format!(
+            "{}|{}|{}",
+            self.chain_id, self.transaction_hash, self.log_index
+        )
+    }
+}
+
+impl RowKey for SwapEvent {
+    fn row_key(&self) -> String {
+        format!(
+            "{}|{}|{}",
+            self.chain_id, self.transaction_hash, self.log_index
+        )
+    }
+}
+
+impl RowKey for RouteEvent {
+    fn row_key(&self) -> String {
+        format!("{}|{}|{}", self.chain_id, self.transaction_hash, self.pairs)
+    }
+}
+
+impl RowKey for RouterCallEvent {
+    fn row_key(&self) -> String {
+        format!("{}|{}", self.chain_id, self.transaction_hash)
+    }
+}
+
+// Each revision of a window is a row of its own; consumers keep the highest.
+impl RowKey for CandleEvent {
+    fn row_key(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}",
+            self.chain_id, self.pair_address, self.interval, self.open_time, self.revision
+        )
+    }
+}
+
+impl RowKey for FeeEvent {
+    fn row_key(&self) -> String {
+        format!(
+            "{}|{}|{}|{:?}",
+            self.chain_id, self.pair_address, self.day, self.rank
+        )
+    }
+}
+
+impl RowKey for WalletEvent {
+    fn row_key(&self) -> String {
+        format!(
+            "{}|{}|{}|{:?}",
+            self.chain_id, self.wallet_address, self.day, self.rank
+        )
+    }
+}
+
+impl RowKey for PositionEvent {
+    fn row_key(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}",
+            self.chain_id,
+            self.pair_address,
+            self.provider_address,
+            self.trigger,
+            self.transaction_hash
+        )
+    }
+}
+
+impl RowKey for MevEvent {
+    fn row_key(&self) -> String {
+        format!(
+            "{}|{}|{}",
+            self.chain_id, self.kind, self.transaction_hashes
+        )
+    }
+}
+
+// Several changes in one transaction differ in the reserves and supply they leave.
+impl RowKey for LiquidityEvent {
+    fn row_key(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}|{}|{}|{}",
+            self.chain_id,
+            self.pair_address,
+            self.trigger,
+            self.block_timestamp,
+            self.transaction_hash,
+            self.reserve0,
+            self.reserve1,
+            self.total_supply
+        )
+    }
+}
+
+impl CandleSink {
+    pub async fn write(&mut self, candles: &[CandleEvent]) -> Result<()> {
+        if candles.is_empty() {
+            return Ok(());
+        }
+        if let Some(csv) = self.csv.as_mut() {
+            csv.write_candle_event(candles)?;
+        }
+        if let Some((tsdb, write_url)) = &self.tsdb {
+            let data = candles
+                .iter()
+                .map(|candle| candle.to_influx_line())
+                .collect::<Vec<_>>()
+                .join("\n");
+            tsdb.write(write_url, &data).await?;
+        }
+        if let Some((mq, topic_name)) = &self.mq {
+            for candle in candles {
+                // Revisions share the key of the window they replace.
+                let key = format!(
+                    "{}-{}-{}-{}",
+                    candle.chain_id, candle.pair_address, candle.interval, candle.open_time
+                );
+                mq.produce_record(topic_name, Some(&key), &serde_json::to_string(candle)?)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MevSink {
+    pub async fn write(&mut self, events: &[MevEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        if let Some(csv) = self.csv.as_mut() {
+            csv.write_mev_event(events)?;
+        }
+        if let Some((tsdb, write_url)) = &self.tsdb {
+            let data = events
+                .iter()
+                .map(|event| event.to_influx_line())
+                .collect::<Vec<_>>()
+                .join("\n");
+            tsdb.write(write_url, &data).await?;
+        }
+        if let Some((mq, topic_name)) = &self.mq {
+            for event in events {
+                let key = format!(
+                    "{}-{}-{}",
+                    event.chain_id, event.kind, event.transaction_hashes
+                );
+                mq.produce_record(topic_name, Some(&key), &serde_json::to_string(event)?)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+macro_rules! sink_record {
+    ($($event:ty),*) => {
+        $(impl SinkRecord for $event {
+            fn to_influx_line(&self) -> String {
+                <$event>::to_influx_line(self)
+            }
+        })*
+    };
+}
+
+sink_record!(
+    MintEvent,
+    BurnEvent,
+    SwapEvent,
+    RouteEvent,
+    FeeEvent,
+    WalletEvent,
+    PositionEvent,
+    LiquidityEvent
+);
+
+impl CsvEventSink {
+    pub fn new(file_name: impl Fn(&str) -> PathBuf + 'static) -> Self {
+        Self {
+            file_name: Box::new(file_name),
+            files: HashMap::new(),
+        }
+    }
+
+    fn file(&mut self, kind: &str) -> Result<&mut PairsTableFile> {
+        if !self.files.contains_key(kind) {
+            let file = (self.file_name)(kind);
+            self.files.insert(
+                kind.to_string(),
+                PairsTableFile::new(file.to_str().unwrap())?,
+            );
+        }
+        Ok(self.files.get_mut(kind).unwrap())
+    }
+}
+
+impl EventSink for CsvEventSink {
+    async fn write<E: SinkRecord>(&mut self, kind: &str, events: &[E]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.file(kind)?.write_events(events)?;
+        info!("Stored {} {} events", events.len(), kind);
+        Ok(())
+    }
+
+    async fn write_custom(&mut self, record: &dyn CustomRecord) -> Result<()> {
+        self.file(record.event_type())?
+            .write_custom_record(record)?;
+        info!("Stored 1 {} record", record.event_type());
+        Ok(())
+    }
+}
+
+impl TsdbEventSink {
+    pub fn new(tsdb: PairsTableTsdb, write_url: &str) -> Self {
+        Self {
+            tsdb,
+            write_url: write_url.to_string(),
+        }
+    }
+}
+
+impl EventSink for TsdbEventSink {
+    async fn write<E: SinkRecord>(&mut self, kind: &str, events: &[E]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let data = events
+            .iter()
+            .map(|event| event.to_influx_line())
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.tsdb.write(&self.write_url, &data).await?;
+        info!("Wrote {} {} events to TSDB", events.len(), kind);
+        Ok(())
+    }
+
+    async fn write_custom(&mut self, record: &dyn CustomRecord) -> Result<()> {
+        self.tsdb
+            .write(&self.write_url, &record.to_influx_line())
+            .await?;
+        info!("Wrote 1 {} record to TSDB", record.event_type());
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -138,7 +664,12 @@ mod tests {
 
         let _tsdb = PairsTableTsdb::new(&app_config.tsdb.auth_token);
 
-        let evm_block = EvmBlock::new(&app_config.eth.http_url).await.unwrap();
+        let evm_block = EvmBlock::new_with_fixture(
+            &app_config.eth.http_url,
+            std::path::Path::new("data/fixtures/test_pair_table.json"),
+        )
+        .await
+        .unwrap();
         let router_addr = Address::from_str("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D").unwrap();
         let uniswap_v2 = UniswapV2::new(evm_block.provider.clone(), router_addr).await;
 
@@ -149,7 +680,8 @@ mod tests {
             .get_pair_created(from_block, to_block)
             .await
             .unwrap();
-        let pair_created_event1 = transform_pair_created_event(&pair_created_event).unwrap();
+        let pair_created_event1 =
+            transform_pair_created_event(&pair_created_event, evm_block.chain_id).unwrap();
         info!("pair_created_event1: {:#?}", pair_created_event1);
 
         let mut pair_created_event_influx_data = String::new();
@@ -188,16 +720,17 @@ mod tests {
                 .unwrap();
 
             if let Some(mint_event_log) = log3.get("Mint") {
-                let mint_event = transform_mint_event(mint_event_log).unwrap();
+                let mint_event = transform_mint_event(mint_event_log, evm_block.chain_id).unwrap();
                 mint_event_temp.extend(mint_event);
             }
             if let Some(burn_event_log) = log3.get("Burn") {
-                let burn_event = transform_burn_event(burn_event_log).unwrap();
+                let burn_event = transform_burn_event(burn_event_log, evm_block.chain_id).unwrap();
                 burn_event_temp.extend(burn_event);
             }
             if let Some(swap_event_log) = log3.get("Swap") {
                 let swap_event = transform_swap_event(
                     swap_event_log,
+                    evm_block.chain_id,
                     uniswap_v2_tokens.token0_decimals,
                     uniswap_v2_tokens.token1_decimals,
                 ).unwrap();
@@ -215,6 +748,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "endless live WebSocket subscription writing to a running TSDB"]
     async fn test_load_swap_event() -> Result<()> {
         let app_config = AppConfig::new().unwrap();
         let _ = app_config.init_log().unwrap();
@@ -233,6 +767,7 @@ mod tests {
         while let Some(log) = stream.next().await {
             let swap_event = transform_swap_event(
                 &[log],
+                evm_block.chain_id,
                 uniswap_v2_tokens.token0_decimals,
                 uniswap_v2_tokens.token1_decimals,
             )
@@ -257,4 +792,92 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_pairs_table_file_idempotent() {
+        let dir = std::env::temp_dir().join(format!("univ2_idempotent_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file_swap = dir.join("swap.csv");
+        let filename = file_swap.to_str().unwrap();
+        let swap = |transaction_hash: &str, log_index| SwapEvent {
+            chain_id: 1,
+            event_type: "Swap".to_string(),
+            transaction_hash: transaction_hash.to_string(),
+            log_index,
+            ..Default::default()
+        };
+        PairsTableFile::new(filename)
+            .unwrap()
+            .write_swap_event(&[swap("0x01", 3), swap("0x01", 4)])
+            .unwrap();
+        // Restart over an overlapping range: one swap already written, one new.
+        let mut repriced = swap("0x01", 4);
+        repriced.amount_usd = Some(10.0);
+        PairsTableFile::new(filename)
+            .unwrap()
+            .write_swap_event(&[repriced, swap("0x02", 0)])
+            .unwrap();
+
+        let content = std::fs::read_to_string(filename).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("chain_id,event_type,"));
+        assert_eq!(
+            lines.iter().filter(|line| line.contains(",0x01,")).count(),
+            2
+        );
+
+        // Candles are keyed by window and revision, not by their values.
+        let file_candle = dir.join("candle.csv");
+        let candle = |revision, close| CandleEvent {
+            interval: "1m".to_string(),
+            open_time: 1751328000,
+            close,
+            revision,
+            ..Default::default()
+        };
+        let mut writer = PairsTableFile::new(file_candle.to_str().unwrap()).unwrap();
+        writer
+            .write_candle_event(&[candle(0, 10.0), candle(1, 11.0)])
+            .unwrap();
+        let mut writer = PairsTableFile::new(file_candle.to_str().unwrap()).unwrap();
+        writer.write_candle_event(&[candle(0, 10.5)]).unwrap();
+        let content = std::fs::read_to_string(&file_candle).unwrap();
+        assert_eq!(content.lines().count(), 3);
+
+        // Other columns, e.g. from an older version, move the file aside.
+        writer.write_swap_event(&[swap("0x01", 3)]).unwrap();
+        let content = std::fs::read_to_string(&file_candle).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 6);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_csv_event_sink() {
+        let dir = std::env::temp_dir().join(format!("univ2_sink_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let swap = SwapEvent {
+            chain_id: 1,
+            transaction_hash: "0x01".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            SinkRecord::to_influx_line(&swap),
+            SwapEvent::to_influx_line(&swap)
+        );
+
+        let output_dir = dir.clone();
+        let mut sink = CsvEventSink::new(move |kind| output_dir.join(format!("{}.csv", kind)));
+        sink.write("swap", std::slice::from_ref(&swap))
+            .await
+            .unwrap();
+        sink.write("swap", &[swap]).await.unwrap();
+        sink.write::<MintEvent>("mint", &[]).await.unwrap();
+        let content = std::fs::read_to_string(dir.join("swap.csv")).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        // Kinds without records get no file.
+        assert!(!dir.join("mint.csv").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
\ No newline at end of file
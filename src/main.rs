@@ -1,138 +1,76 @@
-use alloy::primitives::Address;
-use chrono::Local;
 use clap::Parser;
 use eyre::Result;
-use futures_util::StreamExt;
 use log::{debug, info};
-use serde_json;
 use std::path::Path;
-use std::str::FromStr;
 
-mod extract_block;
-mod extract_event;
-mod init;
-mod load_block;
-mod load_event;
-mod to_mq;
-mod transform_block;
-mod transform_event;
-
-use crate::{
-    extract_block::EvmBlock,
-    extract_event::{
-        BURN_EVENT_SIGNATURE, MINT_EVENT_SIGNATURE, SWAP_EVENT_SIGNATURE, UniswapV2,
-        UniswapV2MultiPair, UniswapV2Tokens,
-    },
-    init::AppConfig,
-    load_event::{PairsTableFile, PairsTableTsdb},
-    to_mq::Mq,
-    transform_event::{
-        BurnEvent, MintEvent, SwapEvent, transform_burn_event, transform_mint_event,
-        transform_pair_created_event, transform_swap_event,
+use etl_evm::{
+    cli::{Cli, Commands, PendingSink, Univ2EventArgs},
+    extract_block::{BlockTimeCache, EvmBlock},
+    init::{AppConfig, parse_time_arg},
+    pipeline::{
+        get_univ2_event, subscribe_univ2_event, subscribe_univ2_event_db, subscribe_univ2_event_mq,
+        subscribe_univ2_pending, transform_univ2_log,
     },
+    transform_event::{TimeFormat, set_decimal_scale, set_time_format},
+    transform_registry::TransformerRegistry,
 };
 
-#[derive(Parser, Debug)]
-#[command(name = "etl_evm")]
-struct Cli {
-    #[command(subcommand)]
-    command: Commands,
-}
-
-#[derive(Parser, Debug)]
-enum Commands {
-    #[command(name = "get_uniswapv2_event_csv")]
-    GetUniv2Event(Univ2EventArgs),
-    #[command(name = "subscribe_uniswapv2_event_csv")]
-    SubscribeUniv2Event(SubscribeUniv2EventArgs),
-    #[command(name = "subscribe_uniswapv2_event_db")]
-    SubscribeUniv2EventDb(SubscribeUniv2EventDbArgs),
-    #[command(name = "subscribe_uniswapv2_create_mq")]
-    SubscribeUniv2EventMq(SubscribeUniv2EventMqArgs),
-}
-
-#[derive(Parser, Debug)]
-struct Univ2EventArgs {
-    #[arg(long)]
-    http_url: Option<String>,
-    #[arg(long)]
-    from_block: Option<u64>,
-    #[arg(long)]
-    to_block: Option<u64>,
-    #[arg(long)]
-    router_address: Option<String>,
-    #[arg(long)]
-    output_dir: Option<String>,
-}
-
-#[derive(Parser, Debug)]
-struct SubscribeUniv2EventArgs {
-    #[arg(long)]
-    ws_url: Option<String>,
-    #[arg(long)]
-    pair_address: Vec<String>,
-    #[arg(long)]
-    output_dir: Option<String>,
-}
-
-#[derive(Parser, Debug)]
-struct SubscribeUniv2EventDbArgs {
-    #[arg(long)]
-    ws_url: Option<String>,
-    #[arg(long)]
-    pair_address: Vec<String>,
-    #[arg(long)]
-    auth_token: Option<String>,
-    #[arg(long)]
-    write_url: Option<String>,
-}
-
-#[derive(Parser, Debug)]
-pub struct SubscribeUniv2EventMqArgs {
-    #[arg(long)]
-    ws_url: Option<String>,
-    #[arg(long)]
-    router_address: Option<String>,
-    #[arg(long)]
-    broker_url: Option<String>,
-}
-
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
     let app_config = AppConfig::new()?;
     let _ = app_config.init_log()?;
+    if let Some(output) = &app_config.output {
+        set_decimal_scale(output.decimal_scale);
+        set_time_format(TimeFormat::new(&output.time_format, &output.timezone)?);
+    }
     debug!("Parsed CLI arguments: {:#?}", cli);
 
     match cli.command {
         Commands::GetUniv2Event(args) => {
             let args_is_full = args.http_url.is_some()
                 && args.router_address.is_some()
-                && args.from_block.is_some()
-                && args.to_block.is_some();
+                && (args.from_block.is_some() || args.from_time.is_some())
+                && (args.to_block.is_some() || args.to_time.is_some());
 
-            let app_config = if args_is_full {
+            let mut app_config = if args_is_full {
                 info!("cli args is full,ignoring config file.");
                 AppConfig::from_get_cli(&args)?
             } else {
                 info!("args is not full, Using default config from data/etl.toml");
                 AppConfig::from_file("data/etl.toml")?
             };
-            debug!("app_config: {:#?}", app_config);
-            get_univ2_event(&app_config).await?;
+            app_config.add_filters(&args.filter)?;
+            let chain_configs = app_config.select_chains(&args.chain)?;
+            futures::future::try_join_all(chain_configs.into_iter().map(|mut app_config| {
+                let args = &args;
+                async move {
+                    if args.from_time.is_some() || args.to_time.is_some() {
+                        resolve_time_range(&mut app_config, args).await?;
+                    }
+                    debug!("app_config: {:#?}", app_config);
+                    get_univ2_event(&app_config, &default_registry(&app_config)).await
+                }
+            }))
+            .await?;
         }
         Commands::SubscribeUniv2Event(args) => {
             let args_is_full = args.ws_url.is_some() && !args.pair_address.is_empty();
 
-            let app_config = if args_is_full {
+            let mut app_config = if args_is_full {
                 info!("cli args is full,ignoring config file.");
                 AppConfig::from_subscribe_cli(&args)?
             } else {
                 info!("args is not full, Using default config from data/etl.toml");
                 AppConfig::from_file("data/etl.toml")?
             };
+            app_config.add_filters(&args.filter)?;
             debug!("app_config: {:#?}", app_config);
-            subscribe_univ2_event(&app_config).await?;
+            let chain_configs = app_config.select_chains(&args.chain)?;
+            futures::future::try_join_all(chain_configs.iter().map(|config| async move {
+                subscribe_univ2_event(config, &default_registry(config)).await
+            }))
+            .await?;
         }
         Commands::SubscribeUniv2EventDb(args) => {
             let args_is_full = args.ws_url.is_some()
@@ -140,293 +78,128 @@ async fn main() -> Result<()> {
                 && args.auth_token.is_some()
                 && args.write_url.is_some();
 
-            let app_config = if args_is_full {
+            let mut app_config = if args_is_full {
                 info!("cli args is full,ignoring config file.");
                 AppConfig::from_subscribe_db_cli(&args)?
             } else {
                 info!("args is not full, Using default config from data/etl.toml");
                 AppConfig::from_file("data/etl.toml")?
             };
+            app_config.add_filters(&args.filter)?;
             debug!("app_config: {:#?}", app_config);
-            subscribe_univ2_event_db(&app_config).await?;
+            let chain_configs = app_config.select_chains(&args.chain)?;
+            futures::future::try_join_all(chain_configs.iter().map(|config| async move {
+                subscribe_univ2_event_db(config, &default_registry(config)).await
+            }))
+            .await?;
         }
         Commands::SubscribeUniv2EventMq(args) => {
             let args_is_full =
                 args.ws_url.is_some() && args.router_address.is_some() && args.broker_url.is_some();
 
-            let app_config = if args_is_full {
+            let mut app_config = if args_is_full {
                 info!("cli args is full,ignoring config file.");
                 AppConfig::from_subscribe_mq_cli(&args)?
             } else {
                 info!("args is not full, Using default config from data/etl.toml");
                 AppConfig::from_file("data/etl.toml")?
             };
+            app_config.add_filters(&args.filter)?;
 
             debug!("app_config: {:#?}", app_config);
-            subscribe_univ2_event_mq(&app_config).await?;
+            let chain_configs = app_config.select_chains(&args.chain)?;
+            futures::future::try_join_all(chain_configs.iter().map(subscribe_univ2_event_mq))
+                .await?;
         }
-    }
-
-    Ok(())
-}
-
-async fn get_univ2_event(config: &AppConfig) -> Result<()> {
-    let evm_block = EvmBlock::new(&config.eth.http_url).await?;
-    let router_address = Address::from_str(&config.uniswap_v2.router_address)?;
-    let uniswap_v2 = UniswapV2::new(evm_block.provider.clone(), router_address).await;
-
-    let pair_created_logs = uniswap_v2
-        .get_pair_created(config.uniswap_v2.from_block, config.uniswap_v2.to_block)
-        .await?;
-    let pair_created_events = transform_pair_created_event(&pair_created_logs)?;
-
-    let output_dir = Path::new(&config.csv.output_dir);
-    std::fs::create_dir_all(output_dir)?;
-    let create_time = Local::now().format("%y%m%d");
-    let output_file = output_dir.join(format!("get_univ2_create_{}.csv", create_time));
-    let mut csv_file0 = PairsTableFile::new(output_file.to_str().unwrap())?;
-    csv_file0.write_pair_created_event(&pair_created_events)?;
-    info!(
-        "Wrote {} Pair Created events to {:?}.",
-        pair_created_events.len(),
-        output_file
-    );
-
-    let mut all_mint_events: Vec<MintEvent> = Vec::new();
-    let mut all_burn_events: Vec<BurnEvent> = Vec::new();
-    let mut all_swap_events: Vec<SwapEvent> = Vec::new();
+        Commands::SubscribeUniv2Pending(args) => {
+            let args_is_full = args.ws_url.is_some()
+                && !args.router_address.is_empty()
+                && (args.sink == PendingSink::Csv || args.broker_url.is_some());
 
-    for event in pair_created_events {
-        let pair_address = event.pair_address;
-        let uniswap_v2_tokens =
-            UniswapV2Tokens::new(pair_address, evm_block.provider.clone()).await?;
+            let mut app_config = if args_is_full {
+                info!("cli args is full,ignoring config file.");
+                AppConfig::from_subscribe_pending_cli(&args)?
+            } else {
+                info!("args is not full, Using default config from data/etl.toml");
+                AppConfig::from_file("data/etl.toml")?
+            };
+            app_config.add_filters(&args.filter)?;
 
-        let log3 = uniswap_v2_tokens
-            .get_all_event(config.uniswap_v2.from_block, config.uniswap_v2.to_block)
+            debug!("app_config: {:#?}", app_config);
+            let chain_configs = app_config.select_chains(&args.chain)?;
+            futures::future::try_join_all(
+                chain_configs
+                    .iter()
+                    .map(|config| subscribe_univ2_pending(config, args.sink)),
+            )
             .await?;
-
-        if let Some(mint_event_log) = log3.get("Mint") {
-            let mint_events = transform_mint_event(mint_event_log)?;
-            all_mint_events.extend(mint_events);
         }
-        if let Some(burn_event_log) = log3.get("Burn") {
-            let burn_events = transform_burn_event(burn_event_log)?;
-            all_burn_events.extend(burn_events);
-        }
-        if let Some(swap_event_log) = log3.get("Swap") {
-            let swap_events = transform_swap_event(
-                swap_event_log,
-                uniswap_v2_tokens.token0_decimals,
-                uniswap_v2_tokens.token1_decimals,
-            )?;
-            all_swap_events.extend(swap_events);
+        Commands::TransformUniv2Log(args) => {
+            let mut app_config = app_config;
+            app_config.add_filters(&args.filter)?;
+            let chain_id = args.chain_id.or(app_config.eth.chain_id).ok_or_else(|| {
+                eyre::eyre!("--chain-id is required when eth.chain_id is not set")
+            })?;
+            let output_dir = args
+                .output_dir
+                .clone()
+                .unwrap_or_else(|| app_config.csv.output_dir.clone());
+            transform_univ2_log(
+                &app_config,
+                &default_registry(&app_config),
+                &args.input,
+                args.pair_metadata.as_deref(),
+                chain_id,
+                Path::new(&output_dir),
+            )
+            .await?;
         }
     }
 
-    let file_mint = output_dir.join(format!("get_univ2_mint_{}.csv", create_time));
-    let mut csv_file1 = PairsTableFile::new(file_mint.to_str().unwrap())?;
-    csv_file1.write_mint_event(&all_mint_events)?;
-    info!(
-        "Wrote {} Mint events to {:?}.",
-        all_mint_events.len(),
-        file_mint
-    );
-
-    let file_burn = output_dir.join(format!("get_univ2_burn_{}.csv", create_time));
-    let mut csv_file2 = PairsTableFile::new(file_burn.to_str().unwrap())?;
-    csv_file2.write_burn_event(&all_burn_events)?;
-    info!(
-        "Wrote {} Burn events to {:?}.",
-        all_burn_events.len(),
-        file_burn
-    );
-
-    let file_swap = output_dir.join(format!("get_univ2_swap_{}.csv", create_time));
-    let mut csv_file3 = PairsTableFile::new(file_swap.to_str().unwrap())?;
-    csv_file3.write_swap_event(&all_swap_events)?;
-    info!(
-        "Wrote {} Swap events to {:?}.",
-        all_swap_events.len(),
-        file_swap
-    );
-
     Ok(())
 }
 
-async fn subscribe_univ2_event(config: &AppConfig) -> Result<()> {
-    let provider = EvmBlock::new(&config.eth.ws_url).await?.provider;
-    let pair_addresses = config
-        .uniswap_v2
-        .pair_address
-        .as_ref()
-        .ok_or_else(|| eyre::eyre!("Missing pair addresses in config"))?
-        .iter()
-        .map(|s| Address::from_str(s))
-        .collect::<Result<Vec<_>, _>>()?;
-
-    let multi_pair = UniswapV2MultiPair::new(provider, pair_addresses).await?;
-
-    let mut stream = multi_pair.subscribe_all_events().await?;
-
-    let output_dir = Path::new(&config.csv.output_dir);
-    let create_time = Local::now().format("%y%m%d");
-    let file_mint = output_dir.join(format!("sub_univ2_mint_{}.csv", create_time));
-    let mut csv_writer_mint = PairsTableFile::new(file_mint.to_str().unwrap())?;
-    let file_burn = output_dir.join(format!("sub_univ2_burn_{}.csv", create_time));
-    let mut csv_writer_burn = PairsTableFile::new(file_burn.to_str().unwrap())?;
-    let file_swap = output_dir.join(format!("sub_univ2_swap_{}.csv", create_time));
-    let mut csv_writer_swap = PairsTableFile::new(file_swap.to_str().unwrap())?;
-
-    info!("Listening for Mint, Burn, and Swap events...");
-    while let Some(log) = stream.next().await {
-        let event_signature = if log.topics().is_empty() {
-            continue;
-        } else {
-            log.topics()[0]
-        };
-        let pair_address = log.address();
-
-        match event_signature {
-            sig if sig == MINT_EVENT_SIGNATURE => {
-                let mint_events = transform_mint_event(&[log])?;
-                csv_writer_mint.write_mint_event(&mint_events)?;
-                info!("Stored 1 Mint event from pair {}", pair_address);
-            }
-            sig if sig == BURN_EVENT_SIGNATURE => {
-                let burn_events = transform_burn_event(&[log])?;
-                csv_writer_burn.write_burn_event(&burn_events)?;
-                info!("Stored 1 Burn event from pair {}", pair_address);
-            }
-            sig if sig == SWAP_EVENT_SIGNATURE => {
-                if let Some(pair_info) = multi_pair.pairs.get(&pair_address) {
-                    let swap_events = transform_swap_event(
-                        &[log.clone()],
-                        pair_info.token0.decimals,
-                        pair_info.token1.decimals,
-                    )?;
-                    csv_writer_swap.write_swap_event(&swap_events)?;
-                    info!("Stored 1 Swap event from pair {}", pair_address);
-                }
-            }
-            _ => {
-                debug!("Ignoring unknown event with signature: {}", event_signature);
-            }
-        }
-    }
-
-    Ok(())
+/// Mint, Burn and Swap plus the custom transformers, see `TransformerRegistry::new`.
+fn default_registry(config: &AppConfig) -> TransformerRegistry {
+    TransformerRegistry::new().with_token_metadata(config.token_metadata_enabled())
 }
 
-async fn subscribe_univ2_event_db(config: &AppConfig) -> Result<()> {
-    let provider = EvmBlock::new(&config.eth.ws_url).await?.provider;
-    let pair_addresses = config
-        .uniswap_v2
-        .pair_address
-        .as_ref()
-        .ok_or_else(|| eyre::eyre!("Missing pair addresses in config"))?
-        .iter()
-        .map(|s| Address::from_str(s))
-        .collect::<Result<Vec<_>, _>>()?;
-
-    let multi_pair = UniswapV2MultiPair::new(provider, pair_addresses).await?;
-    let mut stream = multi_pair.subscribe_all_events().await?;
-
-    let tsdb = PairsTableTsdb::new(&config.tsdb.auth_token);
-    let write_url = config.tsdb.write_url.as_str();
-
-    info!("Starting TSDB event subscription...");
-    while let Some(log) = stream.next().await {
-        let event_signature = log.topics().get(0).cloned().unwrap_or_default();
-        let pair_address = log.address();
-
-        match event_signature {
-            sig if sig == MINT_EVENT_SIGNATURE => {
-                let events = transform_mint_event(&[log])?;
-                let data = events
-                    .iter()
-                    .map(|e| e.to_influx_line())
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                tsdb.write(write_url, &data).await?;
-                info!("Wrote 1 Mint event to TSDB from pair {}", pair_address);
-            }
-            sig if sig == BURN_EVENT_SIGNATURE => {
-                let events = transform_burn_event(&[log])?;
-                let data = events
-                    .iter()
-                    .map(|e| e.to_influx_line())
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                tsdb.write(write_url, &data).await?;
-                info!("Wrote 1 Burn event to TSDB from pair {}", pair_address);
-            }
-            sig if sig == SWAP_EVENT_SIGNATURE => {
-                if let Some(pair_info) = multi_pair.pairs.get(&pair_address) {
-                    let events = transform_swap_event(
-                        &[log],
-                        pair_info.token0.decimals,
-                        pair_info.token1.decimals,
-                    )?;
-                    let data = events
-                        .iter()
-                        .map(|e| e.to_influx_line())
-                        .collect::<Vec<_>>()
-                        .join("\n");
-                    debug!(
-                        "Wrote 1 Swap event to TSDB from pair {}: {}",
-                        pair_address, data
-                    );
-                    tsdb.write(write_url, &data).await?;
-                    info!("Wrote 1 Swap event to TSDB from pair {}", pair_address);
-                }
-            }
-            _ => {
-                debug!("Ignoring unknown event signature: {}", event_signature);
-            }
-        }
+async fn resolve_time_range(config: &mut AppConfig, args: &Univ2EventArgs) -> Result<()> {
+    let evm_block = EvmBlock::new(&config.eth.http_url).await?;
+    evm_block.ensure_chain_id(config.eth.chain_id)?;
+    let cache_file = Path::new(&config.csv.output_dir)
+        .join(format!("block_time_cache_{}.json", evm_block.chain_id));
+    let mut cache = BlockTimeCache::load(&cache_file)?;
+
+    if let Some(from_time) = &args.from_time {
+        let from_timestamp = parse_time_arg(from_time, false)?;
+        config.uniswap_v2.from_block = evm_block
+            .get_block_by_timestamp(from_timestamp, &mut cache)
+            .await?
+            .ok_or_else(|| eyre::eyre!("--from-time {} is after the latest block", from_time))?;
     }
-
-    Ok(())
-}
-
-async fn subscribe_univ2_event_mq(config: &AppConfig) -> Result<()> {
-    let evm_block = EvmBlock::new(&config.eth.ws_url).await?;
-    let provider = evm_block.provider;
-
-    let router_address = Address::from_str(&config.uniswap_v2.router_address)?;
-    let uniswap_v2 = UniswapV2::new(provider.clone(), router_address).await;
-
-    let mq = Mq::new(&config.mq.broker_url).await?;
-    // topic name may only include lowercase letters (a-z), numbers (0-9), and hyphens (-).
-    let topic_name = "uniswap-v2-pair-created";
-
-    let topics = mq.list_topics().await?;
-    if !topics.iter().any(|t| t == topic_name) {
-        info!("Topic '{}' does not exist, creating it.", topic_name);
-        mq.create_topic(topic_name).await?;
-        info!("Topic '{}' created.", topic_name);
-    } else {
-        info!("Topic '{}' already exists.", topic_name);
+    if let Some(to_time) = &args.to_time {
+        let to_timestamp = parse_time_arg(to_time, true)?;
+        config.uniswap_v2.to_block = evm_block
+            .get_last_block_by_timestamp(to_timestamp, &mut cache)
+            .await?;
+    }
+    cache.save(&cache_file)?;
+
+    if config.uniswap_v2.to_block < config.uniswap_v2.from_block {
+        return Err(eyre::eyre!(
+            "Time range {:?} - {:?} contains no blocks",
+            args.from_time,
+            args.to_time
+        ));
     }
-
-    let mut stream = uniswap_v2.subscribe_pair_created().await?;
-
     info!(
-        "Listening for PairCreated events to push to MQ topic '{}'...",
-        topic_name
+        "Resolved time range {:?} - {:?} to blocks {} - {} on chain {}",
+        args.from_time,
+        args.to_time,
+        config.uniswap_v2.from_block,
+        config.uniswap_v2.to_block,
+        evm_block.chain_id
     );
-    while let Some(log) = stream.next().await {
-        let pair_created_events = transform_pair_created_event(&[log])?;
-        if let Some(event) = pair_created_events.first() {
-            let event_json = serde_json::to_string(event)?;
-            mq.produce_record(topic_name, &event_json).await?;
-            info!(
-                "Pushed PairCreated event for pair {} to topic '{}'",
-                event.pair_address, topic_name
-            );
-            debug!("Event data: {}", event_json);
-        }
-    }
-
     Ok(())
 }
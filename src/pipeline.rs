@@ -0,0 +1,1521 @@
+use alloy::consensus::Transaction as _;
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, B256};
+use alloy::providers::{DynProvider, Provider};
+use alloy::rpc::types::Log;
+use chrono::{Local, Utc};
+use eyre::Result;
+use futures_util::StreamExt;
+use log::{debug, info, warn};
+use serde_json;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::{
+    cli::PendingSink,
+    extract_block::EvmBlock,
+    extract_event::{
+        BURN_EVENT_SIGNATURE, MINT_EVENT_SIGNATURE, PAIR_CREATED_EVENT_SIGNATURE,
+        SWAP_EVENT_SIGNATURE, SYNC_EVENT_SIGNATURE, TRANSFER_EVENT_SIGNATURE, UniswapV2,
+        UniswapV2MultiPair, UniswapV2TokenPair, UniswapV2Tokens,
+    },
+    extract_file::{read_log_file, read_pair_metadata, write_pair_metadata},
+    init::AppConfig,
+    load_event::{
+        CandleSink, CsvEventSink, EventSink, MevSink, PairsTableFile, PairsTableTsdb, TsdbEventSink,
+    },
+    to_mq::Mq,
+    transform_candle::{CandleAggregator, parse_interval},
+    transform_event::{
+        BurnEvent, MintEvent, PairCreatedEvent, SwapEvent, transform_pair_created_event,
+        transform_swap_event,
+    },
+    transform_fee::{FeeEvent, FeeTracker, rank_pairs},
+    transform_filter::EventFilter,
+    transform_impact::PriceImpactTracker,
+    transform_liquidity::{LiquidityEvent, LiquidityTracker},
+    transform_mev::{LpProviders, MevBlockDetector, detect_mev},
+    transform_position::{PositionEvent, PositionTracker, price_positions},
+    transform_price::UsdPricer,
+    transform_registry::{CustomRecord, EventRecord, TransformerRegistry},
+    transform_risk::TokenRiskAnalyzer,
+    transform_route::transform_route_event,
+    transform_tx::{SwapIntentEnricher, transform_router_call},
+    transform_wallet::{WalletEvent, WalletTracker, rank_traders},
+};
+
+pub async fn get_univ2_event(config: &AppConfig, registry: &TransformerRegistry) -> Result<()> {
+    let evm_block = EvmBlock::new(&config.eth.http_url).await?;
+    evm_block.ensure_chain_id(config.eth.chain_id)?;
+    let chain_id = evm_block.chain_id;
+    let router_address = Address::from_str(&config.uniswap_v2.router_address)?;
+    let uniswap_v2 = UniswapV2::new(evm_block.provider.clone(), router_address).await;
+
+    let pair_created_logs = uniswap_v2
+        .get_pair_created(config.uniswap_v2.from_block, config.uniswap_v2.to_block)
+        .await?;
+    let pair_created_events = transform_pair_created_event(&pair_created_logs, chain_id)?;
+
+    let output_dir = Path::new(&config.csv.output_dir);
+    std::fs::create_dir_all(output_dir)?;
+    let create_time = Local::now().format("%y%m%d");
+    let output_file = output_dir.join(format!("get_univ2_create_{}_{}.csv", chain_id, create_time));
+    let mut event_filter = event_filter(config)?;
+    let mut csv_file0 = PairsTableFile::new(output_file.to_str().unwrap())?;
+    let pair_created_rows = event_filter.filtered("create", &pair_created_events)?;
+    csv_file0.write_pair_created_event(&pair_created_rows)?;
+    info!(
+        "Wrote {} Pair Created events to {:?}.",
+        pair_created_rows.len(),
+        output_file
+    );
+
+    let mut intent_enricher = config
+        .router_intent_enabled()
+        .then(|| SwapIntentEnricher::new(evm_block.provider.clone()));
+    let mut csv_writer_custom: HashMap<String, PairsTableFile> = HashMap::new();
+    let mut price_impact = price_impact_tracker(config);
+    let mut all_mint_events: Vec<MintEvent> = Vec::new();
+    let mut all_burn_events: Vec<BurnEvent> = Vec::new();
+    let mut all_swap_events: Vec<SwapEvent> = Vec::new();
+    let mut pairs: HashMap<Address, UniswapV2TokenPair> = HashMap::new();
+    // Pairs are created inside the range, so their liquidity starts from zero.
+    let mut liquidity_tracker = liquidity_tracker(config, chain_id)?;
+    let mut all_liquidity_events: Vec<LiquidityEvent> = Vec::new();
+    let mut last_timestamp = 0;
+    let mut position_tracker = position_tracker(config, chain_id);
+    let mut all_position_events: Vec<PositionEvent> = Vec::new();
+    // LP Transfer logs, requested for MEV detection.
+    let mut all_transfer_logs: Vec<Log> = Vec::new();
+
+    for event in pair_created_events {
+        let pair_address = event.pair_address;
+        let uniswap_v2_tokens =
+            UniswapV2Tokens::new(pair_address, evm_block.provider.clone()).await?;
+        pairs.insert(
+            pair_address,
+            UniswapV2TokenPair::from_tokens(&uniswap_v2_tokens),
+        );
+
+        let logs = uniswap_v2_tokens
+            .get_events(
+                config.uniswap_v2.from_block,
+                config.uniswap_v2.to_block,
+                &event_signatures(config, registry, price_impact.is_some()),
+            )
+            .await?;
+        let mut swap_events = Vec::new();
+        for log in &logs {
+            if let Some(tracker) = price_impact.as_mut() {
+                tracker.observe_log(log)?;
+            }
+            if log.topics().first() == Some(&TRANSFER_EVENT_SIGNATURE) {
+                all_transfer_logs.push(log.clone());
+            }
+            for record in registry.transform(log, chain_id, &pairs)? {
+                match record {
+                    EventRecord::Mint(event) => all_mint_events.push(event),
+                    EventRecord::Burn(event) => all_burn_events.push(event),
+                    EventRecord::Swap(mut event) => {
+                        if let Some(tracker) = price_impact.as_ref() {
+                            tracker.apply(&mut event, &pairs[&pair_address]);
+                        }
+                        swap_events.push(*event);
+                    }
+                    EventRecord::Custom(record) => {
+                        if !event_filter.keep_custom(record.as_ref())? {
+                            continue;
+                        }
+                        write_custom_record(
+                            &mut csv_writer_custom,
+                            record.as_ref(),
+                            |event_type| {
+                                output_dir.join(format!(
+                                    "get_univ2_{}_{}_{}.csv",
+                                    event_type, chain_id, create_time
+                                ))
+                            },
+                        )?;
+                    }
+                }
+            }
+        }
+        if let Some(enricher) = intent_enricher.as_mut() {
+            enricher.enrich(&mut swap_events).await?;
+        }
+        all_swap_events.extend(swap_events);
+
+        if let Some(tracker) = liquidity_tracker.as_mut() {
+            let mut liquidity_logs = uniswap_v2_tokens
+                .get_liquidity_event(
+                    config.uniswap_v2.from_block,
+                    config.uniswap_v2.to_block,
+                    position_tracker.is_some(),
+                )
+                .await?;
+            if position_tracker.is_some() {
+                // Positions tie each Mint and Burn to the LP transfers of its transaction.
+                liquidity_logs.extend(
+                    logs.iter()
+                        .filter(|log| {
+                            log.topics().first().is_some_and(|sig| {
+                                *sig == MINT_EVENT_SIGNATURE || *sig == BURN_EVENT_SIGNATURE
+                            })
+                        })
+                        .cloned(),
+                );
+                liquidity_logs.sort_by_key(|log| (log.block_number, log.log_index));
+            }
+            for log in liquidity_logs {
+                last_timestamp = last_timestamp.max(log.block_timestamp.unwrap_or_default());
+                let events = tracker.apply_log(&log, &pairs[&pair_address])?;
+                if let Some(positions) = position_tracker.as_mut() {
+                    positions.update_pool(&events);
+                    all_position_events.extend(positions.apply_log(&log, &pairs[&pair_address])?);
+                }
+                all_liquidity_events.extend(events);
+            }
+        }
+    }
+    if let Some(tracker) = liquidity_tracker.as_mut() {
+        all_liquidity_events.extend(tracker.advance_to(&pairs, last_timestamp));
+    }
+
+    if let Some(pricing) = &config.pricing {
+        // Start from the reserves the range opens with; reference swaps move prices from there.
+        let seed_block = BlockId::number(config.uniswap_v2.from_block.saturating_sub(1));
+        let mut usd_pricer =
+            UsdPricer::connect(pricing, evm_block.provider.clone(), seed_block).await?;
+        let mut reference_swaps = Vec::new();
+        for reference_pair in usd_pricer.reference_pairs.values() {
+            let tokens =
+                UniswapV2Tokens::new(reference_pair.pair_address, evm_block.provider.clone())
+                    .await?;
+            let logs = tokens
+                .get_all_event(config.uniswap_v2.from_block, config.uniswap_v2.to_block)
+                .await?;
+            if let Some(swap_logs) = logs.get("Swap") {
+                reference_swaps.extend(transform_swap_event(
+                    swap_logs,
+                    chain_id,
+                    tokens.token0_decimals,
+                    tokens.token1_decimals,
+                )?);
+            }
+        }
+        usd_pricer.price_events(
+            &reference_swaps,
+            &mut all_swap_events,
+            &mut all_mint_events,
+            &mut all_burn_events,
+            &mut all_liquidity_events,
+            &pairs,
+        );
+    }
+
+    let file_mint = output_dir.join(format!("get_univ2_mint_{}_{}.csv", chain_id, create_time));
+    let mut csv_file1 = PairsTableFile::new(file_mint.to_str().unwrap())?;
+    let mint_rows = event_filter.filtered("mint", &all_mint_events)?;
+    csv_file1.write_mint_event(&mint_rows)?;
+    info!("Wrote {} Mint events to {:?}.", mint_rows.len(), file_mint);
+
+    let file_burn = output_dir.join(format!("get_univ2_burn_{}_{}.csv", chain_id, create_time));
+    let mut csv_file2 = PairsTableFile::new(file_burn.to_str().unwrap())?;
+    let burn_rows = event_filter.filtered("burn", &all_burn_events)?;
+    csv_file2.write_burn_event(&burn_rows)?;
+    info!("Wrote {} Burn events to {:?}.", burn_rows.len(), file_burn);
+
+    let file_swap = output_dir.join(format!("get_univ2_swap_{}_{}.csv", chain_id, create_time));
+    let mut csv_file3 = PairsTableFile::new(file_swap.to_str().unwrap())?;
+    let swap_rows = event_filter.filtered("swap", &all_swap_events)?;
+    csv_file3.write_swap_event(&swap_rows)?;
+    info!("Wrote {} Swap events to {:?}.", swap_rows.len(), file_swap);
+
+    event_filter.log_dropped();
+
+    let route_events = transform_route_event(&all_swap_events, &pairs)?;
+    let file_route = output_dir.join(format!("get_univ2_route_{}_{}.csv", chain_id, create_time));
+    let mut csv_file4 = PairsTableFile::new(file_route.to_str().unwrap())?;
+    csv_file4.write_route_event(&route_events)?;
+    info!(
+        "Wrote {} Route events to {:?}.",
+        route_events.len(),
+        file_route
+    );
+
+    if liquidity_tracker.is_some() {
+        let file_liquidity = output_dir.join(format!(
+            "get_univ2_liquidity_{}_{}.csv",
+            chain_id, create_time
+        ));
+        PairsTableFile::new(file_liquidity.to_str().unwrap())?
+            .write_liquidity_event(&all_liquidity_events)?;
+        info!(
+            "Wrote {} Liquidity events to {:?}.",
+            all_liquidity_events.len(),
+            file_liquidity
+        );
+    }
+
+    if position_tracker.is_some() {
+        price_positions(&mut all_position_events, &all_liquidity_events);
+        let file_position = output_dir.join(format!(
+            "get_univ2_position_{}_{}.csv",
+            chain_id, create_time
+        ));
+        PairsTableFile::new(file_position.to_str().unwrap())?
+            .write_position_event(&all_position_events)?;
+        info!(
+            "Wrote {} Position events to {:?}.",
+            all_position_events.len(),
+            file_position
+        );
+    }
+
+    if let Some(mut fee_tracker) = fee_tracker(config, chain_id)? {
+        for pair_address in pairs.keys() {
+            fee_tracker.set_factory(*pair_address, *uniswap_v2.factory_caller.address());
+        }
+        let fee_events = fee_tracker.track_batch(&all_swap_events, &all_liquidity_events);
+        write_fee_report(
+            &fee_events,
+            &output_dir.join(format!("get_univ2_fee_{}_{}.csv", chain_id, create_time)),
+            &output_dir.join(format!(
+                "get_univ2_fee_ranking_{}_{}.csv",
+                chain_id, create_time
+            )),
+        )?;
+    }
+
+    if let Some(mut wallet_tracker) = wallet_tracker(config, chain_id)? {
+        let wallet_events = wallet_tracker.track_batch(&all_swap_events, &pairs)?;
+        write_wallet_report(
+            config,
+            &wallet_events,
+            &output_dir.join(format!("get_univ2_wallet_{}_{}.csv", chain_id, create_time)),
+            &output_dir.join(format!(
+                "get_univ2_top_traders_{}_{}.csv",
+                chain_id, create_time
+            )),
+        )?;
+    }
+
+    let file_mev = output_dir.join(format!("get_univ2_mev_{}_{}.csv", chain_id, create_time));
+    write_batch_mev(
+        config,
+        chain_id,
+        file_mev,
+        (&all_swap_events, &all_mint_events, &all_burn_events),
+        &all_transfer_logs,
+        &pairs,
+    )
+    .await?;
+
+    let file_candle = output_dir.join(format!("get_univ2_candle_{}_{}.csv", chain_id, create_time));
+    write_batch_candles(config, chain_id, Some(file_candle), &all_swap_events).await?;
+
+    // Lets transform_uniswapv2_log_csv reprocess saved logs of these pairs offline.
+    let file_pairs = output_dir.join(format!("get_univ2_pairs_{}_{}.json", chain_id, create_time));
+    write_pair_metadata(&file_pairs, &pairs)?;
+    info!("Wrote {} pairs to {:?}.", pairs.len(), file_pairs);
+
+    Ok(())
+}
+
+pub async fn transform_univ2_log(
+    config: &AppConfig,
+    registry: &TransformerRegistry,
+    inputs: &[String],
+    pair_metadata: Option<&str>,
+    chain_id: u64,
+    output_dir: &Path,
+) -> Result<()> {
+    let mut logs = Vec::new();
+    for input in inputs {
+        let file_logs = read_log_file(Path::new(input))?;
+        info!("Read {} logs from {}", file_logs.len(), input);
+        logs.extend(file_logs);
+    }
+    logs.sort_by_key(|log| (log.block_number, log.log_index));
+
+    let pairs = match pair_metadata {
+        Some(path) => read_pair_metadata(Path::new(path))?,
+        None => HashMap::new(),
+    };
+
+    let mut liquidity_events = Vec::new();
+    let mut liquidity = liquidity_tracker(config, chain_id)?;
+    let mut position_events = Vec::new();
+    let mut positions = position_tracker(config, chain_id);
+    if let Some(tracker) = liquidity.as_mut() {
+        let mut last_timestamp = 0;
+        for log in &logs {
+            if let Some(pair_info) = pairs.get(&log.address()) {
+                last_timestamp = last_timestamp.max(log.block_timestamp.unwrap());
+                let events = tracker.apply_log(log, pair_info)?;
+                if let Some(positions) = positions.as_mut() {
+                    positions.update_pool(&events);
+                    position_events.extend(positions.apply_log(log, pair_info)?);
+                }
+                liquidity_events.extend(events);
+            }
+        }
+        liquidity_events.extend(tracker.advance_to(&pairs, last_timestamp));
+    }
+
+    let mut price_impact = price_impact_tracker(config);
+    let mut pair_created_logs = Vec::new();
+    let mut mint_events = Vec::new();
+    let mut burn_events = Vec::new();
+    let mut swap_events = Vec::new();
+    let mut custom_records = Vec::new();
+    let mut skipped_swaps = 0;
+    for log in &logs {
+        match log.topics().first() {
+            Some(sig) if *sig == PAIR_CREATED_EVENT_SIGNATURE => {
+                pair_created_logs.push(log.clone());
+                continue;
+            }
+            Some(sig) if *sig == SWAP_EVENT_SIGNATURE && !pairs.contains_key(&log.address()) => {
+                skipped_swaps += 1;
+                continue;
+            }
+            _ => {}
+        }
+        if let Some(tracker) = price_impact.as_mut() {
+            tracker.observe_log(log)?;
+        }
+        for record in registry.transform(log, chain_id, &pairs)? {
+            match record {
+                EventRecord::Mint(event) => mint_events.push(event),
+                EventRecord::Burn(event) => burn_events.push(event),
+                EventRecord::Swap(mut event) => {
+                    if let Some(tracker) = price_impact.as_ref() {
+                        tracker.apply(&mut event, &pairs[&log.address()]);
+                    }
+                    swap_events.push(*event);
+                }
+                EventRecord::Custom(record) => custom_records.push(record),
+            }
+        }
+    }
+    if skipped_swaps > 0 {
+        warn!(
+            "Skipped {} Swap logs of pairs missing from the pair metadata",
+            skipped_swaps
+        );
+    }
+
+    if let Some(pricing) = &config.pricing {
+        // Without an RPC, reference pairs must be in the pair metadata and prices start
+        // with their first swap in the logs.
+        let reference_pairs = pricing
+            .reference_pairs
+            .iter()
+            .map(|s| Address::from_str(s))
+            .collect::<Result<Vec<_>, _>>()?
+            .iter()
+            .filter_map(|address| pairs.get(address).cloned())
+            .collect();
+        let mut usd_pricer = UsdPricer::new(pricing, reference_pairs)?;
+        usd_pricer.price_events(
+            &[],
+            &mut swap_events,
+            &mut mint_events,
+            &mut burn_events,
+            &mut liquidity_events,
+            &pairs,
+        );
+    }
+
+    std::fs::create_dir_all(output_dir)?;
+    let create_time = Local::now().format("%y%m%d");
+    let file_name = |kind: &str| {
+        output_dir.join(format!(
+            "file_univ2_{}_{}_{}.csv",
+            kind, chain_id, create_time
+        ))
+    };
+
+    let mut event_filter = event_filter(config)?;
+    let pair_created_events = transform_pair_created_event(&pair_created_logs, chain_id)?;
+    let pair_created_rows = event_filter.filtered("create", &pair_created_events)?;
+    let file_create = file_name("create");
+    PairsTableFile::new(file_create.to_str().unwrap())?
+        .write_pair_created_event(&pair_created_rows)?;
+    info!(
+        "Wrote {} Pair Created events to {:?}.",
+        pair_created_rows.len(),
+        file_create
+    );
+
+    let file_mint = file_name("mint");
+    let mint_rows = event_filter.filtered("mint", &mint_events)?;
+    PairsTableFile::new(file_mint.to_str().unwrap())?.write_mint_event(&mint_rows)?;
+    info!("Wrote {} Mint events to {:?}.", mint_rows.len(), file_mint);
+
+    let file_burn = file_name("burn");
+    let burn_rows = event_filter.filtered("burn", &burn_events)?;
+    PairsTableFile::new(file_burn.to_str().unwrap())?.write_burn_event(&burn_rows)?;
+    info!("Wrote {} Burn events to {:?}.", burn_rows.len(), file_burn);
+
+    let file_swap = file_name("swap");
+    let swap_rows = event_filter.filtered("swap", &swap_events)?;
+    PairsTableFile::new(file_swap.to_str().unwrap())?.write_swap_event(&swap_rows)?;
+    info!("Wrote {} Swap events to {:?}.", swap_rows.len(), file_swap);
+
+    let mut csv_writer_custom = HashMap::new();
+    for record in &custom_records {
+        if event_filter.keep_custom(record.as_ref())? {
+            write_custom_record(&mut csv_writer_custom, record.as_ref(), file_name)?;
+        }
+    }
+    event_filter.log_dropped();
+
+    let route_events = transform_route_event(&swap_events, &pairs)?;
+    let file_route = file_name("route");
+    PairsTableFile::new(file_route.to_str().unwrap())?.write_route_event(&route_events)?;
+    info!(
+        "Wrote {} Route events to {:?}.",
+        route_events.len(),
+        file_route
+    );
+
+    if liquidity.is_some() {
+        let file_liquidity = file_name("liquidity");
+        PairsTableFile::new(file_liquidity.to_str().unwrap())?
+            .write_liquidity_event(&liquidity_events)?;
+        info!(
+            "Wrote {} Liquidity events to {:?}.",
+            liquidity_events.len(),
+            file_liquidity
+        );
+    }
+
+    if positions.is_some() {
+        price_positions(&mut position_events, &liquidity_events);
+        let file_position = file_name("position");
+        PairsTableFile::new(file_position.to_str().unwrap())?
+            .write_position_event(&position_events)?;
+        info!(
+            "Wrote {} Position events to {:?}.",
+            position_events.len(),
+            file_position
+        );
+    }
+
+    write_batch_candles(config, chain_id, Some(file_name("candle")), &swap_events).await?;
+    write_batch_mev(
+        config,
+        chain_id,
+        file_name("mev"),
+        (&swap_events, &mint_events, &burn_events),
+        &logs,
+        &pairs,
+    )
+    .await?;
+
+    if let Some(mut fee_tracker) = fee_tracker(config, chain_id)? {
+        // Pairs whose PairCreated log is not in the files get the default fee.
+        for event in &pair_created_events {
+            fee_tracker.set_factory(event.pair_address, event.factory_address);
+        }
+        let fee_events = fee_tracker.track_batch(&swap_events, &liquidity_events);
+        write_fee_report(&fee_events, &file_name("fee"), &file_name("fee_ranking"))?;
+    }
+
+    if let Some(mut wallet_tracker) = wallet_tracker(config, chain_id)? {
+        let wallet_events = wallet_tracker.track_batch(&swap_events, &pairs)?;
+        write_wallet_report(
+            config,
+            &wallet_events,
+            &file_name("wallet"),
+            &file_name("top_traders"),
+        )?;
+    }
+
+    Ok(())
+}
+
+pub async fn subscribe_univ2_event(
+    config: &AppConfig,
+    registry: &TransformerRegistry,
+) -> Result<()> {
+    let evm_block = EvmBlock::new(&config.eth.ws_url).await?;
+    evm_block.ensure_chain_id(config.eth.chain_id)?;
+    let chain_id = evm_block.chain_id;
+    let output_dir = PathBuf::from(&config.csv.output_dir);
+    let create_time = Local::now().format("%y%m%d").to_string();
+    let file_name = move |kind: &str| {
+        output_dir.join(format!(
+            "sub_univ2_{}_{}_{}.csv",
+            kind, chain_id, create_time
+        ))
+    };
+    let candles = connect_candles(config, chain_id, Some(file_name("candle")), false).await?;
+    let mev_sink = connect_mev_sink(config, chain_id, Some(file_name("mev")), false).await?;
+    let subscription = LiveSubscription::connect(
+        config,
+        registry,
+        evm_block,
+        CsvEventSink::new(file_name),
+        candles,
+        mev_sink,
+    )
+    .await?;
+    info!("Listening for Mint, Burn, and Swap events...");
+    subscription.run().await
+}
+
+pub async fn subscribe_univ2_event_db(
+    config: &AppConfig,
+    registry: &TransformerRegistry,
+) -> Result<()> {
+    let evm_block = EvmBlock::new(&config.eth.ws_url).await?;
+    evm_block.ensure_chain_id(config.eth.chain_id)?;
+    let chain_id = evm_block.chain_id;
+    let tsdb = PairsTableTsdb::new(&config.tsdb.auth_token);
+    let candles = connect_candles(config, chain_id, None, true).await?;
+    let mev_sink = connect_mev_sink(config, chain_id, None, true).await?;
+    let subscription = LiveSubscription::connect(
+        config,
+        registry,
+        evm_block,
+        TsdbEventSink::new(tsdb, &config.tsdb.write_url),
+        candles,
+        mev_sink,
+    )
+    .await?;
+    info!("Starting TSDB event subscription...");
+    subscription.run().await
+}
+
+/// State of `subscribe_univ2_event` and `subscribe_univ2_event_db`, which only differ in
+/// `sink` and in where candles and MEV flags go.
+struct LiveSubscription<'a, S> {
+    chain_id: u64,
+    registry: &'a TransformerRegistry,
+    signatures: Vec<B256>,
+    multi_pair: UniswapV2MultiPair,
+    // Configured pairs; the others are reference pairs that only keep prices current.
+    watched: HashSet<Address>,
+    usd_pricer: Option<UsdPricer>,
+    intent_enricher: Option<SwapIntentEnricher>,
+    price_impact: Option<PriceImpactTracker>,
+    liquidity: Option<LiquidityTracker>,
+    fee_tracker: Option<FeeTracker>,
+    position_tracker: Option<PositionTracker>,
+    wallet_tracker: Option<WalletTracker>,
+    candles: Option<(CandleAggregator, CandleSink)>,
+    mev: Option<(MevBlockDetector, MevSink)>,
+    // Swaps of the current block, turned into routes once the next block starts.
+    block_swaps: Vec<SwapEvent>,
+    event_filter: EventFilter,
+    sink: S,
+}
+
+impl<'a, S: EventSink> LiveSubscription<'a, S> {
+    async fn connect(
+        config: &AppConfig,
+        registry: &'a TransformerRegistry,
+        evm_block: EvmBlock,
+        sink: S,
+        candles: Option<(CandleAggregator, CandleSink)>,
+        mev_sink: Option<MevSink>,
+    ) -> Result<Self> {
+        let chain_id = evm_block.chain_id;
+        let provider = evm_block.provider;
+        let pair_addresses = config
+            .uniswap_v2
+            .pair_address
+            .as_ref()
+            .ok_or_else(|| eyre::eyre!("Missing pair addresses in config"))?
+            .iter()
+            .map(|s| Address::from_str(s))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let intent_enricher = config
+            .router_intent_enabled()
+            .then(|| SwapIntentEnricher::new(provider.clone()));
+        let watched = pair_addresses.iter().copied().collect::<HashSet<_>>();
+        let (usd_pricer, pair_addresses) =
+            connect_usd_pricer(config, provider.clone(), pair_addresses).await?;
+        let multi_pair = UniswapV2MultiPair::new(provider, pair_addresses).await?;
+        let liquidity = connect_liquidity(config, chain_id, &multi_pair, &watched).await?;
+        let fee_tracker =
+            connect_fee_tracker(config, chain_id, multi_pair.provider.clone(), &watched).await?;
+        let price_impact = price_impact_tracker(config);
+        let mev = match mev_sink {
+            Some(sink) => Some((MevBlockDetector::new(router_addresses(config)?), sink)),
+            None => None,
+        };
+        Ok(Self {
+            chain_id,
+            registry,
+            signatures: event_signatures(config, registry, price_impact.is_some()),
+            multi_pair,
+            watched,
+            usd_pricer,
+            intent_enricher,
+            price_impact,
+            liquidity,
+            fee_tracker,
+            position_tracker: position_tracker(config, chain_id),
+            wallet_tracker: wallet_tracker(config, chain_id)?,
+            candles,
+            mev,
+            block_swaps: Vec::new(),
+            event_filter: event_filter(config)?,
+            sink,
+        })
+    }
+
+    async fn run(mut self) -> Result<()> {
+        let mut stream = self
+            .multi_pair
+            .subscribe_all_events(self.signatures.clone(), self.liquidity.is_some())
+            .await?;
+        let mut snapshot_ticker = tokio::time::interval(LIQUIDITY_SNAPSHOT_TICK);
+        let ticking =
+            self.liquidity.is_some() || self.candles.is_some() || self.wallet_tracker.is_some();
+        loop {
+            tokio::select! {
+                log = stream.next() => match log {
+                    Some(log) => self.handle_log(log).await?,
+                    None => break,
+                },
+                _ = snapshot_ticker.tick(), if ticking => {
+                    self.tick(Utc::now().timestamp() as u64).await?;
+                }
+            }
+        }
+        self.flush().await
+    }
+
+    // Ended candle windows, closed wallet days and due liquidity snapshots at `now`.
+    async fn tick(&mut self, now: u64) -> Result<()> {
+        let pairs = &self.multi_pair.pairs;
+        if let Some((aggregator, sink)) = self.candles.as_mut() {
+            sink.write(&aggregator.close_expired(now)).await?;
+        }
+        if let Some(wallet_tracker) = self.wallet_tracker.as_mut() {
+            let wallet_events = wallet_tracker.close_expired(now, pairs)?;
+            self.sink.write("wallet", &wallet_events).await?;
+        }
+        if let Some(tracker) = self.liquidity.as_mut() {
+            let mut events = tracker.advance_to(pairs, now);
+            price_liquidity_events(self.usd_pricer.as_ref(), &mut events, pairs);
+            self.sink.write("liquidity", &events).await?;
+            if let Some(fee_tracker) = self.fee_tracker.as_mut() {
+                self.sink
+                    .write("fee", &fee_tracker.add_liquidity(&events))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_log(&mut self, log: Log) -> Result<()> {
+        if log.topics().is_empty() {
+            return Ok(());
+        }
+        let pairs = &self.multi_pair.pairs;
+        let pair_address = log.address();
+        if log.removed {
+            return remove_reorged_log(
+                &log,
+                pairs,
+                self.chain_id,
+                self.candles.as_mut(),
+                self.liquidity.as_mut(),
+            )
+            .await;
+        }
+        if !self.watched.contains(&pair_address) {
+            if let Some(usd_pricer) = self.usd_pricer.as_mut() {
+                observe_reference_log(usd_pricer, &log, pairs, self.chain_id)?;
+            }
+            return Ok(());
+        }
+        if let (Some(tracker), Some(pair_info)) =
+            (self.liquidity.as_mut(), pairs.get(&pair_address))
+        {
+            let mut events = tracker.apply_log(&log, pair_info)?;
+            price_liquidity_events(self.usd_pricer.as_ref(), &mut events, pairs);
+            self.sink.write("liquidity", &events).await?;
+            if let Some(fee_tracker) = self.fee_tracker.as_mut() {
+                self.sink
+                    .write("fee", &fee_tracker.add_liquidity(&events))
+                    .await?;
+            }
+            if let Some(positions) = self.position_tracker.as_mut() {
+                positions.update_pool(&events);
+                let position_events = positions.apply_log(&log, pair_info)?;
+                self.sink.write("position", &position_events).await?;
+            }
+        }
+        if let (Some((detector, sink)), Some(block_number)) = (self.mev.as_mut(), log.block_number)
+        {
+            sink.write(&detector.start_block(block_number, pairs)?)
+                .await?;
+            detector.observe_log(&log)?;
+        }
+        if self
+            .block_swaps
+            .first()
+            .is_some_and(|swap| Some(swap.block_number) != log.block_number)
+        {
+            self.write_routes().await?;
+        }
+
+        if let Some(tracker) = self.price_impact.as_mut() {
+            tracker.observe_log(&log)?;
+        }
+        let records = self
+            .registry
+            .transform(&log, self.chain_id, &self.multi_pair.pairs)?;
+        for record in records {
+            self.handle_record(record, pair_address).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_record(&mut self, record: EventRecord, pair_address: Address) -> Result<()> {
+        let pairs = &self.multi_pair.pairs;
+        match record {
+            EventRecord::Mint(mut event) => {
+                if let (Some(usd_pricer), Some(pair_info)) =
+                    (self.usd_pricer.as_ref(), pairs.get(&pair_address))
+                {
+                    usd_pricer.price_mint(&mut event, pair_info);
+                }
+                if self.event_filter.keep("mint", &event)? {
+                    self.sink
+                        .write("mint", std::slice::from_ref(&event))
+                        .await?;
+                }
+                if let Some((detector, _)) = self.mev.as_mut() {
+                    detector.add_mint(&event);
+                }
+            }
+            EventRecord::Burn(mut event) => {
+                if let (Some(usd_pricer), Some(pair_info)) =
+                    (self.usd_pricer.as_ref(), pairs.get(&pair_address))
+                {
+                    usd_pricer.price_burn(&mut event, pair_info);
+                }
+                if self.event_filter.keep("burn", &event)? {
+                    self.sink
+                        .write("burn", std::slice::from_ref(&event))
+                        .await?;
+                }
+                if let Some((detector, _)) = self.mev.as_mut() {
+                    detector.add_burn(&event);
+                }
+            }
+            EventRecord::Swap(event) => {
+                let Some(pair_info) = pairs.get(&pair_address) else {
+                    return Ok(());
+                };
+                let mut events = vec![*event];
+                if let Some(enricher) = self.intent_enricher.as_mut() {
+                    enricher.enrich(&mut events).await?;
+                }
+                let mut event = events.remove(0);
+                if let Some(usd_pricer) = self.usd_pricer.as_mut() {
+                    usd_pricer.price_swap(&mut event, pair_info);
+                }
+                if let Some(tracker) = self.price_impact.as_ref() {
+                    tracker.apply(&mut event, pair_info);
+                }
+                if self.event_filter.keep("swap", &event)? {
+                    debug!(
+                        "Swap event of pair {}: {}",
+                        pair_address,
+                        event.to_influx_line()
+                    );
+                    self.sink
+                        .write("swap", std::slice::from_ref(&event))
+                        .await?;
+                }
+                if let Some((aggregator, sink)) = self.candles.as_mut() {
+                    sink.write(&aggregator.add_swap(&event)).await?;
+                }
+                if let Some(fee_tracker) = self.fee_tracker.as_mut() {
+                    self.sink
+                        .write("fee", &fee_tracker.add_swap(&event))
+                        .await?;
+                }
+                if let Some((detector, _)) = self.mev.as_mut() {
+                    detector.add_swap(&event);
+                }
+                if let Some(wallet_tracker) = self.wallet_tracker.as_mut() {
+                    let wallet_events = wallet_tracker.add_swap(&event, pairs)?;
+                    self.sink.write("wallet", &wallet_events).await?;
+                }
+                self.block_swaps.push(event);
+            }
+            EventRecord::Custom(record) => {
+                if self.event_filter.keep_custom(record.as_ref())? {
+                    self.sink.write_custom(record.as_ref()).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn write_routes(&mut self) -> Result<()> {
+        let route_events = transform_route_event(&self.block_swaps, &self.multi_pair.pairs)?;
+        self.sink.write("route", &route_events).await?;
+        self.block_swaps.clear();
+        Ok(())
+    }
+
+    // The stream ended inside a block; its swaps never saw a following log, and open
+    // candle windows never reach their end.
+    async fn flush(&mut self) -> Result<()> {
+        self.write_routes().await?;
+        if let Some((aggregator, sink)) = self.candles.as_mut() {
+            sink.write(&aggregator.flush()).await?;
+        }
+        let pairs = &self.multi_pair.pairs;
+        if let Some((detector, sink)) = self.mev.as_mut() {
+            sink.write(&detector.flush(pairs)?).await?;
+        }
+        if let Some(wallet_tracker) = self.wallet_tracker.as_mut() {
+            let wallet_events = wallet_tracker.flush(pairs)?;
+            self.sink.write("wallet", &wallet_events).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the USD pricer when `[pricing]` is configured and adds its reference pairs to
+/// the subscribed pairs, so their swaps keep prices current without being written.
+async fn connect_usd_pricer(
+    config: &AppConfig,
+    provider: DynProvider,
+    mut pair_addresses: Vec<Address>,
+) -> Result<(Option<UsdPricer>, Vec<Address>)> {
+    let Some(pricing) = &config.pricing else {
+        return Ok((None, pair_addresses));
+    };
+    let usd_pricer = UsdPricer::connect(pricing, provider, BlockId::latest()).await?;
+    for reference_pair in usd_pricer.reference_pairs.keys() {
+        if !pair_addresses.contains(reference_pair) {
+            pair_addresses.push(*reference_pair);
+        }
+    }
+    Ok((Some(usd_pricer), pair_addresses))
+}
+
+fn observe_reference_log(
+    usd_pricer: &mut UsdPricer,
+    log: &Log,
+    pairs: &HashMap<Address, UniswapV2TokenPair>,
+    chain_id: u64,
+) -> Result<()> {
+    if log.topics().first() != Some(&SWAP_EVENT_SIGNATURE) {
+        return Ok(());
+    }
+    if let Some(pair_info) = pairs.get(&log.address()) {
+        let mut swap_events = transform_swap_event(
+            std::slice::from_ref(log),
+            chain_id,
+            pair_info.token0.decimals,
+            pair_info.token1.decimals,
+        )?;
+        usd_pricer.price_swap(&mut swap_events[0], pair_info);
+    }
+    Ok(())
+}
+
+/// Candle aggregator and sink when `[candles]` is configured. `csv_file` and
+/// `native_tsdb` are the command's own outputs; `[candles]` may add TSDB and MQ.
+async fn connect_candles(
+    config: &AppConfig,
+    chain_id: u64,
+    csv_file: Option<PathBuf>,
+    native_tsdb: bool,
+) -> Result<Option<(CandleAggregator, CandleSink)>> {
+    let Some(candle_cfg) = &config.candles else {
+        return Ok(None);
+    };
+    let aggregator =
+        CandleAggregator::new(chain_id, &candle_cfg.intervals, candle_cfg.late_window_secs)?;
+
+    let csv = match csv_file {
+        Some(file) => Some(PairsTableFile::new(file.to_str().unwrap())?),
+        None => None,
+    };
+    let tsdb = (native_tsdb || candle_cfg.tsdb).then(|| {
+        (
+            PairsTableTsdb::new(&config.tsdb.auth_token),
+            config.tsdb.write_url.clone(),
+        )
+    });
+    let mq = if candle_cfg.mq {
+        Some(connect_topic(config, format!("uniswap-v2-candle-{}", chain_id)).await?)
+    } else {
+        None
+    };
+    Ok(Some((aggregator, CandleSink { csv, tsdb, mq })))
+}
+
+async fn connect_topic(config: &AppConfig, topic_name: String) -> Result<(Mq, String)> {
+    let client = Mq::new(&config.mq.broker_url).await?;
+    // topic name may only include lowercase letters (a-z), numbers (0-9), and hyphens (-).
+    if !client.list_topics().await?.contains(&topic_name) {
+        info!("Topic '{}' does not exist, creating it.", topic_name);
+        client.create_topic(&topic_name).await?;
+    }
+    Ok((client, topic_name))
+}
+
+/// MEV sink when `[mev]` is configured, like `connect_candles`.
+async fn connect_mev_sink(
+    config: &AppConfig,
+    chain_id: u64,
+    csv_file: Option<PathBuf>,
+    native_tsdb: bool,
+) -> Result<Option<MevSink>> {
+    let Some(mev_cfg) = &config.mev else {
+        return Ok(None);
+    };
+    let csv = match csv_file {
+        Some(file) => Some(PairsTableFile::new(file.to_str().unwrap())?),
+        None => None,
+    };
+    let tsdb = native_tsdb.then(|| {
+        (
+            PairsTableTsdb::new(&config.tsdb.auth_token),
+            config.tsdb.write_url.clone(),
+        )
+    });
+    let mq = if mev_cfg.mq {
+        Some(connect_topic(config, format!("uniswap-v2-mev-{}", chain_id)).await?)
+    } else {
+        None
+    };
+    Ok(Some(MevSink { csv, tsdb, mq }))
+}
+
+async fn write_batch_mev(
+    config: &AppConfig,
+    chain_id: u64,
+    csv_file: PathBuf,
+    (swaps, mints, burns): (&[SwapEvent], &[MintEvent], &[BurnEvent]),
+    logs: &[Log],
+    pairs: &HashMap<Address, UniswapV2TokenPair>,
+) -> Result<()> {
+    let Some(mut sink) = connect_mev_sink(config, chain_id, Some(csv_file), false).await? else {
+        return Ok(());
+    };
+    let mut providers = LpProviders::new(router_addresses(config)?);
+    for log in logs {
+        providers.observe_log(log)?;
+    }
+    let events = detect_mev(swaps, mints, burns, &providers, pairs)?;
+    sink.write(&events).await?;
+    info!("Wrote {} MEV events.", events.len());
+    Ok(())
+}
+
+async fn write_batch_candles(
+    config: &AppConfig,
+    chain_id: u64,
+    csv_file: Option<PathBuf>,
+    swaps: &[SwapEvent],
+) -> Result<()> {
+    let Some((mut aggregator, mut sink)) =
+        connect_candles(config, chain_id, csv_file, false).await?
+    else {
+        return Ok(());
+    };
+    let mut ordered = swaps.iter().collect::<Vec<_>>();
+    ordered.sort_by_key(|swap| swap.block_number);
+    let mut candles = Vec::new();
+    for swap in ordered {
+        candles.extend(aggregator.add_swap(swap));
+    }
+    candles.extend(aggregator.flush());
+    sink.write(&candles).await?;
+    info!("Wrote {} Candle events.", candles.len());
+    Ok(())
+}
+
+/// Logs removed by a reorg are not stored; a removed swap is taken back out of its candles
+/// and a removed LP mint or burn out of the tracked supply.
+async fn remove_reorged_log(
+    log: &Log,
+    pairs: &HashMap<Address, UniswapV2TokenPair>,
+    chain_id: u64,
+    candles: Option<&mut (CandleAggregator, CandleSink)>,
+    liquidity: Option<&mut LiquidityTracker>,
+) -> Result<()> {
+    info!(
+        "Skipping log removed by reorg: tx {:?} in block {:?}",
+        log.transaction_hash, log.block_number
+    );
+    if let Some(tracker) = liquidity {
+        tracker.remove_log(log)?;
+    }
+    let (Some((aggregator, sink)), Some(pair_info)) = (candles, pairs.get(&log.address())) else {
+        return Ok(());
+    };
+    if log.topics().first() == Some(&SWAP_EVENT_SIGNATURE) {
+        let swap_events = transform_swap_event(
+            std::slice::from_ref(log),
+            chain_id,
+            pair_info.token0.decimals,
+            pair_info.token1.decimals,
+        )?;
+        sink.write(&aggregator.remove_swap(&swap_events[0])).await?;
+    }
+    Ok(())
+}
+
+// Records of custom transformers go to one file per event type.
+fn write_custom_record(
+    writers: &mut HashMap<String, PairsTableFile>,
+    record: &dyn CustomRecord,
+    file_name: impl Fn(&str) -> PathBuf,
+) -> Result<()> {
+    let event_type = record.event_type();
+    if !writers.contains_key(event_type) {
+        let file = file_name(event_type);
+        writers.insert(
+            event_type.to_string(),
+            PairsTableFile::new(file.to_str().unwrap())?,
+        );
+    }
+    writers
+        .get_mut(event_type)
+        .unwrap()
+        .write_custom_record(record)
+}
+
+// How often live subscriptions emit due liquidity snapshots, ended candles and closed wallet
+// days, about one block.
+const LIQUIDITY_SNAPSHOT_TICK: Duration = Duration::from_secs(15);
+
+// `[fees]` and `[positions]` need the pool state, so they turn on liquidity tracking as well.
+fn liquidity_tracker(config: &AppConfig, chain_id: u64) -> Result<Option<LiquidityTracker>> {
+    if config.liquidity.is_none() && config.fees.is_none() && config.positions.is_none() {
+        return Ok(None);
+    }
+    let snapshot_secs = config
+        .liquidity
+        .as_ref()
+        .and_then(|liquidity| liquidity.snapshot_interval.as_deref())
+        .map(parse_interval)
+        .transpose()?;
+    Ok(Some(LiquidityTracker::new(chain_id, snapshot_secs)))
+}
+
+/// Liquidity tracker for live subscriptions, seeded with the current state of the watched pairs.
+async fn connect_liquidity(
+    config: &AppConfig,
+    chain_id: u64,
+    multi_pair: &UniswapV2MultiPair,
+    watched: &HashSet<Address>,
+) -> Result<Option<LiquidityTracker>> {
+    let Some(mut tracker) = liquidity_tracker(config, chain_id)? else {
+        return Ok(None);
+    };
+    let block_number = multi_pair.provider.get_block_number().await?;
+    for pair_address in watched {
+        let state = multi_pair
+            .get_liquidity_state(*pair_address, Some(block_number))
+            .await?;
+        tracker.seed(*pair_address, state, block_number);
+    }
+    Ok(Some(tracker))
+}
+
+fn price_liquidity_events(
+    usd_pricer: Option<&UsdPricer>,
+    events: &mut [LiquidityEvent],
+    pairs: &HashMap<Address, UniswapV2TokenPair>,
+) {
+    let Some(usd_pricer) = usd_pricer else {
+        return;
+    };
+    for event in events {
+        if let Some(pair_info) = pairs.get(&event.pair_address) {
+            usd_pricer.price_reserves(event, pair_info);
+        }
+    }
+}
+
+fn event_filter(config: &AppConfig) -> Result<EventFilter> {
+    EventFilter::new(
+        &config
+            .filter
+            .as_ref()
+            .map(|filter| filter.expressions.clone())
+            .unwrap_or_default(),
+    )
+}
+
+fn price_impact_tracker(config: &AppConfig) -> Option<PriceImpactTracker> {
+    config
+        .price_impact_enabled()
+        .then(PriceImpactTracker::default)
+}
+
+// Price impact needs the Sync emitted right before each Swap.
+// MEV detection takes JIT liquidity providers from the LP Transfer logs.
+fn event_signatures(
+    config: &AppConfig,
+    registry: &TransformerRegistry,
+    price_impact: bool,
+) -> Vec<B256> {
+    let mut signatures = registry.signatures();
+    if price_impact && !signatures.contains(&SYNC_EVENT_SIGNATURE) {
+        signatures.push(SYNC_EVENT_SIGNATURE);
+    }
+    if config.mev.is_some() && !signatures.contains(&TRANSFER_EVENT_SIGNATURE) {
+        signatures.push(TRANSFER_EVENT_SIGNATURE);
+    }
+    signatures
+}
+
+fn position_tracker(config: &AppConfig, chain_id: u64) -> Option<PositionTracker> {
+    config
+        .positions
+        .as_ref()
+        .map(|positions| PositionTracker::new(chain_id, positions.follow_transfers))
+}
+
+fn fee_tracker(config: &AppConfig, chain_id: u64) -> Result<Option<FeeTracker>> {
+    config
+        .fees
+        .as_ref()
+        .map(|fees| FeeTracker::new(chain_id, fees))
+        .transpose()
+}
+
+/// Fee tracker for live subscriptions; watched pairs are taken to come from the router's factory.
+async fn connect_fee_tracker(
+    config: &AppConfig,
+    chain_id: u64,
+    provider: DynProvider,
+    watched: &HashSet<Address>,
+) -> Result<Option<FeeTracker>> {
+    let Some(mut tracker) = fee_tracker(config, chain_id)? else {
+        return Ok(None);
+    };
+    let router_address = Address::from_str(&config.uniswap_v2.router_address)?;
+    let uniswap_v2 = UniswapV2::new(provider, router_address).await;
+    for pair_address in watched {
+        tracker.set_factory(*pair_address, *uniswap_v2.factory_caller.address());
+    }
+    Ok(Some(tracker))
+}
+
+fn write_fee_report(events: &[FeeEvent], file_fee: &Path, file_ranking: &Path) -> Result<()> {
+    PairsTableFile::new(file_fee.to_str().unwrap())?.write_fee_event(events)?;
+    info!("Wrote {} Fee events to {:?}.", events.len(), file_fee);
+    let ranking = rank_pairs(events);
+    PairsTableFile::new(file_ranking.to_str().unwrap())?.write_fee_event(&ranking)?;
+    info!(
+        "Wrote {} ranked pairs to {:?}.",
+        ranking.len(),
+        file_ranking
+    );
+    Ok(())
+}
+
+/// The configured router and the watched ones.
+fn router_addresses(config: &AppConfig) -> Result<HashSet<Address>> {
+    Ok(std::iter::once(&config.uniswap_v2.router_address)
+        .chain(config.uniswap_v2.watch_router_address.iter().flatten())
+        .map(|s| Address::from_str(s))
+        .collect::<Result<HashSet<_>, _>>()?)
+}
+
+// Router calls are attributed to their receiver when the transaction sender is unknown.
+fn wallet_tracker(config: &AppConfig, chain_id: u64) -> Result<Option<WalletTracker>> {
+    if config.wallets.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(WalletTracker::new(
+        chain_id,
+        router_addresses(config)?,
+    )))
+}
+
+fn write_wallet_report(
+    config: &AppConfig,
+    events: &[WalletEvent],
+    file_wallet: &Path,
+    file_top: &Path,
+) -> Result<()> {
+    PairsTableFile::new(file_wallet.to_str().unwrap())?.write_wallet_event(events)?;
+    info!("Wrote {} Wallet events to {:?}.", events.len(), file_wallet);
+    let top_traders = config.wallets.as_ref().map_or(0, |w| w.top_traders);
+    let ranking = rank_traders(events, top_traders);
+    PairsTableFile::new(file_top.to_str().unwrap())?.write_wallet_event(&ranking)?;
+    info!("Wrote {} top traders to {:?}.", ranking.len(), file_top);
+    Ok(())
+}
+
+pub async fn subscribe_univ2_event_mq(config: &AppConfig) -> Result<()> {
+    let evm_block = EvmBlock::new(&config.eth.ws_url).await?;
+    evm_block.ensure_chain_id(config.eth.chain_id)?;
+    let chain_id = evm_block.chain_id;
+    let provider = evm_block.provider.clone();
+
+    let router_address = Address::from_str(&config.uniswap_v2.router_address)?;
+    let uniswap_v2 = UniswapV2::new(provider.clone(), router_address).await;
+
+    let mq = Mq::new(&config.mq.broker_url).await?;
+    // topic name may only include lowercase letters (a-z), numbers (0-9), and hyphens (-).
+    let topic_name = format!("uniswap-v2-pair-created-{}", chain_id);
+    let topic_name = topic_name.as_str();
+
+    let topics = mq.list_topics().await?;
+    if !topics.iter().any(|t| t == topic_name) {
+        info!("Topic '{}' does not exist, creating it.", topic_name);
+        mq.create_topic(topic_name).await?;
+        info!("Topic '{}' created.", topic_name);
+    } else {
+        info!("Topic '{}' already exists.", topic_name);
+    }
+
+    let mut event_filter = event_filter(config)?;
+    let mut stream = uniswap_v2.subscribe_pair_created().await?;
+
+    info!(
+        "Listening for PairCreated events to push to MQ topic '{}'...",
+        topic_name
+    );
+    let Some(risk) = &config.risk else {
+        while let Some(log) = stream.next().await {
+            if log.removed {
+                continue;
+            }
+            for event in transform_pair_created_event(&[log], chain_id)? {
+                push_pair_created(&mq, topic_name, &mut event_filter, &event).await?;
+            }
+        }
+        return Ok(());
+    };
+
+    // New pairs wait for their first buy, or `observe_blocks` blocks, before they're pushed.
+    let weth_address = uniswap_v2.router_caller.WETH().call().await?;
+    let mut analyzer = TokenRiskAnalyzer::new(provider, risk, weth_address)?;
+    let mut swaps = uniswap_v2.subscribe_swaps().await?;
+    let mut headers = evm_block.subscribe_block_header().await?;
+    loop {
+        tokio::select! {
+            log = stream.next() => {
+                let Some(log) = log else { break };
+                if log.removed {
+                    continue;
+                }
+                for event in transform_pair_created_event(&[log], chain_id)? {
+                    info!("Analyzing token risk of pair {}", event.pair_address);
+                    // A pair the node couldn't be asked about still goes out, unscored.
+                    if let Err(e) = analyzer.add_pair(event.clone()).await {
+                        warn!("Pushing pair {} unscored: {}", event.pair_address, e);
+                        push_pair_created(&mq, topic_name, &mut event_filter, &event).await?;
+                    }
+                }
+            }
+            log = swaps.next() => {
+                let Some(log) = log else { break };
+                if log.removed {
+                    continue;
+                }
+                if let Err(e) = analyzer.observe_swap(&log).await {
+                    warn!("Skipping swap of pair {}: {}", log.address(), e);
+                }
+            }
+            header = headers.next() => {
+                let Some(header) = header else { break };
+                for event in analyzer.ready(header.number) {
+                    push_pair_created(&mq, topic_name, &mut event_filter, &event).await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn push_pair_created(
+    mq: &Mq,
+    topic_name: &str,
+    event_filter: &mut EventFilter,
+    event: &PairCreatedEvent,
+) -> Result<()> {
+    if !event_filter.keep("create", event)? {
+        return Ok(());
+    }
+    let event_json = serde_json::to_string(event)?;
+    let key = format!(
+        "{}-{}-{}",
+        event.chain_id, event.transaction_hash, event.log_index
+    );
+    mq.produce_record(topic_name, Some(&key), &event_json)
+        .await?;
+    info!(
+        "Pushed PairCreated event for pair {} to topic '{}'",
+        event.pair_address, topic_name
+    );
+    debug!("Event data: {}", event_json);
+    Ok(())
+}
+
+pub async fn subscribe_univ2_pending(config: &AppConfig, sink: PendingSink) -> Result<()> {
+    let evm_block = EvmBlock::new(&config.eth.ws_url).await?;
+    evm_block.ensure_chain_id(config.eth.chain_id)?;
+    let chain_id = evm_block.chain_id;
+
+    let router_address = Address::from_str(&config.uniswap_v2.router_address)?;
+    let uniswap_v2 = UniswapV2::new(evm_block.provider.clone(), router_address).await;
+    let weth_address = uniswap_v2.router_caller.WETH().call().await?;
+
+    let routers = match &config.uniswap_v2.watch_router_address {
+        Some(addresses) => addresses
+            .iter()
+            .map(|s| Address::from_str(s))
+            .collect::<Result<HashSet<_>, _>>()?,
+        None => HashSet::from([router_address]),
+    };
+
+    // topic name may only include lowercase letters (a-z), numbers (0-9), and hyphens (-).
+    let topic_name = format!("uniswap-v2-pending-router-call-{}", chain_id);
+    let topic_name = topic_name.as_str();
+    let mut csv_writer = None;
+    let mut mq = None;
+    match sink {
+        PendingSink::Csv => {
+            let output_dir = Path::new(&config.csv.output_dir);
+            std::fs::create_dir_all(output_dir)?;
+            let create_time = Local::now().format("%y%m%d");
+            let file_pending = output_dir.join(format!(
+                "sub_univ2_pending_{}_{}.csv",
+                chain_id, create_time
+            ));
+            csv_writer = Some(PairsTableFile::new(file_pending.to_str().unwrap())?);
+        }
+        PendingSink::Mq => {
+            let client = Mq::new(&config.mq.broker_url).await?;
+            if !client.list_topics().await?.iter().any(|t| t == topic_name) {
+                info!("Topic '{}' does not exist, creating it.", topic_name);
+                client.create_topic(topic_name).await?;
+            }
+            mq = Some(client);
+        }
+    }
+
+    let mut event_filter = event_filter(config)?;
+    let mut stream = evm_block.subscribe_pending_transactions().await?;
+
+    info!(
+        "Listening for pending transactions to routers {:?} on chain {}...",
+        routers, chain_id
+    );
+    while let Some(tx) = stream.next().await {
+        if !tx.to().is_some_and(|to| routers.contains(&to)) {
+            continue;
+        }
+        let seen_at = Utc::now().timestamp() as u64;
+        let router_call = match transform_router_call(&tx, chain_id, weth_address, seen_at) {
+            Ok(Some(router_call)) => router_call,
+            Ok(None) => continue,
+            Err(e) => {
+                debug!("Skipping pending transaction: {}", e);
+                continue;
+            }
+        };
+
+        if !event_filter.keep("pending", &router_call)? {
+            continue;
+        }
+        if let Some(csv_writer) = csv_writer.as_mut() {
+            csv_writer.write_router_call_event(std::slice::from_ref(&router_call))?;
+        }
+        if let Some(mq) = mq.as_ref() {
+            let event_json = serde_json::to_string(&router_call)?;
+            let key = format!("{}-{}", chain_id, router_call.transaction_hash);
+            mq.produce_record(topic_name, Some(&key), &event_json)
+                .await?;
+        }
+        info!(
+            "Stored pending {} from {} (tx {})",
+            router_call.function_name, router_call.sender, router_call.transaction_hash
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract_fixture::load_fixture;
+    use crate::test_util::{USDC_WETH, usdc_weth};
+
+    // Output file of `kind` written by `transform_univ2_log` into `dir`.
+    fn read_output(dir: &Path, kind: &str) -> String {
+        let prefix = format!("file_univ2_{}_1_", kind);
+        let entry = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                let name = path.file_name().unwrap().to_str().unwrap();
+                name.starts_with(&prefix) && name.ends_with(".csv")
+            })
+            .unwrap();
+        std::fs::read_to_string(entry).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_transform_univ2_log() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("etl_evm_pipeline_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+
+        // The USDC/WETH logs of blocks 22921717-22921721 as recorded from mainnet.
+        let fixture = load_fixture(Path::new("data/fixtures/test_transform_pair_event.json"))?;
+        let (_, logs) = fixture
+            .iter()
+            .find(|(key, _)| key.starts_with("eth_getLogs") && key.contains("0x15dc1f5"))
+            .unwrap();
+        let log_file = dir.join("logs.json");
+        std::fs::write(&log_file, logs.to_string())?;
+        let metadata_file = dir.join("pairs.json");
+        write_pair_metadata(&metadata_file, &HashMap::from([(USDC_WETH, usdc_weth())]))?;
+
+        let config = AppConfig::from_file("data/etl_example.toml")?;
+        let registry = TransformerRegistry::new().with_token_metadata(true);
+        transform_univ2_log(
+            &config,
+            &registry,
+            &[log_file.to_str().unwrap().to_string()],
+            metadata_file.to_str(),
+            1,
+            &dir,
+        )
+        .await?;
+
+        let tx = "0x2935801a3d8630d33771278006148dd207684b1b6f16b36da7153442a5682ab4";
+        let swaps = read_output(&dir, "swap");
+        assert_eq!(swaps.lines().count(), 2);
+        assert!(swaps.contains(tx));
+        assert!(swaps.contains(",725.61656,0.290184712141262678,"));
+        assert!(swaps.contains("USDC/WETH"));
+        assert!(read_output(&dir, "route").contains(tx));
+        // One window per configured interval, priced from the swap itself.
+        assert_eq!(read_output(&dir, "candle").lines().count(), 5);
+        assert_eq!(read_output(&dir, "mint").lines().count(), 0);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! Factories shared by the unit tests of the transform modules.
+use crate::extract_event::{TokenInfo, UniswapV2TokenPair};
+use crate::transform_event::{SwapDirection, SwapEvent, to_exact_amount};
+use alloy::primitives::{Address, B256, U256, address};
+use alloy::rpc::types::eth::Log;
+use alloy::sol_types::SolEvent;
+use bigdecimal::BigDecimal;
+
+pub const USDC: Address = address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+pub const WETH: Address = address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+pub const USDC_WETH: Address = address!("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+pub const ROUTER: Address = address!("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D");
+
+pub fn token(address: Address, decimals: u8, symbol: &str) -> TokenInfo {
+    TokenInfo {
+        address,
+        decimals,
+        symbol: symbol.to_string(),
+        block_number: None,
+    }
+}
+
+pub fn pair(pair_address: Address, token0: TokenInfo, token1: TokenInfo) -> UniswapV2TokenPair {
+    UniswapV2TokenPair {
+        pair_address,
+        token0,
+        token1,
+    }
+}
+
+/// The Uniswap V2 USDC/WETH pair.
+pub fn usdc_weth() -> UniswapV2TokenPair {
+    pair(USDC_WETH, token(USDC, 6, "USDC"), token(WETH, 18, "WETH"))
+}
+
+// Whole tokens to the raw amount, through the decimal string so e.g. 0.4 stays exact.
+fn raw_amount(amount: f64, decimals: u8) -> U256 {
+    let whole: BigDecimal = amount.to_string().parse().unwrap();
+    let raw = whole * BigDecimal::from(10u64.pow(decimals as u32));
+    raw.with_scale(0).to_string().parse().unwrap()
+}
+
+/// Swap of `amount0` token0 against `amount1` token1 on `pair`, in whole tokens, paying
+/// token0 in when `token0_in`. It goes through the router in transaction 0x01 of block 1.
+pub fn swap(pair: &UniswapV2TokenPair, token0_in: bool, amount0: f64, amount1: f64) -> SwapEvent {
+    let amount0 = raw_amount(amount0, pair.token0.decimals);
+    let amount1 = raw_amount(amount1, pair.token1.decimals);
+    let (amount0_in, amount1_in, amount0_out, amount1_out) = if token0_in {
+        (amount0, U256::ZERO, U256::ZERO, amount1)
+    } else {
+        (U256::ZERO, amount1, amount0, U256::ZERO)
+    };
+    let (direction, amount_in, amount_out) =
+        SwapDirection::from_amounts(amount0_in, amount1_in, amount0_out, amount1_out);
+    SwapEvent {
+        chain_id: 1,
+        event_type: "Swap".to_string(),
+        caller_address: ROUTER,
+        pair_address: pair.pair_address,
+        receiver_address: ROUTER,
+        amount0_in,
+        amount1_in,
+        amount0_out,
+        amount1_out,
+        direction,
+        amount_in,
+        amount_out,
+        token0_amounts: to_exact_amount(amount0, pair.token0.decimals),
+        token1_amounts: to_exact_amount(amount1, pair.token1.decimals),
+        block_number: 1,
+        transaction_hash: "0x01".to_string(),
+        ..Default::default()
+    }
+}
+
+/// `event` logged first by `pair` in a transaction whose hash, like the block hash, ends in
+/// the block number.
+pub fn pair_log<E: SolEvent>(
+    pair: Address,
+    event: E,
+    block_number: u64,
+    block_timestamp: u64,
+) -> Log {
+    Log {
+        inner: alloy::primitives::Log {
+            address: pair,
+            data: event.encode_log_data(),
+        },
+        block_hash: Some(B256::with_last_byte(block_number as u8)),
+        block_number: Some(block_number),
+        block_timestamp: Some(block_timestamp),
+        transaction_hash: Some(B256::with_last_byte(block_number as u8)),
+        transaction_index: Some(0),
+        log_index: Some(0),
+        ..Default::default()
+    }
+}
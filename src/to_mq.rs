@@ -46,14 +46,21 @@ impl Mq {
         Ok(())
     }
 
-    pub async fn produce_record(&self, topic_name: &str, record: &str) -> Result<()> {
+    /// Sends `record` under `key`; records describing the same event share a key, so
+    /// consumers can drop the repeats a restart produces.
+    pub async fn produce_record(
+        &self,
+        topic_name: &str,
+        key: Option<&str>,
+        record: &str,
+    ) -> Result<()> {
         let producer = self
             .fluvio
             .topic_producer(topic_name)
             .await
             .map_err(|e| eyre::eyre!("Failed to create producer: {}", e))?;
         producer
-            .send(RecordKey::NULL, record)
+            .send(key.map_or(RecordKey::NULL, RecordKey::from), record)
             .await
             .map_err(|e| eyre::eyre!("Failed to send record: {}", e))?;
         producer
@@ -86,7 +93,7 @@ mod tests {
         debug!("topics: {:#?}", topics);
 
         let msg = format!("Hello World! - Time is {}", Local::now().to_rfc2822());
-        mq.produce_record("test-topic", &msg).await?;
+        mq.produce_record("test-topic", None, &msg).await?;
 
         Ok(())
     }
@@ -34,7 +34,12 @@ mod tests {
         let log_level = app_config.init_log().unwrap();
         info!("app_config: {:#?}", app_config);
 
-        let rpc_client = EvmBlock::new(&app_config.eth.http_url).await.unwrap();
+        let rpc_client = EvmBlock::new_with_fixture(
+            &app_config.eth.http_url,
+            std::path::Path::new("data/fixtures/test_transform_block.json"),
+        )
+        .await
+        .unwrap();
         let new_block_number = rpc_client.get_latest_block_number().await.unwrap();
         info!("get_latest_block_number: {:?}", new_block_number);
 
@@ -0,0 +1,425 @@
+use crate::transform_event::{SwapDirection, SwapEvent, decimal_to_f64, serialize_timestamp};
+use alloy::primitives::Address;
+use eyre::Result;
+use log::warn;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct CandleEvent {
+    pub chain_id: u64,
+    pub event_type: String,
+    pub pair_address: Address,
+    pub interval: String,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub open_time: u64,
+    // Prices are token1 per token0, decimals applied.
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume0: f64,
+    pub volume1: f64,
+    pub volume_usd: Option<f64>,
+    pub trade_count: u64,
+    pub first_block: u64,
+    pub last_block: u64,
+    /// Set when a late or removed swap changed a candle that was already emitted.
+    pub revision: u32,
+}
+
+impl CandleEvent {
+    pub fn to_influx_line(&self) -> String {
+        let volume_usd = self
+            .volume_usd
+            .map_or(String::new(), |volume| format!(",volume_usd={}", volume));
+        format!(
+            "candle,chain_id={},pair_address={},interval={} open={},high={},low={},close={},volume0={},volume1={}{},trade_count={}i,first_block={}i,last_block={}i,revision={}i {}",
+            self.chain_id,
+            self.pair_address,
+            self.interval,
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.volume0,
+            self.volume1,
+            volume_usd,
+            self.trade_count,
+            self.first_block,
+            self.last_block,
+            self.revision,
+            self.open_time
+        )
+    }
+}
+
+/// Parses `1m`, `5m`, `1h`, `1d` style intervals into seconds.
+pub fn parse_interval(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value.len().saturating_sub(1);
+    let (count, unit) = value.split_at(split);
+    let count = count
+        .parse::<u64>()
+        .map_err(|_| eyre::eyre!("Invalid candle interval '{}'", value))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(eyre::eyre!("Invalid candle interval '{}'", value)),
+    };
+    if count == 0 {
+        return Err(eyre::eyre!("Candle interval '{}' must be positive", value));
+    }
+    Ok(count * unit_secs)
+}
+
+#[derive(Debug, Clone)]
+struct Trade {
+    block_number: u64,
+    transaction_hash: String,
+    log_index: u64,
+    price: f64,
+    volume0: f64,
+    volume1: f64,
+    volume_usd: Option<f64>,
+}
+
+impl Trade {
+    fn from_swap(swap: &SwapEvent) -> Option<Self> {
+        if swap.direction == SwapDirection::Unknown {
+            return None;
+        }
+        Some(Self {
+            block_number: swap.block_number,
+            transaction_hash: swap.transaction_hash.clone(),
+            log_index: swap.log_index,
+            price: decimal_to_f64(&swap.token1_amounts) / decimal_to_f64(&swap.token0_amounts),
+            volume0: decimal_to_f64(&swap.token0_amounts),
+            volume1: decimal_to_f64(&swap.token1_amounts),
+            volume_usd: swap.amount_usd,
+        })
+    }
+
+    // A log is identified by its transaction and its index in the block.
+    fn same_swap(&self, other: &Trade) -> bool {
+        self.transaction_hash == other.transaction_hash && self.log_index == other.log_index
+    }
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    // Kept so late and removed swaps can rebuild the candle.
+    trades: Vec<Trade>,
+    emitted: bool,
+    revision: u32,
+}
+
+/// Streaming OHLCV aggregation of swaps, per pair and interval, aligned to block timestamps.
+///
+/// A window is emitted once a swap from a later window of the same pair arrives (or on
+/// `flush`). Windows are kept `late_window_secs` past their end: late swaps and swaps from
+/// reorged (removed) logs within that time re-emit the candle with a higher `revision`.
+#[derive(Debug)]
+pub struct CandleAggregator {
+    chain_id: u64,
+    intervals: Vec<(String, u64)>,
+    late_window_secs: u64,
+    // (pair, interval secs, window start) -> trades
+    windows: BTreeMap<(Address, u64, u64), Window>,
+    latest_timestamp: u64,
+}
+
+impl CandleAggregator {
+    pub fn new(chain_id: u64, intervals: &[String], late_window_secs: u64) -> Result<Self> {
+        let intervals = intervals
+            .iter()
+            .map(|name| Ok((name.clone(), parse_interval(name)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            chain_id,
+            intervals,
+            late_window_secs,
+            windows: BTreeMap::new(),
+            latest_timestamp: 0,
+        })
+    }
+
+    /// Adds a swap and returns the candles that closed or changed because of it.
+    pub fn add_swap(&mut self, swap: &SwapEvent) -> Vec<CandleEvent> {
+        let Some(trade) = Trade::from_swap(swap) else {
+            return Vec::new();
+        };
+        self.latest_timestamp = self.latest_timestamp.max(swap.block_timestamp);
+
+        let mut candles = Vec::new();
+        for (_, secs) in self.intervals.clone() {
+            let start = swap.block_timestamp - swap.block_timestamp % secs;
+            let key = (swap.pair_address, secs, start);
+            if !self.windows.contains_key(&key) && self.is_expired(secs, start) {
+                warn!(
+                    "Dropping swap {} for candle {} of pair {}: older than the late window",
+                    swap.transaction_hash, start, swap.pair_address
+                );
+                continue;
+            }
+            let window = self.windows.entry(key).or_default();
+            // Replayed or re-delivered logs must not count twice.
+            if window.trades.iter().any(|t| t.same_swap(&trade)) {
+                continue;
+            }
+            window.trades.push(trade.clone());
+            window
+                .trades
+                .sort_by_key(|trade| (trade.block_number, trade.log_index));
+            if window.emitted {
+                window.revision += 1;
+                candles.extend(self.build(key));
+            }
+            candles.extend(self.close_before(swap.pair_address, secs, start));
+        }
+        self.evict();
+        candles
+    }
+
+    /// Takes back a swap whose log was removed by a reorg.
+    pub fn remove_swap(&mut self, swap: &SwapEvent) -> Vec<CandleEvent> {
+        let Some(trade) = Trade::from_swap(swap) else {
+            return Vec::new();
+        };
+        let mut candles = Vec::new();
+        for (_, secs) in self.intervals.clone() {
+            let start = swap.block_timestamp - swap.block_timestamp % secs;
+            let key = (swap.pair_address, secs, start);
+            let Some(window) = self.windows.get_mut(&key) else {
+                continue;
+            };
+            let Some(index) = window.trades.iter().position(|t| t.same_swap(&trade)) else {
+                continue;
+            };
+            window.trades.remove(index);
+            if window.emitted {
+                window.revision += 1;
+                candles.extend(self.build(key));
+            }
+        }
+        candles
+    }
+
+    /// Emits every window not emitted yet, e.g. at the end of a batch.
+    pub fn flush(&mut self) -> Vec<CandleEvent> {
+        let keys = self
+            .windows
+            .iter()
+            .filter(|(_, window)| !window.emitted)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        keys.into_iter()
+            .filter_map(|key| {
+                self.windows.get_mut(&key).unwrap().emitted = true;
+                self.build(key)
+            })
+            .collect()
+    }
+
+    /// Emits windows that ended by `now` (wall clock, in live mode), so pairs without new
+    /// swaps still get their candles closed.
+    pub fn close_expired(&mut self, now: u64) -> Vec<CandleEvent> {
+        self.latest_timestamp = self.latest_timestamp.max(now);
+        let keys = self
+            .windows
+            .iter()
+            .filter(|((_, secs, start), window)| !window.emitted && start + secs <= now)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        let candles = keys
+            .into_iter()
+            .filter_map(|key| {
+                self.windows.get_mut(&key).unwrap().emitted = true;
+                self.build(key)
+            })
+            .collect();
+        self.evict();
+        candles
+    }
+
+    fn close_before(&mut self, pair_address: Address, secs: u64, start: u64) -> Vec<CandleEvent> {
+        let keys = self
+            .windows
+            .range((pair_address, secs, 0)..(pair_address, secs, start))
+            .filter(|(_, window)| !window.emitted)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        keys.into_iter()
+            .filter_map(|key| {
+                self.windows.get_mut(&key).unwrap().emitted = true;
+                self.build(key)
+            })
+            .collect()
+    }
+
+    fn is_expired(&self, secs: u64, start: u64) -> bool {
+        start + secs + self.late_window_secs < self.latest_timestamp
+    }
+
+    fn evict(&mut self) {
+        let expired = self
+            .windows
+            .iter()
+            .filter(|((_, secs, start), window)| window.emitted && self.is_expired(*secs, *start))
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        for key in expired {
+            self.windows.remove(&key);
+        }
+    }
+
+    // An emitted window that lost all its trades is re-emitted with zero trades.
+    fn build(&self, key: (Address, u64, u64)) -> Option<CandleEvent> {
+        let (pair_address, secs, open_time) = key;
+        let window = self.windows.get(&key)?;
+        let interval = self
+            .intervals
+            .iter()
+            .find(|(_, s)| *s == secs)
+            .map(|(name, _)| name.clone())?;
+        let trades = &window.trades;
+        let prices = trades.iter().map(|trade| trade.price);
+        Some(CandleEvent {
+            chain_id: self.chain_id,
+            event_type: "Candle".to_string(),
+            pair_address,
+            interval,
+            open_time,
+            open: trades.first().map_or(0.0, |trade| trade.price),
+            high: prices.clone().fold(0.0, f64::max),
+            low: if trades.is_empty() {
+                0.0
+            } else {
+                prices.fold(f64::INFINITY, f64::min)
+            },
+            close: trades.last().map_or(0.0, |trade| trade.price),
+            volume0: trades.iter().map(|trade| trade.volume0).sum(),
+            volume1: trades.iter().map(|trade| trade.volume1).sum(),
+            volume_usd: trades
+                .iter()
+                .map(|trade| trade.volume_usd)
+                .sum::<Option<f64>>(),
+            trade_count: trades.len() as u64,
+            first_block: trades.first().map_or(0, |trade| trade.block_number),
+            last_block: trades.last().map_or(0, |trade| trade.block_number),
+            revision: window.revision,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::usdc_weth;
+    use alloy::primitives::U256;
+
+    // 1 USDC for `price` WETH.
+    fn swap(block_number: u64, block_timestamp: u64, price: f64, amount_usd: f64) -> SwapEvent {
+        SwapEvent {
+            amount_in: U256::from(block_number),
+            amount_usd: Some(amount_usd),
+            block_number,
+            block_timestamp,
+            transaction_hash: format!("0x{:02x}", block_number),
+            ..crate::test_util::swap(&usdc_weth(), true, 1.0, price)
+        }
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("1m").unwrap(), 60);
+        assert_eq!(parse_interval("5m").unwrap(), 300);
+        assert_eq!(parse_interval("1h").unwrap(), 3600);
+        assert_eq!(parse_interval("1d").unwrap(), 86400);
+        assert!(parse_interval("0m").is_err());
+        assert!(parse_interval("1w").is_err());
+    }
+
+    #[test]
+    fn test_candle_aggregator() {
+        let mut aggregator = CandleAggregator::new(1, &["1m".to_string()], 120).unwrap();
+        // 2025-07-01 00:00:00 window
+        assert!(
+            aggregator
+                .add_swap(&swap(1, 1751328000, 10.0, 100.0))
+                .is_empty()
+        );
+        assert!(
+            aggregator
+                .add_swap(&swap(2, 1751328012, 12.0, 100.0))
+                .is_empty()
+        );
+        assert!(
+            aggregator
+                .add_swap(&swap(3, 1751328024, 9.0, 100.0))
+                .is_empty()
+        );
+        assert!(
+            aggregator
+                .add_swap(&swap(4, 1751328036, 11.0, 100.0))
+                .is_empty()
+        );
+
+        // Next window closes the first one
+        let candles = aggregator.add_swap(&swap(5, 1751328060, 11.5, 50.0));
+        assert_eq!(candles.len(), 1);
+        let candle = &candles[0];
+        assert_eq!(candle.open_time, 1751328000);
+        assert_eq!(
+            (candle.open, candle.high, candle.low, candle.close),
+            (10.0, 12.0, 9.0, 11.0)
+        );
+        assert_eq!(candle.trade_count, 4);
+        assert_eq!(candle.volume_usd, Some(400.0));
+        assert_eq!((candle.first_block, candle.last_block), (1, 4));
+        assert!(candle.to_influx_line().starts_with("candle,chain_id=1,"));
+
+        // Reorg drops block 3 from the closed window
+        let candles = aggregator.remove_swap(&swap(3, 1751328024, 9.0, 100.0));
+        assert_eq!(candles.len(), 1);
+        assert_eq!((candles[0].low, candles[0].trade_count), (10.0, 3));
+        assert_eq!(candles[0].revision, 1);
+
+        // Late swap still within the late window
+        let candles = aggregator.add_swap(&swap(3, 1751328024, 8.0, 100.0));
+        assert_eq!((candles[0].low, candles[0].revision), (8.0, 2));
+
+        // Far later swap evicts the old window, a late swap for it is then dropped
+        aggregator.add_swap(&swap(20, 1751328600, 11.0, 50.0));
+        assert!(
+            aggregator
+                .add_swap(&swap(6, 1751328030, 7.0, 1.0))
+                .is_empty()
+        );
+
+        // A re-delivered log is ignored; swaps within a block are ordered by log index.
+        let mut in_block = CandleAggregator::new(1, &["1m".to_string()], 120).unwrap();
+        let mut second = swap(30, 1751329200, 5.0, 10.0);
+        second.log_index = 7;
+        let mut first = swap(30, 1751329200, 4.0, 10.0);
+        first.log_index = 2;
+        in_block.add_swap(&second);
+        in_block.add_swap(&first);
+        in_block.add_swap(&second);
+        let candles = in_block.flush();
+        assert_eq!(
+            (candles[0].open, candles[0].close, candles[0].trade_count),
+            (4.0, 5.0, 2)
+        );
+
+        // A quiet pair's window is closed by the clock alone.
+        assert!(aggregator.close_expired(1751328659).is_empty());
+        let candles = aggregator.close_expired(1751328660);
+        assert_eq!(
+            candles.iter().map(|c| c.open_time).collect::<Vec<_>>(),
+            vec![1751328600]
+        );
+        assert!(aggregator.flush().is_empty());
+    }
+}
@@ -1,249 +1,707 @@
-use alloy::primitives::Address;
+use crate::extract_event::{TokenInfo, UniswapV2Factory, UniswapV2Pair, UniswapV2TokenPair};
+use alloy::primitives::{Address, U256};
 use alloy::rpc::types::eth::Log;
-use chrono::{DateTime, Utc};
+use alloy::sol_types::SolEvent;
+use bigdecimal::num_bigint::{BigInt, Sign};
+use bigdecimal::{BigDecimal, RoundingMode, ToPrimitive, Zero};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat, TimeZone, Utc};
 use eyre::Result;
+use std::str::FromStr;
+use std::sync::OnceLock;
 
-#[derive(Debug, serde::Serialize)]
+// Fractional digits written for exact decimal columns, see `set_decimal_scale`.
+static DECIMAL_SCALE: OnceLock<i64> = OnceLock::new();
+const DEFAULT_DECIMAL_SCALE: i64 = 18;
+// Formatting of timestamp columns, see `set_time_format`.
+static TIME_FORMAT: OnceLock<TimeFormat> = OnceLock::new();
+pub const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct PairCreatedEvent {
+    pub chain_id: u64,
     pub event_type: String,
     pub function_signature: String,
     pub token0_address: Address,
     pub token1_address: Address,
     pub block_number: u64,
+    pub block_hash: String,
     pub transaction_hash: String,
+    pub transaction_index: u64,
+    pub log_index: u64,
     pub factory_address: Address,
     pub pair_address: Address,
     #[serde(serialize_with = "serialize_timestamp")]
     pub block_timestamp: u64,
+    // Token risk of the new pair, see `TokenRiskAnalyzer`.
+    pub risk_score: Option<u8>,
+    pub risk_flags: Option<String>,
+    pub fee_on_transfer_bps: Option<u32>,
+    pub honeypot: Option<bool>,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct MintEvent {
+    pub chain_id: u64,
     pub event_type: String,
     pub function_signature: String,
     pub caller_address: Address,
     pub pair_address: Address,
-    pub token0_amount: u128,
-    pub token1_amount: u128,
+    // Token metadata of the pair, see `[enrich] token_metadata`.
+    pub pair_name: Option<String>,
+    pub token0_symbol: Option<String>,
+    pub token1_symbol: Option<String>,
+    pub token0_decimals: Option<u8>,
+    pub token1_decimals: Option<u8>,
+    #[serde(serialize_with = "serialize_u256")]
+    pub token0_amount: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub token1_amount: U256,
     pub block_number: u64,
+    pub block_hash: String,
     pub transaction_hash: String,
+    pub transaction_index: u64,
+    pub log_index: u64,
     #[serde(serialize_with = "serialize_timestamp")]
     pub block_timestamp: u64,
+    // USD valuation from reference pairs, see `UsdPricer`.
+    pub token0_price_usd: Option<f64>,
+    pub token1_price_usd: Option<f64>,
+    pub amount_usd: Option<f64>,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct BurnEvent {
+    pub chain_id: u64,
     pub event_type: String,
     pub function_signature: String,
     pub caller_address: Address,
     pub pair_address: Address,
+    // Token metadata of the pair, see `[enrich] token_metadata`.
+    pub pair_name: Option<String>,
+    pub token0_symbol: Option<String>,
+    pub token1_symbol: Option<String>,
+    pub token0_decimals: Option<u8>,
+    pub token1_decimals: Option<u8>,
     pub address: Address,
-    pub token0_amount: u128,
-    pub token1_amount: u128,
+    #[serde(serialize_with = "serialize_u256")]
+    pub token0_amount: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub token1_amount: U256,
     pub block_number: u64,
+    pub block_hash: String,
     pub transaction_hash: String,
+    pub transaction_index: u64,
+    pub log_index: u64,
     #[serde(serialize_with = "serialize_timestamp")]
     pub block_timestamp: u64,
+    // USD valuation from reference pairs, see `UsdPricer`.
+    pub token0_price_usd: Option<f64>,
+    pub token1_price_usd: Option<f64>,
+    pub amount_usd: Option<f64>,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct SwapEvent {
+    pub chain_id: u64,
     pub event_type: String,
     pub function_signature: String,
     pub caller_address: Address,
     pub pair_address: Address,
+    // Token metadata of the pair, see `[enrich] token_metadata`.
+    pub pair_name: Option<String>,
+    pub token0_symbol: Option<String>,
+    pub token1_symbol: Option<String>,
+    pub token0_decimals: Option<u8>,
+    pub token1_decimals: Option<u8>,
     pub receiver_address: Address,
-    pub token0_amount: u128,
-    pub token1_amount: u128,
-    pub token0_amounts: f64,
-    pub token1_amounts: f64,
-    pub token0_token1: f64,
-    pub token1_token0: f64,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount0_in: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount1_in: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount0_out: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount1_out: U256,
+    pub direction: SwapDirection,
+    // Net amounts of the trade: what the pair received and what it paid out.
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount_in: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount_out: U256,
+    #[serde(serialize_with = "serialize_decimal")]
+    pub token0_amounts: BigDecimal,
+    #[serde(serialize_with = "serialize_decimal")]
+    pub token1_amounts: BigDecimal,
+    /// `amount_out / amount_in` with decimals applied, 0 when the direction is unknown.
+    #[serde(serialize_with = "serialize_decimal")]
+    pub execution_price: BigDecimal,
+    // Ratios of the normalized amounts, 0 when the divisor is.
+    #[serde(serialize_with = "serialize_decimal")]
+    pub token0_token1: BigDecimal,
+    #[serde(serialize_with = "serialize_decimal")]
+    pub token1_token0: BigDecimal,
     pub block_number: u64,
+    pub block_hash: String,
     pub transaction_hash: String,
+    pub transaction_index: u64,
+    pub log_index: u64,
     #[serde(serialize_with = "serialize_timestamp")]
     pub block_timestamp: u64,
+    // USD valuation from reference pairs, see `UsdPricer`.
+    pub token0_price_usd: Option<f64>,
+    pub token1_price_usd: Option<f64>,
+    pub amount_usd: Option<f64>,
+    // Constant-product mid prices (token1 per token0) around the swap, see `PriceImpactTracker`.
+    pub mid_price_before: Option<f64>,
+    pub mid_price_after: Option<f64>,
+    pub price_impact_bps: Option<f64>,
+    pub slippage_bps: Option<f64>,
+    // Trade intent decoded from the originating router transaction, see `SwapIntentEnricher`.
+    pub tx_sender: Option<Address>,
+    pub trade_path: Option<String>,
+    #[serde(serialize_with = "serialize_opt_u256")]
+    pub amount_out_min: Option<U256>,
+    pub deadline: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub enum SwapDirection {
+    #[serde(rename = "token0->token1")]
+    Token0ToToken1,
+    #[serde(rename = "token1->token0")]
+    Token1ToToken0,
+    #[default]
+    #[serde(rename = "unknown")]
+    Unknown,
+}
+
+impl SwapDirection {
+    /// Direction and net (in, out) amounts from the four raw Swap amounts. Flash swaps may
+    /// report both in and out on one side, so only the net flow of each token counts.
+    pub fn from_amounts(
+        amount0_in: U256,
+        amount1_in: U256,
+        amount0_out: U256,
+        amount1_out: U256,
+    ) -> (Self, U256, U256) {
+        if amount0_in > amount0_out && amount1_out > amount1_in {
+            (
+                Self::Token0ToToken1,
+                amount0_in - amount0_out,
+                amount1_out - amount1_in,
+            )
+        } else if amount1_in > amount1_out && amount0_out > amount0_in {
+            (
+                Self::Token1ToToken0,
+                amount1_in - amount1_out,
+                amount0_out - amount0_in,
+            )
+        } else {
+            (Self::Unknown, U256::ZERO, U256::ZERO)
+        }
+    }
+}
+
+impl std::fmt::Display for SwapDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Token0ToToken1 => write!(f, "token0->token1"),
+            Self::Token1ToToken0 => write!(f, "token1->token0"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// How timestamp columns are written: `epoch_seconds`, `epoch_millis`, `rfc3339` or a
+/// strftime pattern, in `UTC`, `local` time or a fixed offset such as `+08:00`.
+#[derive(Debug, Clone)]
+pub struct TimeFormat {
+    style: TimeStyle,
+    timezone: TimeOffset,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TimeStyle {
+    EpochSeconds,
+    EpochMillis,
+    Rfc3339,
+    Pattern(String),
+}
+
+#[derive(Debug, Clone)]
+enum TimeOffset {
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl TimeFormat {
+    pub fn new(format: &str, timezone: &str) -> Result<Self> {
+        let style = match format {
+            "epoch_seconds" => TimeStyle::EpochSeconds,
+            "epoch_millis" => TimeStyle::EpochMillis,
+            "rfc3339" => TimeStyle::Rfc3339,
+            pattern => {
+                if StrftimeItems::new(pattern).any(|item| item == Item::Error) {
+                    eyre::bail!("Invalid time format: {}", pattern);
+                }
+                TimeStyle::Pattern(pattern.to_string())
+            }
+        };
+        let timezone = match timezone {
+            "local" => TimeOffset::Local,
+            "UTC" | "utc" | "Z" => TimeOffset::Fixed(FixedOffset::east_opt(0).unwrap()),
+            offset => TimeOffset::Fixed(
+                FixedOffset::from_str(offset)
+                    .map_err(|e| eyre::eyre!("Invalid timezone {}: {}", offset, e))?,
+            ),
+        };
+        Ok(Self { style, timezone })
+    }
+
+    pub fn format(&self, timestamp: u64) -> String {
+        let date_time = DateTime::<Utc>::from_timestamp(timestamp as i64, 0).unwrap();
+        match &self.timezone {
+            TimeOffset::Local => self.format_in(date_time.with_timezone(&Local), timestamp),
+            TimeOffset::Fixed(offset) => self.format_in(date_time.with_timezone(offset), timestamp),
+        }
+    }
+
+    fn format_in<Tz: TimeZone>(&self, date_time: DateTime<Tz>, timestamp: u64) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        match &self.style {
+            TimeStyle::EpochSeconds => timestamp.to_string(),
+            TimeStyle::EpochMillis => (timestamp * 1000).to_string(),
+            TimeStyle::Rfc3339 => date_time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            TimeStyle::Pattern(pattern) => date_time.format(pattern).to_string(),
+        }
+    }
+}
+
+impl Default for TimeFormat {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIME_FORMAT, "UTC").unwrap()
+    }
+}
+
+/// Sets the timestamp format of CSV and JSON output (`[output] time_format` and `timezone`).
+/// Only the first call takes effect; unset, UTC `%Y-%m-%d %H:%M:%S` is written.
+pub fn set_time_format(format: TimeFormat) {
+    let _ = TIME_FORMAT.set(format);
+}
+
+pub fn serialize_timestamp<S>(timestamp: &u64, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let format = TIME_FORMAT.get_or_init(TimeFormat::default);
+    // Epoch times stay numbers in JSON.
+    match format.style {
+        TimeStyle::EpochSeconds => serializer.serialize_u64(*timestamp),
+        TimeStyle::EpochMillis => serializer.serialize_u64(*timestamp * 1000),
+        _ => serializer.collect_str(&format.format(*timestamp)),
+    }
+}
+
+pub fn serialize_opt_u256<S>(value: &Option<U256>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match value {
+        Some(value) => serializer.collect_str(value),
+        None => serializer.serialize_none(),
+    }
 }
 
-fn serialize_timestamp<S>(timestamp: &u64, serializer: S) -> Result<S::Ok, S::Error>
+pub fn serialize_u256<S>(value: &U256, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
 {
-    let timestamp = DateTime::<Utc>::from_timestamp(*timestamp as i64, 0).unwrap();
-    let formatted = timestamp.format("%Y-%m-%d %H:%M:%S");
-    serializer.collect_str(&formatted)
+    serializer.collect_str(value)
+}
+
+pub fn serialize_decimal<S>(value: &BigDecimal, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_str(&format_decimal(value))
+}
+
+/// Sets the fractional digits of exact decimal output (`[output] decimal_scale`). Only the
+/// first call takes effect; unset, 18 digits are written.
+pub fn set_decimal_scale(scale: i64) {
+    let _ = DECIMAL_SCALE.set(scale);
+}
+
+/// Plain notation rounded half-even to the output scale, without trailing zeros.
+pub fn format_decimal(value: &BigDecimal) -> String {
+    let scale = *DECIMAL_SCALE.get().unwrap_or(&DEFAULT_DECIMAL_SCALE);
+    value
+        .with_scale_round(scale, RoundingMode::HalfEven)
+        .normalized()
+        .to_plain_string()
+}
+
+/// Raw token amount scaled by `decimals`, for estimates such as USD values and analytics.
+pub fn to_decimal_amount(amount: U256, decimals: u8) -> f64 {
+    f64::from(amount) / 10f64.powi(decimals as i32)
+}
+
+/// Raw token amount scaled by `decimals` without rounding, for the `*_amounts` columns.
+pub fn to_exact_amount(amount: U256, decimals: u8) -> BigDecimal {
+    let digits = BigInt::from_bytes_le(Sign::Plus, &amount.to_le_bytes::<32>());
+    BigDecimal::new(digits, decimals as i64)
+}
+
+/// `numerator / denominator`, or 0 when the denominator is 0.
+pub fn decimal_ratio(numerator: &BigDecimal, denominator: &BigDecimal) -> BigDecimal {
+    if denominator.is_zero() {
+        BigDecimal::zero()
+    } else {
+        numerator / denominator
+    }
 }
+
+pub fn decimal_to_f64(value: &BigDecimal) -> f64 {
+    value.to_f64().unwrap_or_default()
+}
+
+pub fn decode_log<E: SolEvent>(log: &Log) -> Result<E> {
+    log.log_decode::<E>()
+        .map(|decoded| decoded.inner.data)
+        .map_err(|e| {
+            eyre::eyre!(
+                "Failed to decode {} log (tx {}, log index {}): {}",
+                E::SIGNATURE,
+                log.transaction_hash
+                    .map_or("unknown".to_string(), |hash| hash.to_string()),
+                log.log_index
+                    .map_or("unknown".to_string(), |index| index.to_string()),
+                e
+            )
+        })
+}
+
+// Logs without their position in the chain (e.g. of pending blocks) can't be keyed.
+fn log_field<T>(value: Option<T>, field: &str, log: &Log) -> Result<T> {
+    value.ok_or_else(|| {
+        eyre::eyre!(
+            "Missing {} of log (tx {})",
+            field,
+            log.transaction_hash
+                .map_or("unknown".to_string(), |hash| hash.to_string())
+        )
+    })
+}
+
+// Optional Influx fields, omitted while a price is unknown.
+fn usd_fields(
+    token0_price_usd: Option<f64>,
+    token1_price_usd: Option<f64>,
+    amount_usd: Option<f64>,
+) -> String {
+    [
+        ("token0_price_usd", token0_price_usd),
+        ("token1_price_usd", token1_price_usd),
+        ("amount_usd", amount_usd),
+    ]
+    .iter()
+    .filter_map(|(name, value)| value.map(|value| format!(",{}={}", name, value)))
+    .collect()
+}
+
+// Optional Influx tags, present once `set_token_metadata` labeled the event.
+fn token_tags(
+    pair_name: &Option<String>,
+    token0_symbol: &Option<String>,
+    token1_symbol: &Option<String>,
+    token0_decimals: Option<u8>,
+    token1_decimals: Option<u8>,
+) -> String {
+    [
+        ("pair_name", pair_name.clone()),
+        ("token0_symbol", token0_symbol.clone()),
+        ("token1_symbol", token1_symbol.clone()),
+        ("token0_decimals", token0_decimals.map(|d| d.to_string())),
+        ("token1_decimals", token1_decimals.map(|d| d.to_string())),
+    ]
+    .iter()
+    .filter_map(|(name, value)| {
+        value
+            .as_ref()
+            .map(|value| format!(",{}={}", name, escape_tag(value)))
+    })
+    .collect()
+}
+
+// Symbols are free text; commas, equals signs and spaces must be escaped in tag values.
+fn escape_tag(value: &str) -> String {
+    value
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+// Symbols of the pair's tokens and its `SYMBOL0/SYMBOL1` name; unknown symbols stay empty.
+fn pair_symbols(pair: &UniswapV2TokenPair) -> (Option<String>, Option<String>, Option<String>) {
+    let symbol = |token: &TokenInfo| (!token.symbol.is_empty()).then(|| token.symbol.clone());
+    let (token0_symbol, token1_symbol) = (symbol(&pair.token0), symbol(&pair.token1));
+    let pair_name = token0_symbol
+        .as_ref()
+        .zip(token1_symbol.as_ref())
+        .map(|(symbol0, symbol1)| format!("{}/{}", symbol0, symbol1));
+    (pair_name, token0_symbol, token1_symbol)
+}
+
 impl PairCreatedEvent {
     pub fn to_influx_line(&self) -> String {
+        let mut risk_fields = String::new();
+        if let Some(risk_score) = self.risk_score {
+            risk_fields.push_str(&format!(",risk_score={}i", risk_score));
+        }
+        if let Some(risk_flags) = &self.risk_flags {
+            risk_fields.push_str(&format!(",risk_flags=\"{}\"", risk_flags));
+        }
+        if let Some(fee_on_transfer_bps) = self.fee_on_transfer_bps {
+            risk_fields.push_str(&format!(",fee_on_transfer_bps={}i", fee_on_transfer_bps));
+        }
+        if let Some(honeypot) = self.honeypot {
+            risk_fields.push_str(&format!(",honeypot={}", honeypot));
+        }
         format!(
-                "create_event,transaction_hash={},event_type={},factory_address={},pair_adress={},token0={},token1={} block_number={} {}",
+                "create_event,chain_id={},transaction_hash={},log_index={},event_type={},factory_address={},pair_adress={},token0={},token1={} block_number={},block_hash=\"{}\",transaction_index={}{} {}",
+                self.chain_id,
                 self.transaction_hash,
+                self.log_index,
                 self.event_type,
                 self.factory_address,
                 self.pair_address,
                 self.token0_address,
                 self.token1_address,
                 self.block_number,
+                self.block_hash,
+                self.transaction_index,
+                risk_fields,
                 self.block_timestamp
             )
     }
 }
 
 impl MintEvent {
+    pub fn set_token_metadata(&mut self, pair: &UniswapV2TokenPair) {
+        (self.pair_name, self.token0_symbol, self.token1_symbol) = pair_symbols(pair);
+        self.token0_decimals = Some(pair.token0.decimals);
+        self.token1_decimals = Some(pair.token1.decimals);
+    }
+
     pub fn to_influx_line(&self) -> String {
         format!(
-                "mint_event,transaction_hash={},event_type={},caller_address={},pair_address={} amount0={},amount1={},block_number={} {}",
+                "mint_event,chain_id={},transaction_hash={},log_index={},event_type={},caller_address={},pair_address={}{} amount0=\"{}\",amount1=\"{}\",block_number={},block_hash=\"{}\",transaction_index={}{} {}",
+                self.chain_id,
                 self.transaction_hash,
+                self.log_index,
                 self.event_type,
                 self.caller_address,
                 self.pair_address,
+                token_tags(
+                    &self.pair_name,
+                    &self.token0_symbol,
+                    &self.token1_symbol,
+                    self.token0_decimals,
+                    self.token1_decimals
+                ),
                 self.token0_amount,
                 self.token1_amount,
                 self.block_number,
+                self.block_hash,
+                self.transaction_index,
+                usd_fields(self.token0_price_usd, self.token1_price_usd, self.amount_usd),
                 self.block_timestamp
             )
     }
 }
 
 impl BurnEvent {
+    pub fn set_token_metadata(&mut self, pair: &UniswapV2TokenPair) {
+        (self.pair_name, self.token0_symbol, self.token1_symbol) = pair_symbols(pair);
+        self.token0_decimals = Some(pair.token0.decimals);
+        self.token1_decimals = Some(pair.token1.decimals);
+    }
+
     pub fn to_influx_line(&self) -> String {
         format!(
-                "burn_event,transaction_hash={},event_type={},caller_address={},pair_address={} amount0={},amount1={},block_number={} {}",
+                "burn_event,chain_id={},transaction_hash={},log_index={},event_type={},caller_address={},pair_address={}{} amount0=\"{}\",amount1=\"{}\",block_number={},block_hash=\"{}\",transaction_index={}{} {}",
+                self.chain_id,
                 self.transaction_hash,
+                self.log_index,
                 self.event_type,
                 self.caller_address,
                 self.pair_address,
+                token_tags(
+                    &self.pair_name,
+                    &self.token0_symbol,
+                    &self.token1_symbol,
+                    self.token0_decimals,
+                    self.token1_decimals
+                ),
                 self.token0_amount,
                 self.token1_amount,
                 self.block_number,
+                self.block_hash,
+                self.transaction_index,
+                usd_fields(self.token0_price_usd, self.token1_price_usd, self.amount_usd),
                 self.block_timestamp
             )
     }
 }
 
 impl SwapEvent {
+    pub fn set_token_metadata(&mut self, pair: &UniswapV2TokenPair) {
+        (self.pair_name, self.token0_symbol, self.token1_symbol) = pair_symbols(pair);
+        self.token0_decimals = Some(pair.token0.decimals);
+        self.token1_decimals = Some(pair.token1.decimals);
+    }
+
     pub fn to_influx_line(&self) -> String {
-        format!("swap_event,pair_address={},caller_address={},receiver_address={},transaction_hash={} \
-                token0_amount={},token1_amount={},token0_amounts={},token1_amounts={},token0_token1={},token1_token0={},block_number={} {}",
+        let mut intent_fields =
+            usd_fields(self.token0_price_usd, self.token1_price_usd, self.amount_usd);
+        for (name, value) in [
+            ("mid_price_before", self.mid_price_before),
+            ("mid_price_after", self.mid_price_after),
+            ("price_impact_bps", self.price_impact_bps),
+            ("slippage_bps", self.slippage_bps),
+        ] {
+            if let Some(value) = value {
+                intent_fields.push_str(&format!(",{}={}", name, value));
+            }
+        }
+        if let Some(tx_sender) = self.tx_sender {
+            intent_fields.push_str(&format!(",tx_sender=\"{}\"", tx_sender));
+        }
+        if let Some(trade_path) = &self.trade_path {
+            intent_fields.push_str(&format!(",trade_path=\"{}\"", trade_path));
+        }
+        if let Some(amount_out_min) = self.amount_out_min {
+            intent_fields.push_str(&format!(",amount_out_min=\"{}\"", amount_out_min));
+        }
+        if let Some(deadline) = self.deadline {
+            intent_fields.push_str(&format!(",deadline={}i", deadline));
+        }
+        format!("swap_event,chain_id={},pair_address={},caller_address={},receiver_address={},transaction_hash={},log_index={},direction={}{} \
+                amount0_in=\"{}\",amount1_in=\"{}\",amount0_out=\"{}\",amount1_out=\"{}\",amount_in=\"{}\",amount_out=\"{}\",token0_amounts=\"{}\",token1_amounts=\"{}\",execution_price=\"{}\",token0_token1=\"{}\",token1_token0=\"{}\",block_number={},block_hash=\"{}\",transaction_index={}{} {}",
+                self.chain_id,
                 self.pair_address,
                 self.caller_address,
                 self.receiver_address,
                 self.transaction_hash,
-                self.token0_amount,
-                self.token1_amount,
-                self.token0_amounts,
-                self.token1_amounts,
-                self.token0_token1,
-                self.token1_token0,
+                self.log_index,
+                self.direction,
+                token_tags(
+                    &self.pair_name,
+                    &self.token0_symbol,
+                    &self.token1_symbol,
+                    self.token0_decimals,
+                    self.token1_decimals
+                ),
+                self.amount0_in,
+                self.amount1_in,
+                self.amount0_out,
+                self.amount1_out,
+                self.amount_in,
+                self.amount_out,
+                format_decimal(&self.token0_amounts),
+                format_decimal(&self.token1_amounts),
+                format_decimal(&self.execution_price),
+                format_decimal(&self.token0_token1),
+                format_decimal(&self.token1_token0),
                 self.block_number,
+                self.block_hash,
+                self.transaction_index,
+                intent_fields,
                 self.block_timestamp
             )
     }
 }
 
-pub fn transform_pair_created_event(logs: &[Log]) -> Result<Vec<PairCreatedEvent>> {
+pub fn transform_pair_created_event(
+    logs: &[Log],
+    chain_id: u64,
+) -> Result<Vec<PairCreatedEvent>> {
     let mut events = Vec::new();
     for log in logs {
-        if log.topics().len() < 3 {
-            return Err(eyre::eyre!("Invalid PairCreated event log topics length"));
-        }
-        let function_signature = log.topics()[0].to_string();
-        let token0_address = Address::from_slice(&log.topics()[1][12..32]);
-        let token1_address = Address::from_slice(&log.topics()[2][12..32]);
-        let block_number = log.block_number.unwrap();
-        let transaction_hash = log.transaction_hash.unwrap().to_string();
-        let factory_address = log.address();
-        let pair_address = Address::from_slice(&log.data().data[12..32]);
-        let block_timestamp = log.block_timestamp.unwrap();
+        let event = decode_log::<UniswapV2Factory::PairCreated>(log)?;
 
         events.push(PairCreatedEvent {
+            chain_id,
             event_type: "PairCreated".to_string(),
-            function_signature,
-            token0_address,
-            token1_address,
-            block_number,
-            transaction_hash,
-            factory_address,
-            pair_address,
-            block_timestamp,
+            function_signature: log.topics()[0].to_string(),
+            token0_address: event.token0,
+            token1_address: event.token1,
+            block_number: log.block_number.unwrap(),
+            block_hash: log_field(log.block_hash, "block hash", log)?.to_string(),
+            transaction_hash: log.transaction_hash.unwrap().to_string(),
+            transaction_index: log_field(log.transaction_index, "transaction index", log)?,
+            log_index: log_field(log.log_index, "log index", log)?,
+            factory_address: log.address(),
+            pair_address: event.pair,
+            block_timestamp: log.block_timestamp.unwrap(),
+            risk_score: None,
+            risk_flags: None,
+            fee_on_transfer_bps: None,
+            honeypot: None,
         });
     }
     Ok(events)
 }
 
-pub fn transform_mint_event(logs: &[Log]) -> Result<Vec<MintEvent>> {
+pub fn transform_mint_event(logs: &[Log], chain_id: u64) -> Result<Vec<MintEvent>> {
     let mut events = Vec::new();
     for log in logs {
-        if log.topics().len() < 2 {
-            return Err(eyre::eyre!("Invalid Mint event log topics length"));
-        }
-        let function_signature = log.topics()[0].to_string();
-        let caller_address = Address::from_slice(&log.topics()[1][12..32]);
-        let pair_address = log.address();
-
-        let log_data = log.data().data.clone();
-        if log_data.len() < 48 {
-            return Err(eyre::eyre!(
-                "Mint event log data length is less than 48 bytes"
-            ));
-        }
-        let token0_amount = u128::from_be_bytes(log_data[16..32].try_into().unwrap());
-        let token1_amount = u128::from_be_bytes(log_data[48..64].try_into().unwrap());
-
-        let block_number = log.block_number.unwrap();
-        let transaction_hash = log.transaction_hash.unwrap().to_string();
-        let block_timestamp = log.block_timestamp.unwrap();
+        let event = decode_log::<UniswapV2Pair::Mint>(log)?;
 
         events.push(MintEvent {
+            chain_id,
             event_type: "Mint".to_string(),
-            function_signature,
-            caller_address,
-            pair_address,
-            token0_amount,
-            token1_amount,
-            block_number,
-            transaction_hash,
-            block_timestamp,
+            function_signature: log.topics()[0].to_string(),
+            caller_address: event.sender,
+            pair_address: log.address(),
+            token0_amount: event.amount0,
+            token1_amount: event.amount1,
+            block_number: log.block_number.unwrap(),
+            block_hash: log_field(log.block_hash, "block hash", log)?.to_string(),
+            transaction_hash: log.transaction_hash.unwrap().to_string(),
+            transaction_index: log_field(log.transaction_index, "transaction index", log)?,
+            log_index: log_field(log.log_index, "log index", log)?,
+            block_timestamp: log.block_timestamp.unwrap(),
+            ..Default::default()
         });
     }
     Ok(events)
 }
 
-pub fn transform_burn_event(logs: &[Log]) -> Result<Vec<BurnEvent>> {
+pub fn transform_burn_event(logs: &[Log], chain_id: u64) -> Result<Vec<BurnEvent>> {
     let mut events = Vec::new();
     for log in logs {
-        if log.topics().len() < 3 {
-            return Err(eyre::eyre!("Invalid Burn event log topics length"));
-        }
-        let function_signature = log.topics()[0].to_string();
-        let caller_address = Address::from_slice(&log.topics()[1][12..32]);
-        let address = Address::from_slice(&log.topics()[2][12..32]);
-        let pair_address = log.address();
-
-        let log_data = log.data().data.clone();
-        if log_data.len() < 64 {
-            return Err(eyre::eyre!(
-                "Burn event log data length is less than 64 bytes"
-            ));
-        }
-        let token0_amount = u128::from_be_bytes(log_data[16..32].try_into().unwrap());
-        let token1_amount = u128::from_be_bytes(log_data[48..64].try_into().unwrap());
-
-        let block_number = log.block_number.unwrap();
-        let transaction_hash = log.transaction_hash.unwrap().to_string();
-        let block_timestamp = log.block_timestamp.unwrap();
+        let event = decode_log::<UniswapV2Pair::Burn>(log)?;
 
         events.push(BurnEvent {
+            chain_id,
             event_type: "Burn".to_string(),
-            function_signature,
-            caller_address,
-            pair_address,
-            address,
-            token0_amount,
-            token1_amount,
-            block_number,
-            transaction_hash,
-            block_timestamp,
+            function_signature: log.topics()[0].to_string(),
+            caller_address: event.sender,
+            pair_address: log.address(),
+            address: event.to,
+            token0_amount: event.amount0,
+            token1_amount: event.amount1,
+            block_number: log.block_number.unwrap(),
+            block_hash: log_field(log.block_hash, "block hash", log)?.to_string(),
+            transaction_hash: log.transaction_hash.unwrap().to_string(),
+            transaction_index: log_field(log.transaction_index, "transaction index", log)?,
+            log_index: log_field(log.log_index, "log index", log)?,
+            block_timestamp: log.block_timestamp.unwrap(),
+            ..Default::default()
         });
     }
     Ok(events)
@@ -251,56 +709,61 @@ pub fn transform_burn_event(logs: &[Log]) -> Result<Vec<BurnEvent>> {
 
 pub fn transform_swap_event(
     logs: &[Log],
+    chain_id: u64,
     token0_decimals: u8,
     token1_decimals: u8,
 ) -> Result<Vec<SwapEvent>> {
     let mut events = Vec::new();
 
     for log in logs {
-        if log.topics().len() < 3 {
-            return Err(eyre::eyre!("Invalid Swap event log topics length"));
-        }
-        let function_signature = log.topics()[0].to_string();
-        let caller_address = Address::from_slice(&log.topics()[1][12..32]);
-        let receiver_address = Address::from_slice(&log.topics()[2][12..32]);
-        let pair_address = log.address();
-
-        let log_data = log.data().data.clone();
-        if log_data.len() < 128 {
-            return Err(eyre::eyre!(
-                "Swap event log data length is less than 128 bytes"
-            ));
-        }
-        let amount0_in = u128::from_be_bytes(log_data[16..32].try_into().unwrap());
-        let amount1_in = u128::from_be_bytes(log_data[48..64].try_into().unwrap());
-        let amount0_out = u128::from_be_bytes(log_data[80..96].try_into().unwrap());
-        let amount1_out = u128::from_be_bytes(log_data[112..128].try_into().unwrap());
-        let token0_amount = amount0_in + amount0_out;
-        let token1_amount = amount1_in + amount1_out;
-        let token0_amounts = token0_amount as f64 / 10f64.powi(token0_decimals as i32);
-        let token1_amounts = token1_amount as f64 / 10f64.powi(token1_decimals as i32);
-        let token0_token1 = token0_amounts / token1_amounts;
-        let token1_token0 = token1_amounts / token0_amounts;
-
-        let block_number = log.block_number.unwrap();
-        let transaction_hash = log.transaction_hash.unwrap().to_string();
-        let block_timestamp = log.block_timestamp.unwrap();
+        let event = decode_log::<UniswapV2Pair::Swap>(log)?;
+        let (direction, amount_in, amount_out) = SwapDirection::from_amounts(
+            event.amount0In,
+            event.amount1In,
+            event.amount0Out,
+            event.amount1Out,
+        );
+        let (token0_amount, token1_amount) = match direction {
+            SwapDirection::Token0ToToken1 => (amount_in, amount_out),
+            SwapDirection::Token1ToToken0 => (amount_out, amount_in),
+            SwapDirection::Unknown => (U256::ZERO, U256::ZERO),
+        };
+        let token0_amounts = to_exact_amount(token0_amount, token0_decimals);
+        let token1_amounts = to_exact_amount(token1_amount, token1_decimals);
+        let execution_price = match direction {
+            SwapDirection::Token0ToToken1 => decimal_ratio(&token1_amounts, &token0_amounts),
+            SwapDirection::Token1ToToken0 => decimal_ratio(&token0_amounts, &token1_amounts),
+            SwapDirection::Unknown => BigDecimal::zero(),
+        };
+        let token0_token1 = decimal_ratio(&token0_amounts, &token1_amounts);
+        let token1_token0 = decimal_ratio(&token1_amounts, &token0_amounts);
 
         events.push(SwapEvent {
+            chain_id,
             event_type: "Swap".to_string(),
-            function_signature,
-            caller_address,
-            pair_address,
-            receiver_address,
-            token0_amount,
-            token1_amount,
+            function_signature: log.topics()[0].to_string(),
+            caller_address: event.sender,
+            pair_address: log.address(),
+            receiver_address: event.to,
+            amount0_in: event.amount0In,
+            amount1_in: event.amount1In,
+            amount0_out: event.amount0Out,
+            amount1_out: event.amount1Out,
+            direction,
+            amount_in,
+            amount_out,
             token0_amounts,
             token1_amounts,
+            execution_price,
             token0_token1,
             token1_token0,
-            block_number,
-            transaction_hash,
-            block_timestamp,
+            block_number: log.block_number.unwrap(),
+            block_hash: log_field(log.block_hash, "block hash", log)?.to_string(),
+            transaction_hash: log.transaction_hash.unwrap().to_string(),
+            transaction_index: log_field(log.transaction_index, "transaction index", log)?,
+            log_index: log_field(log.log_index, "log index", log)?,
+            block_timestamp: log.block_timestamp.unwrap(),
+            ..Default::default()
         });
     }
     Ok(events)
@@ -324,7 +787,12 @@ mod tests {
         let log_level = app_config.init_log().unwrap();
         info!("app_config.log_level: {:?}", log_level);
 
-        let evm_block = EvmBlock::new(&app_config.eth.http_url).await.unwrap();
+        let evm_block = EvmBlock::new_with_fixture(
+            &app_config.eth.http_url,
+            std::path::Path::new("data/fixtures/test_transform_pair.json"),
+        )
+        .await
+        .unwrap();
         let router_addr = address!("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D");
         let uniswap_v2 = UniswapV2::new(evm_block.provider.clone(), router_addr).await;
         info!(
@@ -339,7 +807,8 @@ mod tests {
             .await
             .unwrap();
         info!("pair_created_events: {:#?}", pair_created_events);
-        let transformed_events = transform_pair_created_event(&pair_created_events).unwrap();
+        let transformed_events =
+            transform_pair_created_event(&pair_created_events, evm_block.chain_id).unwrap();
         info!("transformed_events: {:#?}", transformed_events);
     }
 
@@ -349,7 +818,12 @@ mod tests {
         let log_level = app_config.init_log().unwrap();
         info!("app_config.log_level: {:?}", log_level);
 
-        let evm_block = EvmBlock::new(&app_config.eth.http_url).await.unwrap();
+        let evm_block = EvmBlock::new_with_fixture(
+            &app_config.eth.http_url,
+            std::path::Path::new("data/fixtures/test_transform_pair_event.json"),
+        )
+        .await
+        .unwrap();
         let router_addr = address!("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D");
         let uniswap_v2 = UniswapV2::new(evm_block.provider.clone(), router_addr).await;
         info!(
@@ -395,10 +869,15 @@ mod tests {
             .await
             .unwrap();
 
-        let mint_events = transform_mint_event(all_event.get("Mint").unwrap_or(&vec![])).unwrap();
-        let burn_events = transform_burn_event(all_event.get("Burn").unwrap_or(&vec![])).unwrap();
+        let mint_events =
+            transform_mint_event(all_event.get("Mint").unwrap_or(&vec![]), evm_block.chain_id)
+                .unwrap();
+        let burn_events =
+            transform_burn_event(all_event.get("Burn").unwrap_or(&vec![]), evm_block.chain_id)
+                .unwrap();
         let swap_events = transform_swap_event(
             all_event.get("Swap").unwrap_or(&vec![]),
+            evm_block.chain_id,
             uniswap_v2_tokens.token0_decimals,
             uniswap_v2_tokens.token1_decimals,
         )
@@ -408,4 +887,113 @@ mod tests {
         info!("All burn events: {:#?}", burn_events);
         info!("All swap events: {:#?}", swap_events);
     }
+
+    fn swap_log(data: String) -> Log {
+        serde_json::from_value(serde_json::json!({
+            "address": "0xb4e16d0168e52d35cacd2c6185b44281ec28c9dc",
+            "topics": [
+                UniswapV2Pair::Swap::SIGNATURE_HASH.to_string(),
+                "0x0000000000000000000000007a250d5630b4cf539739df2c5dacb4c659f2488d",
+                "0x00000000000000000000000000000000000000000000000000000000000000aa"
+            ],
+            "data": data,
+            "blockNumber": "0x15c4b3a",
+            "blockHash": "0x2222222222222222222222222222222222222222222222222222222222222222",
+            "blockTimestamp": "0x68632d80",
+            "transactionHash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "transactionIndex": "0x1",
+            "logIndex": "0x3"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_time_format() {
+        let timestamp = 1_751_330_176;
+        assert_eq!(
+            TimeFormat::default().format(timestamp),
+            "2025-07-01 00:36:16"
+        );
+        let format = TimeFormat::new("rfc3339", "+08:00").unwrap();
+        assert_eq!(format.format(timestamp), "2025-07-01T08:36:16+08:00");
+        let format = TimeFormat::new("rfc3339", "UTC").unwrap();
+        assert_eq!(format.format(timestamp), "2025-07-01T00:36:16Z");
+        let format = TimeFormat::new("epoch_millis", "local").unwrap();
+        assert_eq!(format.format(timestamp), "1751330176000");
+        let format = TimeFormat::new("%d/%m/%Y %H:%M", "-05:00").unwrap();
+        assert_eq!(format.format(timestamp), "30/06/2025 19:36");
+        assert!(TimeFormat::new("%Y-%Q", "UTC").is_err());
+        assert!(TimeFormat::new(DEFAULT_TIME_FORMAT, "Asia/Shanghai").is_err());
+    }
+
+    #[test]
+    fn test_transform_swap_event_u256() {
+        // amount0In = 2^130 does not fit in u128
+        let amount0_in = U256::from(1) << 130;
+        let log = swap_log(format!(
+            "0x{:064x}{:064x}{:064x}{:064x}",
+            amount0_in,
+            U256::ZERO,
+            U256::ZERO,
+            U256::from(5)
+        ));
+        let events = transform_swap_event(std::slice::from_ref(&log), 1, 18, 18).unwrap();
+        assert_eq!(events[0].amount0_in, amount0_in);
+        assert_eq!(events[0].direction, SwapDirection::Token0ToToken1);
+        assert_eq!(events[0].amount_in, amount0_in);
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(&events[0]).unwrap();
+        let row = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert!(row.contains(",1361129467683753853853498429727072845824,0,0,5,"));
+        // Influx parses unquoted numbers as float64, which keeps about 17 significant digits.
+        let line = events[0].to_influx_line();
+        assert!(line.contains(" amount0_in=\"1361129467683753853853498429727072845824\","));
+        assert!(line.contains(",token0_amounts=\"1361129467683753853853.498429727072845824\","));
+
+        let err = transform_swap_event(&[swap_log("0x01".to_string())], 1, 18, 18).unwrap_err();
+        assert!(err.to_string().contains(
+            "tx 0x1111111111111111111111111111111111111111111111111111111111111111, log index 3"
+        ));
+
+        // Logs of pending blocks have no position to key the event by.
+        let pending = Log {
+            log_index: None,
+            ..log
+        };
+        let err = transform_swap_event(&[pending], 1, 18, 18).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Missing log index of log (tx 0x1111111111111111111111111111111111111111111111111111111111111111)"
+        );
+    }
+
+    #[test]
+    fn test_swap_direction_and_price() {
+        // USDC (6) -> WETH (18) flash swap: 1100 USDC in, 100 USDC back out, 0.4 WETH out
+        let log = swap_log(format!(
+            "0x{:064x}{:064x}{:064x}{:064x}",
+            U256::from(1_100_000_000u64),
+            U256::ZERO,
+            U256::from(100_000_000u64),
+            U256::from(400_000_000_000_000_000u64)
+        ));
+        let swap = &transform_swap_event(&[log], 1, 6, 18).unwrap()[0];
+        assert_eq!(swap.direction, SwapDirection::Token0ToToken1);
+        assert_eq!(swap.amount_in, U256::from(1_000_000_000u64));
+        assert_eq!(swap.amount_out, U256::from(400_000_000_000_000_000u64));
+        assert_eq!(swap.token0_amounts, BigDecimal::from(1000));
+        assert_eq!(format_decimal(&swap.execution_price), "0.0004");
+        assert_eq!(
+            format_decimal(&to_exact_amount(U256::from(123_456_789_012_345_678_901u128), 18)),
+            "123.456789012345678901"
+        );
+        assert!(swap.to_influx_line().contains(",log_index=3,direction=token0->token1 "));
+        assert_eq!((swap.transaction_index, swap.log_index), (1, 3));
+        assert!(swap.block_hash.starts_with("0x2222"));
+
+        let (direction, _, _) =
+            SwapDirection::from_amounts(U256::from(5), U256::from(5), U256::from(5), U256::from(5));
+        assert_eq!(direction, SwapDirection::Unknown);
+    }
 }
@@ -0,0 +1,396 @@
+use crate::init::FeeCfg;
+use crate::transform_event::{SwapEvent, decimal_to_f64, serialize_timestamp};
+use crate::transform_liquidity::LiquidityEvent;
+use alloy::primitives::Address;
+use eyre::Result;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+
+const DAY_SECS: u64 = 86400;
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct FeeEvent {
+    pub chain_id: u64,
+    pub event_type: String,
+    pub pair_address: Address,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub day: u64,
+    pub fee_bps: u32,
+    pub swap_count: u64,
+    pub volume_token0: f64,
+    pub fee_token0: f64,
+    // USD sums over the priced swaps only.
+    pub volume_usd: Option<f64>,
+    pub fee_usd: Option<f64>,
+    // Time-weighted over the part of the day the pair's liquidity was known.
+    pub avg_tvl_token0: Option<f64>,
+    pub avg_tvl_usd: Option<f64>,
+    pub apr: Option<f64>,
+    /// APR over the last `apr_window_days` days.
+    pub rolling_apr: Option<f64>,
+    /// Position in the ranking report, empty in daily rows.
+    pub rank: Option<usize>,
+}
+
+impl FeeEvent {
+    pub fn to_influx_line(&self) -> String {
+        let optional_fields: String = [
+            ("volume_usd", self.volume_usd),
+            ("fee_usd", self.fee_usd),
+            ("avg_tvl_token0", self.avg_tvl_token0),
+            ("avg_tvl_usd", self.avg_tvl_usd),
+            ("apr", self.apr),
+            ("rolling_apr", self.rolling_apr),
+        ]
+        .iter()
+        .filter_map(|(name, value)| value.map(|value| format!(",{}={}", name, value)))
+        .collect();
+        format!(
+            "pair_fee,chain_id={},pair_address={} fee_bps={}i,swap_count={}i,volume_token0={},fee_token0={}{} {}",
+            self.chain_id,
+            self.pair_address,
+            self.fee_bps,
+            self.swap_count,
+            self.volume_token0,
+            self.fee_token0,
+            optional_fields,
+            self.day
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct DayBucket {
+    // First time covered by this day's data, for APRs of partial days.
+    start: u64,
+    swap_count: u64,
+    volume_token0: f64,
+    fee_token0: f64,
+    volume_usd: Option<f64>,
+    fee_usd: Option<f64>,
+    tvl_token0_secs: f64,
+    tvl_secs: u64,
+    tvl_usd_secs: f64,
+    tvl_usd_known_secs: u64,
+}
+
+#[derive(Debug, Default)]
+struct PairFees {
+    day: u64,
+    bucket: DayBucket,
+    tvl: Option<(f64, Option<f64>)>,
+    tvl_since: u64,
+    // (fee_token0, avg_tvl_token0, covered secs) of the latest closed days.
+    history: VecDeque<(f64, Option<f64>, u64)>,
+}
+
+impl PairFees {
+    fn accumulate_tvl(&mut self, until: u64) {
+        if let Some((tvl_token0, tvl_usd)) = self.tvl {
+            let secs = until.saturating_sub(self.tvl_since);
+            self.bucket.tvl_token0_secs += tvl_token0 * secs as f64;
+            self.bucket.tvl_secs += secs;
+            if let Some(tvl_usd) = tvl_usd {
+                self.bucket.tvl_usd_secs += tvl_usd * secs as f64;
+                self.bucket.tvl_usd_known_secs += secs;
+            }
+        }
+        self.tvl_since = self.tvl_since.max(until);
+    }
+}
+
+/// Swap fees collected per pair and day, and the APR they pay on the pair's average TVL.
+///
+/// The fee is `fee_bps` of each swap's input, valued in token0 at the swap price. TVL comes
+/// from the pair's liquidity series, so APRs stay empty while it is unknown.
+#[derive(Debug)]
+pub struct FeeTracker {
+    chain_id: u64,
+    default_fee_bps: u32,
+    factory_fee_bps: HashMap<Address, u32>,
+    pair_factories: HashMap<Address, Address>,
+    apr_window_days: usize,
+    pairs: HashMap<Address, PairFees>,
+    latest_timestamp: u64,
+}
+
+impl FeeTracker {
+    pub fn new(chain_id: u64, cfg: &FeeCfg) -> Result<Self> {
+        let factory_fee_bps = cfg
+            .factory_fee_bps
+            .iter()
+            .map(|(factory, fee_bps)| Ok((Address::from_str(factory)?, *fee_bps)))
+            .collect::<Result<HashMap<_, _>>>()?;
+        Ok(Self {
+            chain_id,
+            default_fee_bps: cfg.default_fee_bps,
+            factory_fee_bps,
+            pair_factories: HashMap::new(),
+            apr_window_days: cfg.apr_window_days.max(1),
+            pairs: HashMap::new(),
+            latest_timestamp: 0,
+        })
+    }
+
+    pub fn set_factory(&mut self, pair_address: Address, factory_address: Address) {
+        self.pair_factories.insert(pair_address, factory_address);
+    }
+
+    fn fee_bps(&self, pair_address: &Address) -> u32 {
+        self.pair_factories
+            .get(pair_address)
+            .and_then(|factory| self.factory_fee_bps.get(factory))
+            .copied()
+            .unwrap_or(self.default_fee_bps)
+    }
+
+    /// Adds a swap and returns the days of its pair that closed before it.
+    pub fn add_swap(&mut self, swap: &SwapEvent) -> Vec<FeeEvent> {
+        let fee_rate = self.fee_bps(&swap.pair_address) as f64 / 10_000.0;
+        let events = self.advance(swap.pair_address, swap.block_timestamp);
+        let bucket = &mut self.pairs.get_mut(&swap.pair_address).unwrap().bucket;
+        bucket.swap_count += 1;
+        let volume_token0 = decimal_to_f64(&swap.token0_amounts);
+        bucket.volume_token0 += volume_token0;
+        bucket.fee_token0 += volume_token0 * fee_rate;
+        if let Some(amount_usd) = swap.amount_usd {
+            *bucket.volume_usd.get_or_insert(0.0) += amount_usd;
+            *bucket.fee_usd.get_or_insert(0.0) += amount_usd * fee_rate;
+        }
+        events
+    }
+
+    /// Adds TVL changes and returns the days that closed before them.
+    pub fn add_liquidity(&mut self, liquidity: &[LiquidityEvent]) -> Vec<FeeEvent> {
+        let mut events = Vec::new();
+        for event in liquidity {
+            events.extend(self.advance(event.pair_address, event.block_timestamp));
+            let pair = self.pairs.get_mut(&event.pair_address).unwrap();
+            pair.accumulate_tvl(event.block_timestamp);
+            pair.tvl = Some((event.tvl_token0, event.tvl_usd));
+            pair.tvl_since = event.block_timestamp;
+        }
+        events
+    }
+
+    /// Processes a whole batch in time order and closes the last, partial days.
+    pub fn track_batch(
+        &mut self,
+        swaps: &[SwapEvent],
+        liquidity: &[LiquidityEvent],
+    ) -> Vec<FeeEvent> {
+        let mut items = swaps
+            .iter()
+            .map(|swap| (swap.block_timestamp, 1, Some(swap), None))
+            .chain(
+                liquidity
+                    .iter()
+                    .map(|event| (event.block_timestamp, 0, None, Some(event))),
+            )
+            .collect::<Vec<_>>();
+        items.sort_by_key(|(timestamp, order, _, _)| (*timestamp, *order));
+
+        let mut events = Vec::new();
+        for (_, _, swap, liquidity) in items {
+            if let Some(swap) = swap {
+                events.extend(self.add_swap(swap));
+            }
+            if let Some(liquidity) = liquidity {
+                events.extend(self.add_liquidity(std::slice::from_ref(liquidity)));
+            }
+        }
+        events.extend(self.flush());
+        events
+    }
+
+    /// Closes the current day of every pair up to the latest time seen.
+    pub fn flush(&mut self) -> Vec<FeeEvent> {
+        let mut addresses = self.pairs.keys().copied().collect::<Vec<_>>();
+        addresses.sort();
+        let latest_timestamp = self.latest_timestamp;
+        let mut events = Vec::new();
+        for address in addresses {
+            events.extend(self.advance(address, latest_timestamp));
+            events.extend(self.close_day(address, latest_timestamp));
+        }
+        events
+    }
+
+    fn advance(&mut self, pair_address: Address, timestamp: u64) -> Vec<FeeEvent> {
+        self.latest_timestamp = self.latest_timestamp.max(timestamp);
+        let day = timestamp - timestamp % DAY_SECS;
+        self.pairs.entry(pair_address).or_insert_with(|| PairFees {
+            day,
+            bucket: DayBucket {
+                start: timestamp,
+                ..Default::default()
+            },
+            tvl_since: timestamp,
+            ..Default::default()
+        });
+
+        let mut events = Vec::new();
+        while self.pairs[&pair_address].day < day {
+            let day_end = self.pairs[&pair_address].day + DAY_SECS;
+            events.extend(self.close_day(pair_address, day_end));
+            self.pairs.get_mut(&pair_address).unwrap().day = day_end;
+        }
+        events
+    }
+
+    fn close_day(&mut self, pair_address: Address, end: u64) -> Option<FeeEvent> {
+        let fee_bps = self.fee_bps(&pair_address);
+        let apr_window_days = self.apr_window_days;
+        let pair = self.pairs.get_mut(&pair_address)?;
+        pair.accumulate_tvl(end);
+        let bucket = std::mem::replace(
+            &mut pair.bucket,
+            DayBucket {
+                start: end,
+                ..Default::default()
+            },
+        );
+        if bucket.swap_count == 0 && bucket.tvl_secs == 0 {
+            return None;
+        }
+
+        let avg_tvl_token0 =
+            (bucket.tvl_secs > 0).then(|| bucket.tvl_token0_secs / bucket.tvl_secs as f64);
+        let avg_tvl_usd = (bucket.tvl_usd_known_secs > 0)
+            .then(|| bucket.tvl_usd_secs / bucket.tvl_usd_known_secs as f64);
+        let covered_secs = end.saturating_sub(bucket.start).max(1);
+        let apr = annualize(bucket.fee_token0, avg_tvl_token0, covered_secs);
+
+        pair.history
+            .push_back((bucket.fee_token0, avg_tvl_token0, covered_secs));
+        if pair.history.len() > apr_window_days {
+            pair.history.pop_front();
+        }
+        let window_fee = pair.history.iter().map(|(fee, _, _)| fee).sum::<f64>();
+        let window_tvls = pair
+            .history
+            .iter()
+            .filter_map(|(_, tvl, _)| *tvl)
+            .collect::<Vec<_>>();
+        let window_tvl = (!window_tvls.is_empty())
+            .then(|| window_tvls.iter().sum::<f64>() / window_tvls.len() as f64);
+        let window_secs = pair.history.iter().map(|(_, _, secs)| secs).sum::<u64>();
+
+        Some(FeeEvent {
+            chain_id: self.chain_id,
+            event_type: "Fee".to_string(),
+            pair_address,
+            day: pair.day,
+            fee_bps,
+            swap_count: bucket.swap_count,
+            volume_token0: bucket.volume_token0,
+            fee_token0: bucket.fee_token0,
+            volume_usd: bucket.volume_usd,
+            fee_usd: bucket.fee_usd,
+            avg_tvl_token0,
+            avg_tvl_usd,
+            apr,
+            rolling_apr: annualize(window_fee, window_tvl, window_secs),
+            rank: None,
+        })
+    }
+}
+
+fn annualize(fee: f64, tvl: Option<f64>, secs: u64) -> Option<f64> {
+    tvl.filter(|tvl| *tvl > 0.0)
+        .map(|tvl| fee / tvl * (365 * DAY_SECS) as f64 / secs as f64)
+}
+
+/// Latest day of every pair, ordered by rolling APR (pairs without one last).
+pub fn rank_pairs(events: &[FeeEvent]) -> Vec<FeeEvent> {
+    let mut latest: HashMap<Address, &FeeEvent> = HashMap::new();
+    for event in events {
+        let entry = latest.entry(event.pair_address).or_insert(event);
+        if event.day >= entry.day {
+            *entry = event;
+        }
+    }
+    let mut ranking = latest.into_values().cloned().collect::<Vec<_>>();
+    ranking.sort_by(|a, b| {
+        b.rolling_apr
+            .unwrap_or(f64::NEG_INFINITY)
+            .total_cmp(&a.rolling_apr.unwrap_or(f64::NEG_INFINITY))
+            .then(a.pair_address.cmp(&b.pair_address))
+    });
+    for (i, event) in ranking.iter_mut().enumerate() {
+        event.rank = Some(i + 1);
+    }
+    ranking
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform_event::SwapDirection;
+    use alloy::primitives::address;
+    use bigdecimal::BigDecimal;
+
+    #[test]
+    fn test_fee_tracker() -> Result<()> {
+        let pair_a = address!("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+        let pair_b = address!("0xA43fe16908251ee70EF74718545e4FE6C5cCEc9f");
+        let fork_factory = address!("0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac");
+        let cfg = FeeCfg {
+            default_fee_bps: 30,
+            factory_fee_bps: HashMap::from([(fork_factory.to_string().to_lowercase(), 25)]),
+            apr_window_days: 7,
+        };
+        let mut tracker = FeeTracker::new(1, &cfg)?;
+        tracker.set_factory(pair_b, fork_factory);
+
+        let liquidity = |pair_address, block_timestamp, tvl_token0| LiquidityEvent {
+            pair_address,
+            block_timestamp,
+            tvl_token0,
+            ..Default::default()
+        };
+        let swap = |pair_address, block_timestamp, token0_amounts| SwapEvent {
+            pair_address,
+            block_timestamp,
+            token0_amounts: BigDecimal::try_from(token0_amounts).unwrap(),
+            direction: SwapDirection::Token0ToToken1,
+            amount_usd: Some(token0_amounts),
+            ..Default::default()
+        };
+
+        // Pair A: 10000 TVL all day, 1000 traded -> 3 fee, APR 3 / 10000 * 365.
+        let events = tracker.track_batch(
+            &[
+                swap(pair_a, 3600, 1000.0),
+                swap(pair_b, 3600, 1000.0),
+                swap(pair_a, DAY_SECS + 10, 1.0),
+            ],
+            &[liquidity(pair_a, 0, 10000.0), liquidity(pair_b, 0, 20000.0)],
+        );
+        let day0 = events
+            .iter()
+            .find(|e| e.pair_address == pair_a && e.day == 0)
+            .unwrap();
+        assert_eq!(day0.swap_count, 1);
+        assert!((day0.fee_token0 - 3.0).abs() < 1e-9);
+        assert_eq!(day0.fee_usd, day0.volume_usd.map(|v| v * 0.003));
+        assert_eq!(day0.avg_tvl_token0, Some(10000.0));
+        assert!((day0.apr.unwrap() - 0.1095).abs() < 1e-9);
+        assert_eq!(day0.rolling_apr, day0.apr);
+
+        // Forked factory charges 0.25%.
+        let fork_day0 = events.iter().find(|e| e.pair_address == pair_b).unwrap();
+        assert_eq!(fork_day0.fee_bps, 25);
+        assert!((fork_day0.fee_token0 - 2.5).abs() < 1e-9);
+
+        let ranking = rank_pairs(&events);
+        assert_eq!(ranking.len(), 2);
+        assert_eq!(ranking[0].rank, Some(1));
+        assert!(ranking[0].rolling_apr >= ranking[1].rolling_apr);
+        assert!(
+            day0.to_influx_line()
+                .starts_with("pair_fee,chain_id=1,pair_address=")
+        );
+        Ok(())
+    }
+}
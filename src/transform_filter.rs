@@ -0,0 +1,193 @@
+use crate::load_event::csv_row;
+use crate::transform_registry::CustomRecord;
+use csv::StringRecord;
+use evalexpr::{
+    ContextWithMutableVariables, EvalexprError, HashMapContext, Node, Value, build_operator_tree,
+};
+use eyre::{Result, WrapErr};
+use log::{debug, info};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+// Live commands report the dropped count of an event type every this many drops.
+const DROPPED_LOG_EVERY: u64 = 100;
+
+/// Boolean expressions over the columns of written records, by event type (`create`,
+/// `mint`, `burn`, `swap`, `pending` or the type of a custom record). Records for which
+/// the expression is false are not written; event types without one are all kept.
+pub struct EventFilter {
+    expressions: HashMap<String, (String, Node)>,
+    // (seen, dropped) per event type.
+    counts: BTreeMap<String, (u64, u64)>,
+}
+
+impl EventFilter {
+    pub fn new(expressions: &BTreeMap<String, String>) -> Result<Self> {
+        let expressions = expressions
+            .iter()
+            .map(|(event_type, expression)| {
+                let node = build_operator_tree(expression).wrap_err_with(|| {
+                    format!("Invalid filter for {} events: {}", event_type, expression)
+                })?;
+                Ok((event_type.clone(), (expression.clone(), node)))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            expressions,
+            counts: BTreeMap::new(),
+        })
+    }
+
+    /// Whether to write `record`. Fails on columns the record doesn't have; any other
+    /// evaluation error, e.g. comparing an empty `amount_usd`, drops the record.
+    pub fn keep<T: Serialize>(&mut self, event_type: &str, record: &T) -> Result<bool> {
+        if !self.expressions.contains_key(event_type) {
+            return Ok(true);
+        }
+        let (headers, row) = csv_row(record)?;
+        self.keep_row(event_type, &headers, &row)
+    }
+
+    pub fn keep_custom(&mut self, record: &dyn CustomRecord) -> Result<bool> {
+        if !self.expressions.contains_key(record.event_type()) {
+            return Ok(true);
+        }
+        let (headers, row) = record.to_csv_row()?;
+        self.keep_row(record.event_type(), &headers, &row)
+    }
+
+    /// The records of `events` to write.
+    pub fn filtered<T: Serialize + Clone>(
+        &mut self,
+        event_type: &str,
+        events: &[T],
+    ) -> Result<Vec<T>> {
+        let mut kept = Vec::with_capacity(events.len());
+        for event in events {
+            if self.keep(event_type, event)? {
+                kept.push(event.clone());
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Logs how many records of each filtered event type were dropped so far.
+    pub fn log_dropped(&self) {
+        for (event_type, (seen, dropped)) in &self.counts {
+            info!(
+                "Filter dropped {} of {} {} events",
+                dropped, seen, event_type
+            );
+        }
+    }
+
+    fn keep_row(
+        &mut self,
+        event_type: &str,
+        headers: &StringRecord,
+        row: &StringRecord,
+    ) -> Result<bool> {
+        let (expression, node) = &self.expressions[event_type];
+        let mut context = HashMapContext::new();
+        for (header, field) in headers.iter().zip(row.iter()) {
+            context.set_value(header.to_string(), to_value(field))?;
+        }
+        let keep = match node.eval_boolean_with_context(&context) {
+            Ok(keep) => keep,
+            Err(EvalexprError::VariableIdentifierNotFound(name)) => {
+                eyre::bail!(
+                    "Filter for {} events uses unknown column {}: {}",
+                    event_type,
+                    name,
+                    expression
+                );
+            }
+            Err(e) => {
+                debug!("Filter for {} events failed on a record: {}", event_type, e);
+                false
+            }
+        };
+        let (seen, dropped) = self.counts.entry(event_type.to_string()).or_default();
+        *seen += 1;
+        if !keep {
+            *dropped += 1;
+            if *dropped % DROPPED_LOG_EVERY == 0 {
+                info!(
+                    "Filter dropped {} of {} {} events so far",
+                    dropped, seen, event_type
+                );
+            }
+        }
+        Ok(keep)
+    }
+}
+
+// CSV fields are strings; numeric ones (amounts, prices, block numbers) compare as numbers.
+fn to_value(field: &str) -> Value {
+    match field {
+        "" => return Value::Empty,
+        "true" => return Value::Boolean(true),
+        "false" => return Value::Boolean(false),
+        _ => {}
+    }
+    let numeric = field.bytes().any(|b| b.is_ascii_digit())
+        && field
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b == b'.' || b == b'-');
+    if numeric {
+        if let Ok(value) = field.parse::<i64>() {
+            return Value::Int(value);
+        }
+        if let Ok(value) = field.parse::<f64>() {
+            return Value::Float(value);
+        }
+    }
+    Value::String(field.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform_event::SwapEvent;
+
+    #[test]
+    fn test_event_filter() {
+        let swap = |symbol: &str, amount_usd: Option<f64>| SwapEvent {
+            token0_symbol: Some(symbol.to_string()),
+            amount_usd,
+            ..Default::default()
+        };
+        let mut filter = EventFilter::new(&BTreeMap::from([(
+            "swap".to_string(),
+            r#"amount_usd > 10000 && token0_symbol == "WETH""#.to_string(),
+        )]))
+        .unwrap();
+
+        assert!(filter.keep("swap", &swap("WETH", Some(25_000.5))).unwrap());
+        assert!(!filter.keep("swap", &swap("WETH", Some(500.0))).unwrap());
+        assert!(!filter.keep("swap", &swap("USDC", Some(25_000.5))).unwrap());
+        // Unpriced swaps can't pass a USD threshold.
+        assert!(!filter.keep("swap", &swap("WETH", None)).unwrap());
+        // Event types without a filter are all written.
+        assert!(filter.keep("mint", &swap("USDC", None)).unwrap());
+        let kept = filter
+            .filtered("swap", &[swap("WETH", Some(20_000.0)), swap("DAI", None)])
+            .unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(filter.counts["swap"], (6, 4));
+
+        let mut typo = EventFilter::new(&BTreeMap::from([(
+            "swap".to_string(),
+            "amount_usdc > 1".to_string(),
+        )]))
+        .unwrap();
+        assert!(typo.keep("swap", &swap("WETH", Some(1.0))).is_err());
+        assert!(
+            EventFilter::new(&BTreeMap::from([(
+                "swap".to_string(),
+                "(amount_usd > 1".to_string()
+            )]))
+            .is_err()
+        );
+    }
+}
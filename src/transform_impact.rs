@@ -0,0 +1,144 @@
+use crate::extract_event::{SYNC_EVENT_SIGNATURE, UniswapV2Pair, UniswapV2TokenPair};
+use crate::transform_event::{
+    SwapDirection, SwapEvent, decimal_to_f64, decode_log, to_decimal_amount,
+};
+use alloy::primitives::{Address, U256};
+use alloy::rpc::types::eth::Log;
+use eyre::Result;
+use std::collections::HashMap;
+
+const BPS: f64 = 10_000.0;
+
+/// Price impact and slippage of swaps from the constant-product reserves. A pair emits Sync
+/// right before Swap in the same call, so that Sync holds the reserves after the swap and
+/// the reserves before it follow from the swap amounts.
+#[derive(Debug, Default)]
+pub struct PriceImpactTracker {
+    // Reserves of the last Sync per pair and the transaction that emitted it.
+    reserves: HashMap<Address, (String, U256, U256)>,
+}
+
+impl PriceImpactTracker {
+    /// Keeps the reserves of Sync logs, ignores any other log.
+    pub fn observe_log(&mut self, log: &Log) -> Result<()> {
+        if log.topics().first() != Some(&SYNC_EVENT_SIGNATURE) {
+            return Ok(());
+        }
+        let sync = decode_log::<UniswapV2Pair::Sync>(log)?;
+        let transaction_hash = log
+            .transaction_hash
+            .map(|hash| hash.to_string())
+            .unwrap_or_default();
+        self.reserves.insert(
+            log.address(),
+            (
+                transaction_hash,
+                U256::from(sync.reserve0),
+                U256::from(sync.reserve1),
+            ),
+        );
+        Ok(())
+    }
+
+    /// Fills the mid prices (token1 per token0), price impact and slippage of `swap`. They
+    /// stay empty without a Sync of the same transaction or when the direction is unknown.
+    pub fn apply(&self, swap: &mut SwapEvent, pair: &UniswapV2TokenPair) {
+        let Some((transaction_hash, reserve0_after, reserve1_after)) =
+            self.reserves.get(&swap.pair_address)
+        else {
+            return;
+        };
+        if *transaction_hash != swap.transaction_hash || swap.direction == SwapDirection::Unknown {
+            return;
+        }
+        let (Some(reserve0_before), Some(reserve1_before)) = (
+            (*reserve0_after + swap.amount0_out).checked_sub(swap.amount0_in),
+            (*reserve1_after + swap.amount1_out).checked_sub(swap.amount1_in),
+        ) else {
+            return;
+        };
+        let mid_price = |reserve0: U256, reserve1: U256| {
+            let reserve0 = to_decimal_amount(reserve0, pair.token0.decimals);
+            let reserve1 = to_decimal_amount(reserve1, pair.token1.decimals);
+            (reserve0 > 0.0).then(|| reserve1 / reserve0)
+        };
+        let (Some(before), Some(after)) = (
+            mid_price(reserve0_before, reserve1_before),
+            mid_price(*reserve0_after, *reserve1_after),
+        ) else {
+            return;
+        };
+        let execution = decimal_to_f64(&swap.token1_amounts) / decimal_to_f64(&swap.token0_amounts);
+        // Selling token0 gets less token1 than the mid price, buying it costs more.
+        let slippage = match swap.direction {
+            SwapDirection::Token0ToToken1 => before - execution,
+            _ => execution - before,
+        };
+        swap.mid_price_before = Some(before);
+        swap.mid_price_after = Some(after);
+        swap.price_impact_bps = Some((after - before).abs() / before * BPS);
+        swap.slippage_bps = Some(slippage / before * BPS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{USDC_WETH, usdc_weth};
+    use crate::transform_event::to_exact_amount;
+    use alloy::primitives::B256;
+
+    #[test]
+    fn test_price_impact_tracker() {
+        let pair_address = USDC_WETH;
+        let pair = usdc_weth();
+        let transaction_hash = B256::repeat_byte(0x11);
+        // 1,000,000 USDC / 500 WETH before; 10,000 USDC in, 4.9357... WETH out (0.3% fee).
+        let amount_in = U256::from(10_000_000_000u64);
+        let amount_out = U256::from(4_935_790_171_985_306_494u128);
+        let reserve0_after = U256::from(1_010_000_000_000u64);
+        let reserve1_after =
+            U256::from(500u64) * U256::from(10u64).pow(U256::from(18)) - amount_out;
+        let sync: Log = serde_json::from_value(serde_json::json!({
+            "address": pair_address,
+            "topics": [SYNC_EVENT_SIGNATURE],
+            "data": format!("0x{:064x}{:064x}", reserve0_after, reserve1_after),
+            "blockNumber": "0x1",
+            "transactionHash": transaction_hash,
+            "transactionIndex": "0x0",
+            "logIndex": "0x1"
+        }))
+        .unwrap();
+        let mut swap = SwapEvent {
+            pair_address,
+            direction: SwapDirection::Token0ToToken1,
+            amount0_in: amount_in,
+            amount1_out: amount_out,
+            amount_in,
+            amount_out,
+            token0_amounts: to_exact_amount(amount_in, 6),
+            token1_amounts: to_exact_amount(amount_out, 18),
+            transaction_hash: transaction_hash.to_string(),
+            ..Default::default()
+        };
+
+        let mut tracker = PriceImpactTracker::default();
+        tracker.apply(&mut swap, &pair);
+        assert!(swap.mid_price_before.is_none());
+
+        tracker.observe_log(&sync).unwrap();
+        tracker.apply(&mut swap, &pair);
+        assert!((swap.mid_price_before.unwrap() - 0.0005).abs() < 1e-12);
+        // The mid price drops about 2% after a 1% trade; slippage adds the fee to half of it.
+        assert!((swap.price_impact_bps.unwrap() - 196.7).abs() < 0.1);
+        assert!((swap.slippage_bps.unwrap() - 128.4).abs() < 0.1);
+
+        let mut other = SwapEvent {
+            transaction_hash: B256::repeat_byte(0x22).to_string(),
+            ..swap.clone()
+        };
+        other.mid_price_before = None;
+        tracker.apply(&mut other, &pair);
+        assert!(other.mid_price_before.is_none());
+    }
+}
@@ -0,0 +1,352 @@
+use crate::extract_event::{
+    BURN_EVENT_SIGNATURE, MINT_EVENT_SIGNATURE, SWAP_EVENT_SIGNATURE, SYNC_EVENT_SIGNATURE,
+    TRANSFER_EVENT_SIGNATURE, UniswapV2Pair, UniswapV2TokenPair,
+};
+use crate::transform_event::{decode_log, serialize_timestamp, serialize_u256, to_decimal_amount};
+use alloy::primitives::{Address, U256};
+use alloy::rpc::types::eth::Log;
+use eyre::Result;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct LiquidityEvent {
+    pub chain_id: u64,
+    pub event_type: String,
+    /// Log that changed the pair (`Sync`, or `Mint`/`Burn`/`Swap` without Sync), or `Snapshot`.
+    pub trigger: String,
+    pub pair_address: Address,
+    #[serde(serialize_with = "serialize_u256")]
+    pub reserve0: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub reserve1: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub total_supply: U256,
+    pub token0_reserve: f64,
+    pub token1_reserve: f64,
+    // Both reserves valued in one token at the pool price, i.e. twice its own reserve.
+    pub tvl_token0: f64,
+    pub tvl_token1: f64,
+    pub block_number: u64,
+    pub transaction_hash: String,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub block_timestamp: u64,
+    // USD valuation from reference pairs, see `UsdPricer`.
+    pub token0_price_usd: Option<f64>,
+    pub token1_price_usd: Option<f64>,
+    pub tvl_usd: Option<f64>,
+}
+
+impl LiquidityEvent {
+    pub fn to_influx_line(&self) -> String {
+        let usd_fields: String = [
+            ("token0_price_usd", self.token0_price_usd),
+            ("token1_price_usd", self.token1_price_usd),
+            ("tvl_usd", self.tvl_usd),
+        ]
+        .iter()
+        .filter_map(|(name, value)| value.map(|value| format!(",{}={}", name, value)))
+        .collect();
+        format!(
+            "pair_liquidity,chain_id={},pair_address={},trigger={} reserve0=\"{}\",reserve1=\"{}\",total_supply=\"{}\",token0_reserve={},token1_reserve={},tvl_token0={},tvl_token1={},block_number={}i{} {}",
+            self.chain_id,
+            self.pair_address,
+            self.trigger,
+            self.reserve0,
+            self.reserve1,
+            self.total_supply,
+            self.token0_reserve,
+            self.token1_reserve,
+            self.tvl_token0,
+            self.tvl_token1,
+            self.block_number,
+            usd_fields,
+            self.block_timestamp
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct PairState {
+    reserve0: U256,
+    reserve1: U256,
+    total_supply: U256,
+    // Once a Sync is seen, reserves come only from Sync and no longer from deltas.
+    synced: bool,
+    block_number: u64,
+    next_snapshot: u64,
+}
+
+/// Cumulative reserves and LP total supply per pair.
+///
+/// Reserves are taken from Sync logs; logs without Sync (e.g. partial log files) fall back
+/// to Mint/Burn/Swap deltas, which are only exact from a seeded state or the pair creation.
+/// LP supply follows Transfer logs from and to the zero address. With `snapshot_secs`, a
+/// `Snapshot` row per pair is emitted at every interval boundary as well.
+#[derive(Debug)]
+pub struct LiquidityTracker {
+    chain_id: u64,
+    snapshot_secs: Option<u64>,
+    states: HashMap<Address, PairState>,
+}
+
+impl LiquidityTracker {
+    pub fn new(chain_id: u64, snapshot_secs: Option<u64>) -> Self {
+        Self {
+            chain_id,
+            snapshot_secs,
+            states: HashMap::new(),
+        }
+    }
+
+    /// Starting state read from the chain, e.g. at the block before a batch range.
+    pub fn seed(
+        &mut self,
+        pair_address: Address,
+        (reserve0, reserve1, total_supply): (U256, U256, U256),
+        block_number: u64,
+    ) {
+        let state = self.states.entry(pair_address).or_default();
+        state.reserve0 = reserve0;
+        state.reserve1 = reserve1;
+        state.total_supply = total_supply;
+        state.block_number = block_number;
+    }
+
+    /// Applies a pair log and returns the snapshots it passed plus the changed liquidity, if any.
+    pub fn apply_log(
+        &mut self,
+        log: &Log,
+        pair: &UniswapV2TokenPair,
+    ) -> Result<Vec<LiquidityEvent>> {
+        let Some(&signature) = log.topics().first() else {
+            return Ok(Vec::new());
+        };
+        let block_timestamp = log.block_timestamp.unwrap();
+        self.states.entry(pair.pair_address).or_default();
+        let mut events = self.snapshots_until(pair, block_timestamp);
+
+        let state = self.states.get_mut(&pair.pair_address).unwrap();
+        state.block_number = log.block_number.unwrap();
+
+        let changed = match signature {
+            sig if sig == SYNC_EVENT_SIGNATURE => {
+                let sync = decode_log::<UniswapV2Pair::Sync>(log)?;
+                state.reserve0 = U256::from(sync.reserve0);
+                state.reserve1 = U256::from(sync.reserve1);
+                state.synced = true;
+                true
+            }
+            sig if sig == TRANSFER_EVENT_SIGNATURE => {
+                let transfer = decode_log::<UniswapV2Pair::Transfer>(log)?;
+                if transfer.from == Address::ZERO {
+                    state.total_supply += transfer.value;
+                } else if transfer.to == Address::ZERO {
+                    state.total_supply = state.total_supply.saturating_sub(transfer.value);
+                }
+                // Always followed by the Sync (or Mint/Burn) of the same call.
+                false
+            }
+            _ if state.synced => false,
+            sig if sig == MINT_EVENT_SIGNATURE => {
+                let mint = decode_log::<UniswapV2Pair::Mint>(log)?;
+                state.reserve0 += mint.amount0;
+                state.reserve1 += mint.amount1;
+                true
+            }
+            sig if sig == BURN_EVENT_SIGNATURE => {
+                let burn = decode_log::<UniswapV2Pair::Burn>(log)?;
+                state.reserve0 = state.reserve0.saturating_sub(burn.amount0);
+                state.reserve1 = state.reserve1.saturating_sub(burn.amount1);
+                true
+            }
+            sig if sig == SWAP_EVENT_SIGNATURE => {
+                let swap = decode_log::<UniswapV2Pair::Swap>(log)?;
+                state.reserve0 = (state.reserve0 + swap.amount0In).saturating_sub(swap.amount0Out);
+                state.reserve1 = (state.reserve1 + swap.amount1In).saturating_sub(swap.amount1Out);
+                true
+            }
+            _ => false,
+        };
+
+        if changed {
+            let trigger = trigger_name(signature);
+            let mut event = self.event(pair, trigger, block_timestamp);
+            event.transaction_hash = log.transaction_hash.unwrap().to_string();
+            events.push(event);
+        }
+        Ok(events)
+    }
+
+    /// Takes back the LP supply change of a log removed by a reorg. Reserves are
+    /// absolute and get corrected by the next Sync.
+    pub fn remove_log(&mut self, log: &Log) -> Result<()> {
+        if log.topics().first() != Some(&TRANSFER_EVENT_SIGNATURE) {
+            return Ok(());
+        }
+        let Some(state) = self.states.get_mut(&log.address()) else {
+            return Ok(());
+        };
+        let transfer = decode_log::<UniswapV2Pair::Transfer>(log)?;
+        if transfer.from == Address::ZERO {
+            state.total_supply = state.total_supply.saturating_sub(transfer.value);
+        } else if transfer.to == Address::ZERO {
+            state.total_supply += transfer.value;
+        }
+        Ok(())
+    }
+
+    /// Emits the snapshots of every tracked pair due at or before `timestamp`.
+    pub fn advance_to(
+        &mut self,
+        pairs: &HashMap<Address, UniswapV2TokenPair>,
+        timestamp: u64,
+    ) -> Vec<LiquidityEvent> {
+        let mut addresses = self.states.keys().copied().collect::<Vec<_>>();
+        addresses.sort();
+        addresses
+            .iter()
+            .filter_map(|address| pairs.get(address))
+            .flat_map(|pair| self.snapshots_until(pair, timestamp))
+            .collect()
+    }
+
+    fn snapshots_until(
+        &mut self,
+        pair: &UniswapV2TokenPair,
+        timestamp: u64,
+    ) -> Vec<LiquidityEvent> {
+        let (Some(secs), Some(state)) = (self.snapshot_secs, self.states.get(&pair.pair_address))
+        else {
+            return Vec::new();
+        };
+        // The first call only schedules the boundary after `timestamp`.
+        let mut next_snapshot = match state.next_snapshot {
+            0 => timestamp - timestamp % secs + secs,
+            next_snapshot => next_snapshot,
+        };
+        let mut events = Vec::new();
+        while next_snapshot <= timestamp {
+            events.push(self.event(pair, "Snapshot", next_snapshot));
+            next_snapshot += secs;
+        }
+        self.states
+            .get_mut(&pair.pair_address)
+            .unwrap()
+            .next_snapshot = next_snapshot;
+        events
+    }
+
+    fn event(
+        &self,
+        pair: &UniswapV2TokenPair,
+        trigger: &str,
+        block_timestamp: u64,
+    ) -> LiquidityEvent {
+        let state = &self.states[&pair.pair_address];
+        let token0_reserve = to_decimal_amount(state.reserve0, pair.token0.decimals);
+        let token1_reserve = to_decimal_amount(state.reserve1, pair.token1.decimals);
+        LiquidityEvent {
+            chain_id: self.chain_id,
+            event_type: "Liquidity".to_string(),
+            trigger: trigger.to_string(),
+            pair_address: pair.pair_address,
+            reserve0: state.reserve0,
+            reserve1: state.reserve1,
+            total_supply: state.total_supply,
+            token0_reserve,
+            token1_reserve,
+            tvl_token0: token0_reserve * 2.0,
+            tvl_token1: token1_reserve * 2.0,
+            block_number: state.block_number,
+            block_timestamp,
+            ..Default::default()
+        }
+    }
+}
+
+fn trigger_name(signature: alloy::primitives::B256) -> &'static str {
+    match signature {
+        sig if sig == SYNC_EVENT_SIGNATURE => "Sync",
+        sig if sig == MINT_EVENT_SIGNATURE => "Mint",
+        sig if sig == BURN_EVENT_SIGNATURE => "Burn",
+        _ => "Swap",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{USDC_WETH, pair, pair_log, token};
+    use alloy::primitives::{Uint, address};
+
+    #[test]
+    fn test_liquidity_tracker() -> Result<()> {
+        let pair_address = USDC_WETH;
+        let token = |decimals| token(Address::ZERO, decimals, "");
+        let pair = pair(pair_address, token(6), token(18));
+        let pairs = HashMap::from([(pair_address, pair.clone())]);
+        let mut tracker = LiquidityTracker::new(1, Some(3600));
+
+        // Without Sync, reserves follow Mint and Swap deltas; LP supply follows Transfer.
+        let lp_mint = UniswapV2Pair::Transfer {
+            from: Address::ZERO,
+            to: address!("0x00000000000000000000000000000000000000aa"),
+            value: U256::from(1000),
+        };
+        let mint = UniswapV2Pair::Mint {
+            sender: Address::ZERO,
+            amount0: U256::from(5_000_000_000u64),
+            amount1: U256::from(2_000_000_000_000_000_000u64),
+        };
+        assert!(
+            tracker
+                .apply_log(&pair_log(pair_address, lp_mint, 1, 3700), &pair)?
+                .is_empty()
+        );
+        let events = tracker.apply_log(&pair_log(pair_address, mint, 1, 3700), &pair)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].trigger, "Mint");
+        assert_eq!(events[0].total_supply, U256::from(1000));
+        assert_eq!(events[0].token0_reserve, 5000.0);
+        assert_eq!(events[0].tvl_token1, 4.0);
+
+        let swap = UniswapV2Pair::Swap {
+            sender: Address::ZERO,
+            amount0In: U256::from(1_000_000_000u64),
+            amount1In: U256::ZERO,
+            amount0Out: U256::ZERO,
+            amount1Out: U256::from(300_000_000_000_000_000u64),
+            to: Address::ZERO,
+        };
+        let events = tracker.apply_log(&pair_log(pair_address, swap.clone(), 2, 3800), &pair)?;
+        assert_eq!(events[0].reserve0, U256::from(6_000_000_000u64));
+        assert_eq!(events[0].reserve1, U256::from(1_700_000_000_000_000_000u64));
+
+        // Sync crosses the 7200 snapshot boundary and then overrides the reserves.
+        let sync = UniswapV2Pair::Sync {
+            reserve0: Uint::from(7_000_000_000u64),
+            reserve1: Uint::from(1_500_000_000_000_000_000u64),
+        };
+        let events = tracker.apply_log(&pair_log(pair_address, sync, 3, 7300), &pair)?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].trigger, "Snapshot");
+        assert_eq!(events[0].block_timestamp, 7200);
+        assert_eq!(events[0].reserve0, U256::from(6_000_000_000u64));
+        assert_eq!(events[1].trigger, "Sync");
+        assert_eq!(events[1].reserve0, U256::from(7_000_000_000u64));
+        assert!(
+            events[1]
+                .to_influx_line()
+                .starts_with("pair_liquidity,chain_id=1,")
+        );
+
+        // After a Sync, swaps no longer move reserves by themselves.
+        assert!(
+            tracker
+                .apply_log(&pair_log(pair_address, swap, 4, 7400), &pair)?
+                .is_empty()
+        );
+        assert_eq!(tracker.advance_to(&pairs, 15000).len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,641 @@
+use crate::extract_event::{TRANSFER_EVENT_SIGNATURE, UniswapV2Pair, UniswapV2TokenPair};
+use crate::transform_event::{
+    BurnEvent, MintEvent, SwapDirection, SwapEvent, decimal_to_f64, decode_log,
+    serialize_timestamp, to_decimal_amount,
+};
+use crate::transform_route::transform_route_event;
+use alloy::primitives::Address;
+use alloy::rpc::types::eth::Log;
+use eyre::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MevKind {
+    Sandwich,
+    Arbitrage,
+    JitLiquidity,
+}
+
+impl std::fmt::Display for MevKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sandwich => write!(f, "sandwich"),
+            Self::Arbitrage => write!(f, "arbitrage"),
+            Self::JitLiquidity => write!(f, "jit_liquidity"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MevEvent {
+    pub chain_id: u64,
+    pub event_type: String,
+    pub kind: MevKind,
+    pub actor: Address,
+    /// Pair of the sandwich or JIT position; the first pair of an arbitrage cycle.
+    pub pair_address: Address,
+    /// Transactions in block order, e.g. `front>victim>back` or `mint>swap>burn`.
+    pub transaction_hashes: String,
+    pub victim_count: usize,
+    pub profit_token: Address,
+    pub profit: f64,
+    pub profit_usd: Option<f64>,
+    pub block_number: u64,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub block_timestamp: u64,
+}
+
+impl MevEvent {
+    pub fn to_influx_line(&self) -> String {
+        let profit_usd = self
+            .profit_usd
+            .map_or(String::new(), |profit| format!(",profit_usd={}", profit));
+        format!(
+            "mev_event,chain_id={},kind={},actor={},pair_address={} transaction_hashes=\"{}\",victim_count={}i,profit_token=\"{}\",profit={}{},block_number={}i {}",
+            self.chain_id,
+            self.kind,
+            self.actor,
+            self.pair_address,
+            self.transaction_hashes,
+            self.victim_count,
+            self.profit_token,
+            self.profit,
+            profit_usd,
+            self.block_number,
+            self.block_timestamp
+        )
+    }
+}
+
+// Router users share the router as caller, so the decoded transaction sender is preferred.
+fn actor(swap: &SwapEvent) -> Address {
+    swap.tx_sender.unwrap_or(swap.caller_address)
+}
+
+/// LP providers of Mint and Burn events, per (transaction hash, pair), from the pair token's
+/// Transfer logs as `PositionTracker` derives them: the receiver of the last LP mint, the
+/// sender of the LP tokens returned to the pair. Without those logs the event's caller is
+/// used, unless it is one of the routers shared by all their users.
+#[derive(Debug, Default)]
+pub struct LpProviders {
+    routers: HashSet<Address>,
+    mints: HashMap<(String, Address), Address>,
+    burns: HashMap<(String, Address), Address>,
+}
+
+impl LpProviders {
+    pub fn new(routers: HashSet<Address>) -> Self {
+        Self {
+            routers,
+            ..Default::default()
+        }
+    }
+
+    /// Records the provider of LP Transfer logs; other logs are ignored.
+    pub fn observe_log(&mut self, log: &Log) -> Result<()> {
+        if log.topics().first() != Some(&TRANSFER_EVENT_SIGNATURE) {
+            return Ok(());
+        }
+        let transfer = decode_log::<UniswapV2Pair::Transfer>(log)?;
+        let key = (
+            log.transaction_hash.unwrap_or_default().to_string(),
+            log.address(),
+        );
+        if transfer.from == Address::ZERO && transfer.to != Address::ZERO {
+            self.mints.insert(key, transfer.to);
+        } else if transfer.to == log.address() && transfer.from != Address::ZERO {
+            self.burns.insert(key, transfer.from);
+        }
+        Ok(())
+    }
+
+    fn mint_provider(&self, mint: &MintEvent) -> Option<Address> {
+        self.provider(
+            &self.mints,
+            &mint.transaction_hash,
+            mint.pair_address,
+            mint.caller_address,
+        )
+    }
+
+    fn burn_provider(&self, burn: &BurnEvent) -> Option<Address> {
+        self.provider(
+            &self.burns,
+            &burn.transaction_hash,
+            burn.pair_address,
+            burn.caller_address,
+        )
+    }
+
+    fn provider(
+        &self,
+        providers: &HashMap<(String, Address), Address>,
+        transaction_hash: &str,
+        pair_address: Address,
+        caller: Address,
+    ) -> Option<Address> {
+        providers
+            .get(&(transaction_hash.to_string(), pair_address))
+            .copied()
+            .or((!self.routers.contains(&caller)).then_some(caller))
+    }
+
+    fn clear(&mut self) {
+        self.mints.clear();
+        self.burns.clear();
+    }
+}
+
+// Swaps, mints and burns of one block.
+type BlockEvents<'a> = (Vec<&'a SwapEvent>, Vec<&'a MintEvent>, Vec<&'a BurnEvent>);
+
+/// Flags sandwiches, cyclic arbitrage and JIT liquidity, block by block, from the swaps,
+/// mints and burns of known pairs. Profits are estimates in the named token and, when
+/// priced, in USD at the block's swap prices.
+pub fn detect_mev(
+    swaps: &[SwapEvent],
+    mints: &[MintEvent],
+    burns: &[BurnEvent],
+    providers: &LpProviders,
+    pairs: &HashMap<Address, UniswapV2TokenPair>,
+) -> Result<Vec<MevEvent>> {
+    let mut blocks: BTreeMap<u64, BlockEvents> = BTreeMap::new();
+    for swap in swaps {
+        blocks.entry(swap.block_number).or_default().0.push(swap);
+    }
+    for mint in mints {
+        blocks.entry(mint.block_number).or_default().1.push(mint);
+    }
+    for burn in burns {
+        blocks.entry(burn.block_number).or_default().2.push(burn);
+    }
+
+    let mut events = Vec::new();
+    for (_, (mut swaps, mut mints, mut burns)) in blocks {
+        swaps.sort_by_key(|swap| swap.log_index);
+        mints.sort_by_key(|mint| mint.log_index);
+        burns.sort_by_key(|burn| burn.log_index);
+        let usd_prices = usd_prices(&swaps, pairs);
+        events.extend(detect_sandwiches(&swaps, pairs, &usd_prices));
+        events.extend(detect_arbitrage(&swaps, pairs, &usd_prices)?);
+        events.extend(detect_jit_liquidity(
+            &swaps,
+            &mints,
+            &burns,
+            providers,
+            pairs,
+            &usd_prices,
+        ));
+    }
+    Ok(events)
+}
+
+/// Collects the events of the live block and runs `detect_mev` once the next block starts.
+#[derive(Debug, Default)]
+pub struct MevBlockDetector {
+    block_number: Option<u64>,
+    swaps: Vec<SwapEvent>,
+    mints: Vec<MintEvent>,
+    burns: Vec<BurnEvent>,
+    providers: LpProviders,
+}
+
+impl MevBlockDetector {
+    pub fn new(routers: HashSet<Address>) -> Self {
+        Self {
+            providers: LpProviders::new(routers),
+            ..Default::default()
+        }
+    }
+
+    /// Call with the block of every new log, before adding its events.
+    pub fn start_block(
+        &mut self,
+        block_number: u64,
+        pairs: &HashMap<Address, UniswapV2TokenPair>,
+    ) -> Result<Vec<MevEvent>> {
+        if self.block_number == Some(block_number) {
+            return Ok(Vec::new());
+        }
+        let events = self.flush(pairs)?;
+        self.block_number = Some(block_number);
+        Ok(events)
+    }
+
+    /// Detects on the collected block, e.g. when the subscription ends.
+    pub fn flush(&mut self, pairs: &HashMap<Address, UniswapV2TokenPair>) -> Result<Vec<MevEvent>> {
+        let events = detect_mev(
+            &self.swaps,
+            &self.mints,
+            &self.burns,
+            &self.providers,
+            pairs,
+        )?;
+        self.swaps.clear();
+        self.mints.clear();
+        self.burns.clear();
+        self.providers.clear();
+        Ok(events)
+    }
+
+    pub fn observe_log(&mut self, log: &Log) -> Result<()> {
+        self.providers.observe_log(log)
+    }
+
+    pub fn add_swap(&mut self, swap: &SwapEvent) {
+        self.swaps.push(swap.clone());
+    }
+
+    pub fn add_mint(&mut self, mint: &MintEvent) {
+        self.mints.push(mint.clone());
+    }
+
+    pub fn add_burn(&mut self, burn: &BurnEvent) {
+        self.burns.push(burn.clone());
+    }
+}
+
+fn usd_prices(
+    swaps: &[&SwapEvent],
+    pairs: &HashMap<Address, UniswapV2TokenPair>,
+) -> HashMap<Address, f64> {
+    let mut prices = HashMap::new();
+    for swap in swaps {
+        let Some(pair) = pairs.get(&swap.pair_address) else {
+            continue;
+        };
+        if let Some(price) = swap.token0_price_usd {
+            prices.insert(pair.token0.address, price);
+        }
+        if let Some(price) = swap.token1_price_usd {
+            prices.insert(pair.token1.address, price);
+        }
+    }
+    prices
+}
+
+/// Same actor trades into a pair before a victim swapping the same way and sells about the
+/// same amount back right after. The profit is in the front-run's input token.
+fn detect_sandwiches(
+    swaps: &[&SwapEvent],
+    pairs: &HashMap<Address, UniswapV2TokenPair>,
+    usd_prices: &HashMap<Address, f64>,
+) -> Vec<MevEvent> {
+    let mut events = Vec::new();
+    let mut used = HashSet::new();
+    for (i, front) in swaps.iter().enumerate() {
+        let Some(pair) = pairs.get(&front.pair_address) else {
+            continue;
+        };
+        if front.direction == SwapDirection::Unknown || used.contains(&i) {
+            continue;
+        }
+        let same_pair = |swap: &&SwapEvent| swap.pair_address == front.pair_address;
+        let back = swaps.iter().enumerate().skip(i + 1).find(|(j, back)| {
+            !used.contains(j)
+                && same_pair(back)
+                && back.transaction_hash != front.transaction_hash
+                && actor(back) == actor(front)
+                && back.direction != front.direction
+                && back.direction != SwapDirection::Unknown
+                && is_close(back.amount_in, front.amount_out)
+        });
+        let Some((j, back)) = back else {
+            continue;
+        };
+        let victims = swaps[i + 1..j]
+            .iter()
+            .filter(|victim| {
+                same_pair(victim)
+                    && victim.direction == front.direction
+                    && victim.transaction_hash != front.transaction_hash
+                    && victim.transaction_hash != back.transaction_hash
+                    && actor(victim) != actor(front)
+            })
+            .collect::<Vec<_>>();
+        if victims.is_empty() {
+            continue;
+        }
+        used.extend([i, j]);
+
+        let (profit_token, decimals) = match front.direction {
+            SwapDirection::Token0ToToken1 => (pair.token0.address, pair.token0.decimals),
+            _ => (pair.token1.address, pair.token1.decimals),
+        };
+        let profit = to_decimal_amount(back.amount_out, decimals)
+            - to_decimal_amount(front.amount_in, decimals);
+        let transaction_hashes = std::iter::once(&front.transaction_hash)
+            .chain(victims.iter().map(|victim| &victim.transaction_hash))
+            .chain(std::iter::once(&back.transaction_hash))
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(">");
+        events.push(MevEvent {
+            chain_id: front.chain_id,
+            event_type: "Mev".to_string(),
+            kind: MevKind::Sandwich,
+            actor: actor(front),
+            pair_address: front.pair_address,
+            transaction_hashes,
+            victim_count: victims.len(),
+            profit_token,
+            profit,
+            profit_usd: usd_prices.get(&profit_token).map(|price| profit * price),
+            block_number: front.block_number,
+            block_timestamp: front.block_timestamp,
+        });
+    }
+    events
+}
+
+// The back-run sells within 10% of what the front-run bought.
+fn is_close(a: alloy::primitives::U256, b: alloy::primitives::U256) -> bool {
+    let (a, b) = (f64::from(a), f64::from(b));
+    b > 0.0 && (a - b).abs() <= b * 0.1
+}
+
+/// A route of two or more hops in one transaction that ends in the token it started with.
+fn detect_arbitrage(
+    swaps: &[&SwapEvent],
+    pairs: &HashMap<Address, UniswapV2TokenPair>,
+    usd_prices: &HashMap<Address, f64>,
+) -> Result<Vec<MevEvent>> {
+    let swaps = swaps.iter().map(|swap| (*swap).clone()).collect::<Vec<_>>();
+    let routes = transform_route_event(&swaps, pairs)?;
+    Ok(routes
+        .into_iter()
+        .filter(|route| route.hop_count >= 2 && route.token_in == route.token_out)
+        .map(|route| {
+            let profit = decimal_to_f64(&(&route.token_out_amounts - &route.token_in_amounts));
+            let pair_address = route
+                .pairs
+                .split('>')
+                .next()
+                .and_then(|pair| pair.parse().ok())
+                .unwrap_or_default();
+            MevEvent {
+                chain_id: route.chain_id,
+                event_type: "Mev".to_string(),
+                kind: MevKind::Arbitrage,
+                actor: route.sender_address,
+                pair_address,
+                transaction_hashes: route.transaction_hash,
+                victim_count: 0,
+                profit_token: route.token_in,
+                profit,
+                profit_usd: usd_prices.get(&route.token_in).map(|price| profit * price),
+                block_number: route.block_number,
+                block_timestamp: route.block_timestamp,
+            }
+        })
+        .collect())
+}
+
+/// Liquidity minted right before another transaction's swap on the pair and burned right
+/// after, by the same LP provider. The profit is the position's change valued in token0.
+fn detect_jit_liquidity(
+    swaps: &[&SwapEvent],
+    mints: &[&MintEvent],
+    burns: &[&BurnEvent],
+    providers: &LpProviders,
+    pairs: &HashMap<Address, UniswapV2TokenPair>,
+    usd_prices: &HashMap<Address, f64>,
+) -> Vec<MevEvent> {
+    let mut events = Vec::new();
+    let mut used_burns = HashSet::new();
+    for mint in mints {
+        let Some(pair) = pairs.get(&mint.pair_address) else {
+            continue;
+        };
+        let Some(provider) = providers.mint_provider(mint) else {
+            continue;
+        };
+        let Some((b, burn)) = burns.iter().enumerate().find(|(b, burn)| {
+            !used_burns.contains(b)
+                && burn.pair_address == mint.pair_address
+                && burn.log_index > mint.log_index
+                && providers.burn_provider(burn) == Some(provider)
+                && burn.transaction_hash != mint.transaction_hash
+        }) else {
+            continue;
+        };
+        let swapped = swaps
+            .iter()
+            .filter(|swap| {
+                swap.pair_address == mint.pair_address
+                    && swap.log_index > mint.log_index
+                    && swap.log_index < burn.log_index
+                    && swap.transaction_hash != mint.transaction_hash
+                    && swap.transaction_hash != burn.transaction_hash
+            })
+            .collect::<Vec<_>>();
+        let Some(last_swap) = swapped.last() else {
+            continue;
+        };
+        used_burns.insert(b);
+
+        let delta0 = to_decimal_amount(burn.token0_amount, pair.token0.decimals)
+            - to_decimal_amount(mint.token0_amount, pair.token0.decimals);
+        let delta1 = to_decimal_amount(burn.token1_amount, pair.token1.decimals)
+            - to_decimal_amount(mint.token1_amount, pair.token1.decimals);
+        // token0 per token1 at the last swap inside the position.
+        let price1 =
+            decimal_to_f64(&last_swap.token0_amounts) / decimal_to_f64(&last_swap.token1_amounts);
+        let profit = delta0
+            + if price1.is_finite() {
+                delta1 * price1
+            } else {
+                0.0
+            };
+        let transaction_hashes = std::iter::once(&mint.transaction_hash)
+            .chain(swapped.iter().map(|swap| &swap.transaction_hash))
+            .chain(std::iter::once(&burn.transaction_hash))
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(">");
+        events.push(MevEvent {
+            chain_id: mint.chain_id,
+            event_type: "Mev".to_string(),
+            kind: MevKind::JitLiquidity,
+            actor: provider,
+            pair_address: mint.pair_address,
+            transaction_hashes,
+            victim_count: swapped.len(),
+            profit_token: pair.token0.address,
+            profit,
+            profit_usd: usd_prices
+                .get(&pair.token0.address)
+                .map(|price| profit * price),
+            block_number: mint.block_number,
+            block_timestamp: mint.block_timestamp,
+        });
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{USDC, USDC_WETH, usdc_weth};
+    use alloy::primitives::{B256, U256, address};
+    use alloy::sol_types::SolEvent;
+
+    const SUSHI_PAIR: Address = address!("0x397FF1542f962076d0BFE58eA045FfA2d347ACa0");
+    const BOT: Address = address!("0x00000000000000000000000000000000000000b0");
+
+    fn pairs() -> HashMap<Address, UniswapV2TokenPair> {
+        let sushi = UniswapV2TokenPair {
+            pair_address: SUSHI_PAIR,
+            ..usdc_weth()
+        };
+        HashMap::from([(USDC_WETH, usdc_weth()), (SUSHI_PAIR, sushi)])
+    }
+
+    // USDC -> WETH when `buy`, amounts in whole tokens.
+    fn swap(
+        tx: &str,
+        log_index: u64,
+        caller: Address,
+        buy: bool,
+        usdc: u64,
+        weth: u64,
+    ) -> SwapEvent {
+        SwapEvent {
+            caller_address: caller,
+            receiver_address: caller,
+            token0_price_usd: Some(1.0),
+            block_number: 100,
+            transaction_hash: tx.to_string(),
+            log_index,
+            ..crate::test_util::swap(&usdc_weth(), buy, usdc as f64, weth as f64)
+        }
+    }
+
+    #[test]
+    fn test_detect_sandwich_and_jit() -> Result<()> {
+        let user = address!("0x00000000000000000000000000000000000000aa");
+        let swaps = [
+            swap("0x01", 1, BOT, true, 10000, 4),
+            swap("0x02", 5, user, true, 5000, 2),
+            swap("0x03", 9, BOT, false, 10100, 4),
+            // USDC -> WETH here, WETH -> USDC on the other pair, in one transaction.
+            SwapEvent {
+                receiver_address: SUSHI_PAIR,
+                ..swap("0x05", 20, BOT, true, 1000, 1)
+            },
+            SwapEvent {
+                pair_address: SUSHI_PAIR,
+                ..swap("0x05", 21, BOT, false, 1010, 1)
+            },
+        ];
+        let mint = MintEvent {
+            pair_address: USDC_WETH,
+            caller_address: BOT,
+            token0_amount: U256::from(1_000_000_000u64),
+            token1_amount: U256::ZERO,
+            block_number: 100,
+            transaction_hash: "0x00".to_string(),
+            log_index: 0,
+            ..Default::default()
+        };
+        let burn = BurnEvent {
+            pair_address: USDC_WETH,
+            caller_address: BOT,
+            token0_amount: U256::from(1_003_000_000u64),
+            token1_amount: U256::ZERO,
+            block_number: 100,
+            transaction_hash: "0x04".to_string(),
+            log_index: 12,
+            ..Default::default()
+        };
+
+        let providers = LpProviders::default();
+        let events = detect_mev(
+            &swaps,
+            std::slice::from_ref(&mint),
+            std::slice::from_ref(&burn),
+            &providers,
+            &pairs(),
+        )?;
+        let sandwich = events.iter().find(|e| e.kind == MevKind::Sandwich).unwrap();
+        assert_eq!(sandwich.transaction_hashes, "0x01>0x02>0x03");
+        assert_eq!(sandwich.victim_count, 1);
+        assert_eq!(sandwich.profit_token, USDC);
+        assert!((sandwich.profit - 100.0).abs() < 1e-9);
+        assert_eq!(sandwich.profit_usd, Some(sandwich.profit));
+
+        let jit = events
+            .iter()
+            .find(|e| e.kind == MevKind::JitLiquidity)
+            .unwrap();
+        assert_eq!(jit.victim_count, 3);
+        assert!((jit.profit - 3.0).abs() < 1e-9);
+        assert!(jit.to_influx_line().contains("kind=jit_liquidity"));
+
+        let arbitrage = events
+            .iter()
+            .find(|e| e.kind == MevKind::Arbitrage)
+            .unwrap();
+        assert_eq!(arbitrage.pair_address, USDC_WETH);
+        assert!((arbitrage.profit - 10.0).abs() < 1e-9);
+
+        // Without the victim there is nothing to sandwich.
+        let events = detect_mev(
+            &[swaps[0].clone(), swaps[2].clone()],
+            &[],
+            &[],
+            &providers,
+            &pairs(),
+        )?;
+        assert!(events.is_empty());
+
+        // Through a router the caller is the router for everyone; the LP transfers decide.
+        let router = address!("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D");
+        let (mint_tx, burn_tx) = (B256::with_last_byte(0xa0), B256::with_last_byte(0xa4));
+        let mint = MintEvent {
+            caller_address: router,
+            transaction_hash: mint_tx.to_string(),
+            ..mint
+        };
+        let burn = BurnEvent {
+            caller_address: router,
+            transaction_hash: burn_tx.to_string(),
+            ..burn
+        };
+        let transfer = |tx, from, to| Log {
+            inner: alloy::primitives::Log {
+                address: USDC_WETH,
+                data: UniswapV2Pair::Transfer {
+                    from,
+                    to,
+                    value: U256::from(1),
+                }
+                .encode_log_data(),
+            },
+            transaction_hash: Some(tx),
+            ..Default::default()
+        };
+        let jit_count = |providers: &LpProviders| -> Result<usize> {
+            let events = detect_mev(
+                &swaps,
+                std::slice::from_ref(&mint),
+                std::slice::from_ref(&burn),
+                providers,
+                &pairs(),
+            )?;
+            Ok(events
+                .iter()
+                .filter(|e| e.kind == MevKind::JitLiquidity)
+                .count())
+        };
+        let mut providers = LpProviders::new(HashSet::from([router]));
+        assert_eq!(jit_count(&providers)?, 0);
+        providers.observe_log(&transfer(mint_tx, Address::ZERO, BOT))?;
+        providers.observe_log(&transfer(burn_tx, user, USDC_WETH))?;
+        assert_eq!(jit_count(&providers)?, 0);
+        providers.observe_log(&transfer(burn_tx, BOT, USDC_WETH))?;
+        assert_eq!(jit_count(&providers)?, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,442 @@
+use crate::extract_event::{
+    BURN_EVENT_SIGNATURE, MINT_EVENT_SIGNATURE, TRANSFER_EVENT_SIGNATURE, UniswapV2Pair,
+    UniswapV2TokenPair,
+};
+use crate::transform_event::{decode_log, serialize_timestamp, serialize_u256, to_decimal_amount};
+use crate::transform_liquidity::LiquidityEvent;
+use alloy::primitives::{Address, U256};
+use alloy::rpc::types::eth::Log;
+use eyre::Result;
+use std::collections::HashMap;
+
+// LP tokens of Uniswap V2 pairs always have 18 decimals.
+const LP_DECIMALS: u8 = 18;
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct PositionEvent {
+    pub chain_id: u64,
+    pub event_type: String,
+    /// `Mint`, `Burn`, or `Transfer` when LP tokens move between holders.
+    pub trigger: String,
+    pub provider_address: Address,
+    pub pair_address: Address,
+    #[serde(serialize_with = "serialize_u256")]
+    pub lp_balance: U256,
+    pub share: f64,
+    // Cost basis: amounts deposited, reduced pro rata when LP tokens leave the position.
+    pub deposited_token0: f64,
+    pub deposited_token1: f64,
+    pub current_token0: f64,
+    pub current_token1: f64,
+    // Position and held deposits, both valued in token0 at the pool price.
+    pub value_token0: f64,
+    pub hold_value_token0: f64,
+    /// `value / hold_value - 1`, empty without a cost basis.
+    pub impermanent_loss: Option<f64>,
+    pub block_number: u64,
+    pub transaction_hash: String,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub block_timestamp: u64,
+    pub value_usd: Option<f64>,
+    pub hold_value_usd: Option<f64>,
+}
+
+impl PositionEvent {
+    pub fn to_influx_line(&self) -> String {
+        let optional_fields: String = [
+            ("impermanent_loss", self.impermanent_loss),
+            ("value_usd", self.value_usd),
+            ("hold_value_usd", self.hold_value_usd),
+        ]
+        .iter()
+        .filter_map(|(name, value)| value.map(|value| format!(",{}={}", name, value)))
+        .collect();
+        format!(
+            "lp_position,chain_id={},pair_address={},provider_address={},trigger={} lp_balance=\"{}\",share={},deposited_token0={},deposited_token1={},current_token0={},current_token1={},value_token0={},hold_value_token0={},block_number={}i{} {}",
+            self.chain_id,
+            self.pair_address,
+            self.provider_address,
+            self.trigger,
+            self.lp_balance,
+            self.share,
+            self.deposited_token0,
+            self.deposited_token1,
+            self.current_token0,
+            self.current_token1,
+            self.value_token0,
+            self.hold_value_token0,
+            self.block_number,
+            optional_fields,
+            self.block_timestamp
+        )
+    }
+
+    /// Values the position and the held deposits in USD from either token's price.
+    pub fn price(&mut self, token0_price_usd: Option<f64>, token1_price_usd: Option<f64>) {
+        // token0 per token1 at the pool price, from the position's own amounts.
+        let pool_price =
+            (self.current_token1 > 0.0).then(|| self.current_token0 / self.current_token1);
+        let usd_per_token0 = token0_price_usd.or_else(|| {
+            token1_price_usd
+                .zip(pool_price)
+                .map(|(price, pool)| price / pool)
+        });
+        self.value_usd = usd_per_token0.map(|price| self.value_token0 * price);
+        self.hold_value_usd = usd_per_token0.map(|price| self.hold_value_token0 * price);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Pool {
+    token0_reserve: f64,
+    token1_reserve: f64,
+    total_supply: U256,
+    token0_price_usd: Option<f64>,
+    token1_price_usd: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct Position {
+    balance: U256,
+    deposited_token0: f64,
+    deposited_token1: f64,
+}
+
+impl Position {
+    // Moves `value` LP tokens out and returns the cost basis that goes with them.
+    fn take(&mut self, value: U256) -> (f64, f64) {
+        if self.balance.is_zero() {
+            return (0.0, 0.0);
+        }
+        let taken = value.min(self.balance);
+        let fraction =
+            to_decimal_amount(taken, LP_DECIMALS) / to_decimal_amount(self.balance, LP_DECIMALS);
+        let basis = (
+            self.deposited_token0 * fraction,
+            self.deposited_token1 * fraction,
+        );
+        self.balance -= taken;
+        self.deposited_token0 -= basis.0;
+        self.deposited_token1 -= basis.1;
+        basis
+    }
+}
+
+/// LP positions per provider, rebuilt from the pair token's Transfer logs.
+///
+/// The provider of a Mint is the receiver of the last LP mint in the same transaction, the
+/// provider of a Burn the sender of the LP tokens returned to the pair. Transfers between
+/// holders move the position and its cost basis when `follow_transfers` is set. Pool
+/// reserves, supply and prices come from the pair's liquidity series via `update_pool`.
+#[derive(Debug)]
+pub struct PositionTracker {
+    chain_id: u64,
+    follow_transfers: bool,
+    pools: HashMap<Address, Pool>,
+    positions: HashMap<(Address, Address), Position>,
+    // (transaction hash, provider) of the latest LP mint / return to the pair, per pair.
+    pending_mint: HashMap<Address, (String, Address)>,
+    pending_burn: HashMap<Address, (String, Address)>,
+}
+
+impl PositionTracker {
+    pub fn new(chain_id: u64, follow_transfers: bool) -> Self {
+        Self {
+            chain_id,
+            follow_transfers,
+            pools: HashMap::new(),
+            positions: HashMap::new(),
+            pending_mint: HashMap::new(),
+            pending_burn: HashMap::new(),
+        }
+    }
+
+    pub fn update_pool(&mut self, liquidity: &[LiquidityEvent]) {
+        for event in liquidity {
+            self.pools.insert(
+                event.pair_address,
+                Pool {
+                    token0_reserve: event.token0_reserve,
+                    token1_reserve: event.token1_reserve,
+                    total_supply: event.total_supply,
+                    token0_price_usd: event.token0_price_usd,
+                    token1_price_usd: event.token1_price_usd,
+                },
+            );
+        }
+    }
+
+    /// Applies a Transfer, Mint or Burn log of `pair` and returns the changed positions.
+    pub fn apply_log(
+        &mut self,
+        log: &Log,
+        pair: &UniswapV2TokenPair,
+    ) -> Result<Vec<PositionEvent>> {
+        let Some(&signature) = log.topics().first() else {
+            return Ok(Vec::new());
+        };
+        let pair_address = pair.pair_address;
+        let transaction_hash = log.transaction_hash.unwrap_or_default().to_string();
+        let mut providers = Vec::new();
+        let mut trigger = "Transfer";
+
+        match signature {
+            sig if sig == TRANSFER_EVENT_SIGNATURE => {
+                let transfer = decode_log::<UniswapV2Pair::Transfer>(log)?;
+                let (from, to, value) = (transfer.from, transfer.to, transfer.value);
+                if from == Address::ZERO && to != Address::ZERO {
+                    self.positions
+                        .entry((pair_address, to))
+                        .or_default()
+                        .balance += value;
+                    self.pending_mint
+                        .insert(pair_address, (transaction_hash.clone(), to));
+                } else if to == pair_address {
+                    // Returned for burning; the basis leaves with the Burn that follows.
+                    self.positions
+                        .entry((pair_address, from))
+                        .or_default()
+                        .take(value);
+                    self.pending_burn
+                        .insert(pair_address, (transaction_hash.clone(), from));
+                } else if self.follow_transfers
+                    && from != Address::ZERO
+                    && to != Address::ZERO
+                    && from != pair_address
+                {
+                    let (basis0, basis1) = self
+                        .positions
+                        .entry((pair_address, from))
+                        .or_default()
+                        .take(value);
+                    let position = self.positions.entry((pair_address, to)).or_default();
+                    position.balance += value;
+                    position.deposited_token0 += basis0;
+                    position.deposited_token1 += basis1;
+                    providers.extend([from, to]);
+                }
+            }
+            sig if sig == MINT_EVENT_SIGNATURE => {
+                let mint = decode_log::<UniswapV2Pair::Mint>(log)?;
+                if let Some(provider) =
+                    take_pending(&mut self.pending_mint, pair_address, &transaction_hash)
+                {
+                    let position = self.positions.entry((pair_address, provider)).or_default();
+                    position.deposited_token0 +=
+                        to_decimal_amount(mint.amount0, pair.token0.decimals);
+                    position.deposited_token1 +=
+                        to_decimal_amount(mint.amount1, pair.token1.decimals);
+                    providers.push(provider);
+                    trigger = "Mint";
+                }
+            }
+            sig if sig == BURN_EVENT_SIGNATURE => {
+                if let Some(provider) =
+                    take_pending(&mut self.pending_burn, pair_address, &transaction_hash)
+                {
+                    providers.push(provider);
+                    trigger = "Burn";
+                }
+            }
+            _ => {}
+        }
+
+        Ok(providers
+            .into_iter()
+            .map(|provider| {
+                let mut event = self.event(pair_address, provider, trigger);
+                event.block_number = log.block_number.unwrap_or_default();
+                event.transaction_hash = transaction_hash.clone();
+                event.block_timestamp = log.block_timestamp.unwrap_or_default();
+                event
+            })
+            .collect())
+    }
+
+    fn event(&self, pair_address: Address, provider: Address, trigger: &str) -> PositionEvent {
+        let position = &self.positions[&(pair_address, provider)];
+        let pool = self.pools.get(&pair_address);
+        let share = pool
+            .filter(|pool| !pool.total_supply.is_zero())
+            .map_or(0.0, |pool| {
+                to_decimal_amount(position.balance, LP_DECIMALS)
+                    / to_decimal_amount(pool.total_supply, LP_DECIMALS)
+            });
+        let (current_token0, current_token1) = pool.map_or((0.0, 0.0), |pool| {
+            (pool.token0_reserve * share, pool.token1_reserve * share)
+        });
+        let pool_price = pool
+            .filter(|pool| pool.token1_reserve > 0.0)
+            .map_or(0.0, |pool| pool.token0_reserve / pool.token1_reserve);
+        let value_token0 = current_token0 + current_token1 * pool_price;
+        let hold_value_token0 = position.deposited_token0 + position.deposited_token1 * pool_price;
+
+        let mut event = PositionEvent {
+            chain_id: self.chain_id,
+            event_type: "Position".to_string(),
+            trigger: trigger.to_string(),
+            provider_address: provider,
+            pair_address,
+            lp_balance: position.balance,
+            share,
+            deposited_token0: position.deposited_token0,
+            deposited_token1: position.deposited_token1,
+            current_token0,
+            current_token1,
+            value_token0,
+            hold_value_token0,
+            impermanent_loss: (hold_value_token0 > 0.0)
+                .then(|| value_token0 / hold_value_token0 - 1.0),
+            ..Default::default()
+        };
+        if let Some(pool) = pool {
+            event.price(pool.token0_price_usd, pool.token1_price_usd);
+        }
+        event
+    }
+}
+
+fn take_pending(
+    pending: &mut HashMap<Address, (String, Address)>,
+    pair_address: Address,
+    transaction_hash: &str,
+) -> Option<Address> {
+    pending
+        .remove(&pair_address)
+        .filter(|(tx, _)| tx == transaction_hash)
+        .map(|(_, provider)| provider)
+}
+
+/// Prices positions tracked before the USD prices were known, e.g. in batch mode, with the
+/// latest priced liquidity row of their pair at or before their block.
+pub fn price_positions(positions: &mut [PositionEvent], liquidity: &[LiquidityEvent]) {
+    let mut by_pair: HashMap<Address, Vec<&LiquidityEvent>> = HashMap::new();
+    for event in liquidity {
+        by_pair.entry(event.pair_address).or_default().push(event);
+    }
+    for events in by_pair.values_mut() {
+        events.sort_by_key(|event| event.block_number);
+    }
+    for position in positions {
+        let Some(events) = by_pair.get(&position.pair_address) else {
+            continue;
+        };
+        let end = events.partition_point(|event| event.block_number <= position.block_number);
+        if let Some(event) = end.checked_sub(1).map(|i| events[i]) {
+            position.price(event.token0_price_usd, event.token1_price_usd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{USDC_WETH, pair, pair_log, token};
+    use alloy::primitives::address;
+
+    fn lp_transfer(from: Address, to: Address, value: u128) -> UniswapV2Pair::Transfer {
+        UniswapV2Pair::Transfer {
+            from,
+            to,
+            value: U256::from(value),
+        }
+    }
+
+    #[test]
+    fn test_position_tracker() -> Result<()> {
+        let pair_address = USDC_WETH;
+        let provider = address!("0x00000000000000000000000000000000000000aa");
+        let fee_to = address!("0x00000000000000000000000000000000000000fe");
+        let token = |decimals| token(Address::ZERO, decimals, "");
+        let pair = pair(pair_address, token(18), token(18));
+        let pool = |token0_reserve, token1_reserve, total_supply: u128| LiquidityEvent {
+            pair_address,
+            token0_reserve,
+            token1_reserve,
+            total_supply: U256::from(total_supply),
+            token0_price_usd: Some(1.0),
+            ..Default::default()
+        };
+        let mut tracker = PositionTracker::new(1, false);
+        let e18 = 1_000_000_000_000_000_000u128;
+
+        // Provider deposits 100 token0 + 100 token1 for the whole supply.
+        let mint = UniswapV2Pair::Mint {
+            sender: Address::ZERO,
+            amount0: U256::from(100 * e18),
+            amount1: U256::from(100 * e18),
+        };
+        tracker.apply_log(
+            &pair_log(pair_address, lp_transfer(Address::ZERO, fee_to, 1), 1, 12),
+            &pair,
+        )?;
+        tracker.apply_log(
+            &pair_log(
+                pair_address,
+                lp_transfer(Address::ZERO, provider, 100 * e18),
+                1,
+                12,
+            ),
+            &pair,
+        )?;
+        tracker.update_pool(&[pool(100.0, 100.0, 100 * e18 + 1)]);
+        let events = tracker.apply_log(&pair_log(pair_address, mint, 1, 12), &pair)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].trigger, "Mint");
+        assert_eq!(events[0].provider_address, provider);
+        assert_eq!(events[0].deposited_token0, 100.0);
+        assert!(events[0].impermanent_loss.unwrap().abs() < 1e-9);
+
+        // Price of token1 quadruples: 50 token0 and 200 token1 in the pool. Holding is
+        // worth 125 token0, the position 100, so IL is -20%. Then half of it is burned.
+        tracker.update_pool(&[pool(50.0, 200.0, 100 * e18 + 1)]);
+        tracker.apply_log(
+            &pair_log(
+                pair_address,
+                lp_transfer(provider, pair_address, 50 * e18),
+                2,
+                24,
+            ),
+            &pair,
+        )?;
+        tracker.apply_log(
+            &pair_log(
+                pair_address,
+                lp_transfer(pair_address, Address::ZERO, 50 * e18),
+                2,
+                24,
+            ),
+            &pair,
+        )?;
+        tracker.update_pool(&[pool(25.0, 100.0, 50 * e18 + 1)]);
+        let burn = UniswapV2Pair::Burn {
+            sender: Address::ZERO,
+            amount0: U256::from(25 * e18),
+            amount1: U256::from(100 * e18),
+            to: provider,
+        };
+        let events = tracker.apply_log(&pair_log(pair_address, burn, 2, 24), &pair)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].trigger, "Burn");
+        assert_eq!(events[0].lp_balance, U256::from(50 * e18));
+        assert_eq!(events[0].deposited_token1, 50.0);
+        assert!((events[0].value_token0 - 50.0).abs() < 1e-6);
+        assert!((events[0].hold_value_token0 - 62.5).abs() < 1e-6);
+        assert!((events[0].impermanent_loss.unwrap() + 0.2).abs() < 1e-6);
+        assert!((events[0].value_usd.unwrap() - 50.0).abs() < 1e-6);
+
+        // Without follow_transfers, moving LP tokens to another holder emits nothing.
+        let transfer = lp_transfer(provider, fee_to, e18);
+        assert!(
+            tracker
+                .apply_log(&pair_log(pair_address, transfer, 3, 36), &pair)?
+                .is_empty()
+        );
+        assert!(
+            events[0]
+                .to_influx_line()
+                .starts_with("lp_position,chain_id=1,pair_address=")
+        );
+        Ok(())
+    }
+}
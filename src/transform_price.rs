@@ -0,0 +1,290 @@
+use crate::extract_event::{UniswapV2TokenPair, UniswapV2Tokens};
+use crate::init::PricingCfg;
+use crate::transform_event::{
+    BurnEvent, MintEvent, SwapDirection, SwapEvent, decimal_to_f64, to_decimal_amount,
+};
+use crate::transform_liquidity::LiquidityEvent;
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, U256};
+use alloy::providers::DynProvider;
+use eyre::Result;
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// Tracks USD prices of tokens. Stablecoins are fixed at 1 USD, reference pairs price their
+/// other token against them, and any other pair prices its unknown side from a priced one.
+#[derive(Debug)]
+pub struct UsdPricer {
+    stablecoins: HashSet<Address>,
+    pub reference_pairs: HashMap<Address, UniswapV2TokenPair>,
+    // Tokens of reference pairs; only the reference pairs themselves may move their price.
+    anchors: HashSet<Address>,
+    prices: HashMap<Address, f64>,
+}
+
+impl UsdPricer {
+    pub fn new(cfg: &PricingCfg, reference_pairs: Vec<UniswapV2TokenPair>) -> Result<Self> {
+        let stablecoins = cfg
+            .stablecoins
+            .iter()
+            .map(|s| Address::from_str(s))
+            .collect::<Result<HashSet<_>, _>>()?;
+        let anchors = reference_pairs
+            .iter()
+            .flat_map(|pair| [pair.token0.address, pair.token1.address])
+            .chain(stablecoins.iter().copied())
+            .collect();
+        Ok(Self {
+            stablecoins,
+            reference_pairs: reference_pairs
+                .into_iter()
+                .map(|pair| (pair.pair_address, pair))
+                .collect(),
+            anchors,
+            prices: HashMap::new(),
+        })
+    }
+
+    /// Loads the reference pairs and seeds their prices from the reserves at `block`.
+    pub async fn connect(cfg: &PricingCfg, provider: DynProvider, block: BlockId) -> Result<Self> {
+        let mut reference_pairs = Vec::new();
+        let mut reserve_prices = Vec::new();
+        for pair_address in &cfg.reference_pairs {
+            let tokens =
+                UniswapV2Tokens::new(Address::from_str(pair_address)?, provider.clone()).await?;
+            let (price0, _, _) = tokens.get_price_at(block).await?;
+            reference_pairs.push(UniswapV2TokenPair::from_tokens(&tokens));
+            reserve_prices.push((tokens.pair_address, decimal_to_f64(&price0)));
+        }
+
+        let mut pricer = Self::new(cfg, reference_pairs)?;
+        for (pair_address, price0) in reserve_prices {
+            let pair = pricer.reference_pairs[&pair_address].clone();
+            pricer.observe(&pair, price0);
+        }
+        debug!("Seeded USD prices: {:?}", pricer.prices);
+        Ok(pricer)
+    }
+
+    pub fn price(&self, token: Address) -> Option<f64> {
+        if self.stablecoins.contains(&token) {
+            Some(1.0)
+        } else {
+            self.prices.get(&token).copied()
+        }
+    }
+
+    /// Records a trade price on `pair`, given as token1 per token0.
+    pub fn observe(&mut self, pair: &UniswapV2TokenPair, price0: f64) {
+        if !price0.is_finite() || price0 <= 0.0 {
+            return;
+        }
+        let (token0, token1) = (pair.token0.address, pair.token1.address);
+        let is_reference = self.reference_pairs.contains_key(&pair.pair_address);
+        let may_update = |token: &Address| {
+            !self.stablecoins.contains(token) && (is_reference || !self.anchors.contains(token))
+        };
+        let (update0, update1) = (may_update(&token0), may_update(&token1));
+
+        if let Some(usd1) = self.price(token1).filter(|_| update0) {
+            self.prices.insert(token0, price0 * usd1);
+        } else if let Some(usd0) = self.price(token0).filter(|_| update1) {
+            self.prices.insert(token1, usd0 / price0);
+        }
+    }
+
+    pub fn price_swap(&mut self, swap: &mut SwapEvent, pair: &UniswapV2TokenPair) {
+        if swap.direction != SwapDirection::Unknown {
+            self.observe(pair, swap_price(swap));
+        }
+        swap.token0_price_usd = self.price(pair.token0.address);
+        swap.token1_price_usd = self.price(pair.token1.address);
+        swap.amount_usd = swap
+            .token0_price_usd
+            .map(|price| decimal_to_f64(&swap.token0_amounts) * price)
+            .or_else(|| {
+                swap.token1_price_usd
+                    .map(|price| decimal_to_f64(&swap.token1_amounts) * price)
+            });
+    }
+
+    pub fn price_mint(&self, mint: &mut MintEvent, pair: &UniswapV2TokenPair) {
+        (
+            mint.token0_price_usd,
+            mint.token1_price_usd,
+            mint.amount_usd,
+        ) = self.price_liquidity(mint.token0_amount, mint.token1_amount, pair);
+    }
+
+    pub fn price_burn(&self, burn: &mut BurnEvent, pair: &UniswapV2TokenPair) {
+        (
+            burn.token0_price_usd,
+            burn.token1_price_usd,
+            burn.amount_usd,
+        ) = self.price_liquidity(burn.token0_amount, burn.token1_amount, pair);
+    }
+
+    pub fn price_reserves(&self, liquidity: &mut LiquidityEvent, pair: &UniswapV2TokenPair) {
+        (
+            liquidity.token0_price_usd,
+            liquidity.token1_price_usd,
+            liquidity.tvl_usd,
+        ) = self.price_liquidity(liquidity.reserve0, liquidity.reserve1, pair);
+    }
+
+    fn price_liquidity(
+        &self,
+        amount0: U256,
+        amount1: U256,
+        pair: &UniswapV2TokenPair,
+    ) -> (Option<f64>, Option<f64>, Option<f64>) {
+        let price0 = self.price(pair.token0.address);
+        let price1 = self.price(pair.token1.address);
+        let value0 = price0.map(|price| to_decimal_amount(amount0, pair.token0.decimals) * price);
+        let value1 = price1.map(|price| to_decimal_amount(amount1, pair.token1.decimals) * price);
+        // Both sides are deposited at the pool ratio, so one known side values the other.
+        let amount_usd = match (value0, value1) {
+            (Some(value0), Some(value1)) => Some(value0 + value1),
+            (Some(value), None) | (None, Some(value)) => Some(value * 2.0),
+            (None, None) => None,
+        };
+        (price0, price1, amount_usd)
+    }
+
+    /// Prices a batch in block order so each event sees the prices of its own block.
+    /// `reference_swaps` only move prices and are not written anywhere.
+    pub fn price_events(
+        &mut self,
+        reference_swaps: &[SwapEvent],
+        swaps: &mut [SwapEvent],
+        mints: &mut [MintEvent],
+        burns: &mut [BurnEvent],
+        liquidity: &mut [LiquidityEvent],
+        pairs: &HashMap<Address, UniswapV2TokenPair>,
+    ) {
+        enum Item {
+            Reference(usize),
+            Swap(usize),
+            Mint(usize),
+            Burn(usize),
+            Liquidity(usize),
+        }
+        let mut items = Vec::new();
+        items.extend(
+            reference_swaps
+                .iter()
+                .enumerate()
+                .map(|(i, e)| (e.block_number, 0, Item::Reference(i))),
+        );
+        items.extend(
+            swaps
+                .iter()
+                .enumerate()
+                .map(|(i, e)| (e.block_number, 1, Item::Swap(i))),
+        );
+        items.extend(
+            mints
+                .iter()
+                .enumerate()
+                .map(|(i, e)| (e.block_number, 2, Item::Mint(i))),
+        );
+        items.extend(
+            burns
+                .iter()
+                .enumerate()
+                .map(|(i, e)| (e.block_number, 2, Item::Burn(i))),
+        );
+        items.extend(
+            liquidity
+                .iter()
+                .enumerate()
+                .map(|(i, e)| (e.block_number, 2, Item::Liquidity(i))),
+        );
+        items.sort_by_key(|(block_number, order, _)| (*block_number, *order));
+
+        for (_, _, item) in items {
+            match item {
+                Item::Reference(i) => {
+                    let swap = &reference_swaps[i];
+                    if let Some(pair) = self.reference_pairs.get(&swap.pair_address).cloned()
+                        && swap.direction != SwapDirection::Unknown
+                    {
+                        self.observe(&pair, swap_price(swap));
+                    }
+                }
+                Item::Swap(i) => {
+                    if let Some(pair) = pairs.get(&swaps[i].pair_address) {
+                        self.price_swap(&mut swaps[i], pair);
+                    }
+                }
+                Item::Mint(i) => {
+                    if let Some(pair) = pairs.get(&mints[i].pair_address) {
+                        self.price_mint(&mut mints[i], pair);
+                    }
+                }
+                Item::Burn(i) => {
+                    if let Some(pair) = pairs.get(&burns[i].pair_address) {
+                        self.price_burn(&mut burns[i], pair);
+                    }
+                }
+                Item::Liquidity(i) => {
+                    if let Some(pair) = pairs.get(&liquidity[i].pair_address) {
+                        self.price_reserves(&mut liquidity[i], pair);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// token1 per token0; USD valuation works in floating point.
+fn swap_price(swap: &SwapEvent) -> f64 {
+    decimal_to_f64(&swap.token1_amounts) / decimal_to_f64(&swap.token0_amounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{USDC, WETH, pair, swap, token};
+    use alloy::primitives::address;
+
+    #[test]
+    fn test_usd_pricer() {
+        let pepe = address!("0x6982508145454Ce325dDbE47a25d4ec3d2311933");
+        let usdc_weth = crate::test_util::usdc_weth();
+        let pepe_weth = pair(
+            address!("0xA43fe16908251ee70EF74718545e4FE6C5cCEc9f"),
+            token(pepe, 18, "PEPE"),
+            token(WETH, 18, "WETH"),
+        );
+        let cfg = PricingCfg {
+            reference_pairs: vec![usdc_weth.pair_address.to_string()],
+            stablecoins: vec![USDC.to_string()],
+        };
+        let mut pricer = UsdPricer::new(&cfg, vec![usdc_weth.clone()]).unwrap();
+
+        // 2500 USDC -> 1 WETH
+        let mut reference = swap(&usdc_weth, true, 2500.0, 1.0);
+        pricer.price_swap(&mut reference, &usdc_weth);
+        assert_eq!(pricer.price(WETH), Some(2500.0));
+        assert_eq!(reference.amount_usd, Some(2500.0));
+
+        // 1e6 PEPE -> 0.01 WETH prices PEPE through WETH, but cannot move WETH itself
+        let mut trade = swap(&pepe_weth, true, 1_000_000.0, 0.01);
+        pricer.price_swap(&mut trade, &pepe_weth);
+        assert_eq!(pricer.price(WETH), Some(2500.0));
+        assert!((trade.token0_price_usd.unwrap() - 0.000025).abs() < 1e-12);
+        assert!((trade.amount_usd.unwrap() - 25.0).abs() < 1e-9);
+
+        let mut mint = MintEvent {
+            pair_address: usdc_weth.pair_address,
+            token0_amount: U256::from(5_000_000_000u64),
+            token1_amount: U256::from(2_000_000_000_000_000_000u64),
+            ..Default::default()
+        };
+        pricer.price_mint(&mut mint, &usdc_weth);
+        assert_eq!(mint.amount_usd, Some(10000.0));
+        assert!(mint.to_influx_line().contains(",amount_usd=10000 "));
+    }
+}
@@ -0,0 +1,327 @@
+use crate::extract_event::{
+    BURN_EVENT_SIGNATURE, MINT_EVENT_SIGNATURE, SWAP_EVENT_SIGNATURE, UniswapV2TokenPair,
+};
+use crate::transform_event::{
+    BurnEvent, MintEvent, SwapEvent, transform_burn_event, transform_mint_event,
+    transform_swap_event,
+};
+use alloy::primitives::{Address, B256};
+use alloy::rpc::types::eth::Log;
+use csv::StringRecord;
+use eyre::Result;
+use log::debug;
+use std::collections::HashMap;
+
+/// A typed record decoded from one log.
+#[derive(Debug)]
+pub enum EventRecord {
+    Mint(MintEvent),
+    Burn(BurnEvent),
+    Swap(Box<SwapEvent>),
+    /// Record of a custom transformer, written to its own CSV file and as an Influx line.
+    Custom(Box<dyn CustomRecord>),
+}
+
+pub trait CustomRecord: std::fmt::Debug + Send {
+    /// Names the output, e.g. `sync` writes `sub_univ2_sync_<chain_id>_<date>.csv`.
+    fn event_type(&self) -> &str;
+    fn to_influx_line(&self) -> String;
+    /// Header and values of the CSV row, usually `load_event::csv_row(self)`.
+    fn to_csv_row(&self) -> Result<(StringRecord, StringRecord)>;
+    /// Identity of the CSV row, see `load_event::RowKey`; the whole row by default.
+    fn row_key(&self) -> Result<String> {
+        let (_, record) = self.to_csv_row()?;
+        Ok(record.iter().collect::<Vec<_>>().join("|"))
+    }
+}
+
+/// Decodes the logs of one event signature (topic0) into typed records.
+pub trait Transformer: Send + Sync {
+    fn signature(&self) -> B256;
+
+    /// Records of `log`, emitted by a pair in `pairs` or a reference pair. Returns nothing
+    /// when the log can't be decoded without metadata that is missing.
+    fn transform(
+        &self,
+        log: &Log,
+        chain_id: u64,
+        pairs: &HashMap<Address, UniswapV2TokenPair>,
+    ) -> Result<Vec<EventRecord>>;
+}
+
+struct MintTransformer;
+struct BurnTransformer;
+struct SwapTransformer;
+
+impl Transformer for MintTransformer {
+    fn signature(&self) -> B256 {
+        MINT_EVENT_SIGNATURE
+    }
+
+    fn transform(
+        &self,
+        log: &Log,
+        chain_id: u64,
+        _pairs: &HashMap<Address, UniswapV2TokenPair>,
+    ) -> Result<Vec<EventRecord>> {
+        let events = transform_mint_event(std::slice::from_ref(log), chain_id)?;
+        Ok(events.into_iter().map(EventRecord::Mint).collect())
+    }
+}
+
+impl Transformer for BurnTransformer {
+    fn signature(&self) -> B256 {
+        BURN_EVENT_SIGNATURE
+    }
+
+    fn transform(
+        &self,
+        log: &Log,
+        chain_id: u64,
+        _pairs: &HashMap<Address, UniswapV2TokenPair>,
+    ) -> Result<Vec<EventRecord>> {
+        let events = transform_burn_event(std::slice::from_ref(log), chain_id)?;
+        Ok(events.into_iter().map(EventRecord::Burn).collect())
+    }
+}
+
+impl Transformer for SwapTransformer {
+    fn signature(&self) -> B256 {
+        SWAP_EVENT_SIGNATURE
+    }
+
+    // Normalized amounts need the token decimals of the pair.
+    fn transform(
+        &self,
+        log: &Log,
+        chain_id: u64,
+        pairs: &HashMap<Address, UniswapV2TokenPair>,
+    ) -> Result<Vec<EventRecord>> {
+        let Some(pair_info) = pairs.get(&log.address()) else {
+            return Ok(Vec::new());
+        };
+        let events = transform_swap_event(
+            std::slice::from_ref(log),
+            chain_id,
+            pair_info.token0.decimals,
+            pair_info.token1.decimals,
+        )?;
+        Ok(events
+            .into_iter()
+            .map(|event| EventRecord::Swap(Box::new(event)))
+            .collect())
+    }
+}
+
+/// Transformers by event signature. The subscriptions and the batch paths fetch the logs
+/// of `signatures` and dispatch each one through `transform`.
+pub struct TransformerRegistry {
+    transformers: HashMap<B256, Box<dyn Transformer>>,
+    // Label Mint, Burn and Swap records with the token symbols and decimals of their pair.
+    token_metadata: bool,
+}
+
+impl TransformerRegistry {
+    /// The Mint, Burn and Swap transformers plus those of `custom_transformers`.
+    pub fn new() -> Self {
+        let mut registry = Self {
+            transformers: HashMap::new(),
+            token_metadata: false,
+        };
+        registry
+            .register(Box::new(MintTransformer))
+            .register(Box::new(BurnTransformer))
+            .register(Box::new(SwapTransformer));
+        for transformer in custom_transformers() {
+            registry.register(transformer);
+        }
+        registry
+    }
+
+    /// Adds `transformer`, replacing the one registered for the same signature.
+    pub fn register(&mut self, transformer: Box<dyn Transformer>) -> &mut Self {
+        self.transformers
+            .insert(transformer.signature(), transformer);
+        self
+    }
+
+    /// `[enrich] token_metadata`: records of known pairs get symbols, decimals and a pair name.
+    pub fn with_token_metadata(mut self, enabled: bool) -> Self {
+        self.token_metadata = enabled;
+        self
+    }
+
+    pub fn signatures(&self) -> Vec<B256> {
+        let mut signatures = self.transformers.keys().copied().collect::<Vec<_>>();
+        signatures.sort();
+        signatures
+    }
+
+    /// Records of `log`, nothing when no transformer handles its signature.
+    pub fn transform(
+        &self,
+        log: &Log,
+        chain_id: u64,
+        pairs: &HashMap<Address, UniswapV2TokenPair>,
+    ) -> Result<Vec<EventRecord>> {
+        let Some(transformer) = log
+            .topics()
+            .first()
+            .and_then(|signature| self.transformers.get(signature))
+        else {
+            debug!("Ignoring log with signature: {:?}", log.topics().first());
+            return Ok(Vec::new());
+        };
+        let mut records = transformer.transform(log, chain_id, pairs)?;
+        if let (true, Some(pair)) = (self.token_metadata, pairs.get(&log.address())) {
+            for record in records.iter_mut() {
+                match record {
+                    EventRecord::Mint(event) => event.set_token_metadata(pair),
+                    EventRecord::Burn(event) => event.set_token_metadata(pair),
+                    EventRecord::Swap(event) => event.set_token_metadata(pair),
+                    EventRecord::Custom(_) => {}
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+impl Default for TransformerRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decoders for events beyond Mint, Burn and Swap. Implement `Transformer` (and
+/// `CustomRecord` for its output) in a module of its own and list it here, or register it
+/// on the registry passed to the `pipeline` entry points from another crate.
+fn custom_transformers() -> Vec<Box<dyn Transformer>> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract_event::{SYNC_EVENT_SIGNATURE, UniswapV2Pair};
+    use crate::load_event::csv_row;
+    use crate::test_util::{USDC_WETH, usdc_weth};
+    use crate::transform_event::decode_log;
+
+    #[derive(Debug, serde::Serialize)]
+    struct SyncRecord {
+        pair_address: Address,
+        reserve0: String,
+        reserve1: String,
+    }
+
+    impl CustomRecord for SyncRecord {
+        fn event_type(&self) -> &str {
+            "sync"
+        }
+
+        fn to_influx_line(&self) -> String {
+            format!(
+                "sync_event,pair_address={} reserve0=\"{}\",reserve1=\"{}\"",
+                self.pair_address, self.reserve0, self.reserve1
+            )
+        }
+
+        fn to_csv_row(&self) -> Result<(StringRecord, StringRecord)> {
+            csv_row(self)
+        }
+    }
+
+    struct SyncTransformer;
+
+    impl Transformer for SyncTransformer {
+        fn signature(&self) -> B256 {
+            SYNC_EVENT_SIGNATURE
+        }
+
+        fn transform(
+            &self,
+            log: &Log,
+            _chain_id: u64,
+            _pairs: &HashMap<Address, UniswapV2TokenPair>,
+        ) -> Result<Vec<EventRecord>> {
+            let event = decode_log::<UniswapV2Pair::Sync>(log)?;
+            Ok(vec![EventRecord::Custom(Box::new(SyncRecord {
+                pair_address: log.address(),
+                reserve0: event.reserve0.to_string(),
+                reserve1: event.reserve1.to_string(),
+            }))])
+        }
+    }
+
+    fn log(topics: &[B256], data: String) -> Log {
+        serde_json::from_value(serde_json::json!({
+            "address": "0xb4e16d0168e52d35cacd2c6185b44281ec28c9dc",
+            "topics": topics,
+            "data": data,
+            "blockNumber": "0x15c4b3a",
+            "blockHash": "0x2222222222222222222222222222222222222222222222222222222222222222",
+            "blockTimestamp": "0x68632d80",
+            "transactionHash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "transactionIndex": "0x1",
+            "logIndex": "0x3"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_transformer_registry() {
+        let pair_address = USDC_WETH;
+        let pairs = HashMap::from([(pair_address, usdc_weth())]);
+        let swap = log(
+            &[
+                SWAP_EVENT_SIGNATURE,
+                B256::left_padding_from(&[1]),
+                B256::left_padding_from(&[2]),
+            ],
+            format!("0x{:064x}{:064x}{:064x}{:064x}", 1_000_000u64, 0, 0, 400u64),
+        );
+        let sync = log(
+            &[SYNC_EVENT_SIGNATURE],
+            format!("0x{:064x}{:064x}", 5u64, 7u64),
+        );
+
+        let mut registry = TransformerRegistry::new();
+        assert!(!registry.signatures().contains(&SYNC_EVENT_SIGNATURE));
+        assert!(registry.transform(&sync, 1, &pairs).unwrap().is_empty());
+        // Swaps of pairs without metadata can't be normalized.
+        assert!(
+            registry
+                .transform(&swap, 1, &HashMap::new())
+                .unwrap()
+                .is_empty()
+        );
+        let records = registry.transform(&swap, 1, &pairs).unwrap();
+        assert!(matches!(&records[..], [EventRecord::Swap(swap)] if swap.log_index == 3));
+        let records = TransformerRegistry::new()
+            .with_token_metadata(true)
+            .transform(&swap, 1, &pairs)
+            .unwrap();
+        let [EventRecord::Swap(labeled)] = &records[..] else {
+            panic!("expected one swap, got {:?}", records);
+        };
+        assert_eq!(labeled.pair_name.as_deref(), Some("USDC/WETH"));
+        assert_eq!(labeled.token1_decimals, Some(18));
+        assert!(
+            labeled
+                .to_influx_line()
+                .contains(",pair_name=USDC/WETH,token0_symbol=USDC,")
+        );
+
+        registry.register(Box::new(SyncTransformer));
+        assert_eq!(registry.signatures().len(), 4);
+        let records = registry.transform(&sync, 1, &pairs).unwrap();
+        let [EventRecord::Custom(record)] = &records[..] else {
+            panic!("expected one custom record, got {:?}", records);
+        };
+        assert_eq!(record.event_type(), "sync");
+        let (headers, row) = record.to_csv_row().unwrap();
+        assert_eq!(headers.get(1), Some("reserve0"));
+        assert_eq!(row.get(2), Some("7"));
+    }
+}
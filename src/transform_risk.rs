@@ -0,0 +1,475 @@
+use crate::extract_event::{TRANSFER_EVENT_SIGNATURE, UniswapV2Pair};
+use crate::init::RiskCfg;
+use crate::transform_event::{PairCreatedEvent, decode_log};
+use alloy::primitives::{Address, B256, U256, address, keccak256};
+use alloy::providers::{DynProvider, Provider};
+use alloy::rpc::json_rpc::ErrorPayload;
+use alloy::rpc::types::eth::Log;
+use alloy::sol;
+use alloy::transports::RpcError;
+use eyre::Result;
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+sol! {
+    #[sol(rpc)]
+    contract RiskToken {
+        function owner() external view returns (address);
+        function balanceOf(address account) external view returns (uint256);
+        function transfer(address to, uint256 amount) external returns (bool);
+    }
+}
+
+const BPS: u32 = 10_000;
+const DEAD_ADDRESS: Address = address!("0x000000000000000000000000000000000000dEaD");
+
+// Owner functions that freeze holders or inflate the supply, matched by selector.
+const BLACKLIST_FUNCTIONS: &[&str] = &[
+    "blacklist(address)",
+    "addBlackList(address)",
+    "addToBlacklist(address)",
+    "setBlacklist(address,bool)",
+    "blacklistAddress(address,bool)",
+    "setBots(address[],bool)",
+    "addBots(address[])",
+];
+const MINT_FUNCTIONS: &[&str] = &[
+    "mint(address,uint256)",
+    "mint(uint256)",
+    "mintTo(address,uint256)",
+];
+
+// Risk score weights, the sum is capped at 100.
+const HONEYPOT_SCORE: u32 = 100;
+const HIGH_FEE_BPS: u32 = 1_000;
+const HIGH_FEE_SCORE: u32 = 50;
+const FEE_SCORE: u32 = 25;
+const BLACKLIST_SCORE: u32 = 25;
+const MINT_SCORE: u32 = 25;
+
+/// Findings for one token of a new pair. `None` means not observed yet.
+#[derive(Debug, Clone, Default)]
+struct TokenRisk {
+    blacklist: bool,
+    mintable: bool,
+    fee_on_transfer_bps: Option<u32>,
+    honeypot: Option<bool>,
+}
+
+impl TokenRisk {
+    fn score(&self) -> u32 {
+        let mut score = 0;
+        if self.honeypot == Some(true) {
+            score += HONEYPOT_SCORE;
+        }
+        match self.fee_on_transfer_bps {
+            Some(bps) if bps >= HIGH_FEE_BPS => score += HIGH_FEE_SCORE,
+            Some(bps) if bps > 0 => score += FEE_SCORE,
+            _ => {}
+        }
+        if self.blacklist {
+            score += BLACKLIST_SCORE;
+        }
+        if self.mintable {
+            score += MINT_SCORE;
+        }
+        score.min(100)
+    }
+
+    fn flags(&self) -> Vec<&'static str> {
+        [
+            (self.honeypot == Some(true), "honeypot"),
+            (
+                self.fee_on_transfer_bps.is_some_and(|bps| bps > 0),
+                "fee_on_transfer",
+            ),
+            (self.blacklist, "blacklist"),
+            (self.mintable, "mintable"),
+        ]
+        .into_iter()
+        .filter_map(|(flagged, flag)| flagged.then_some(flag))
+        .collect()
+    }
+}
+
+struct PendingPair {
+    event: PairCreatedEvent,
+    // Tokens of the pair that aren't trusted, with their findings so far.
+    tokens: Vec<(Address, TokenRisk)>,
+    deadline_block: u64,
+}
+
+impl PendingPair {
+    // Every analyzed token was bought at least once, so its sell was simulated.
+    fn is_complete(&self) -> bool {
+        self.tokens.iter().all(|(_, risk)| risk.honeypot.is_some())
+    }
+
+    fn into_event(self) -> PairCreatedEvent {
+        let mut event = self.event;
+        let risks = self.tokens.iter().map(|(_, risk)| risk);
+        event.risk_score = Some(risks.clone().map(TokenRisk::score).max().unwrap_or(0) as u8);
+        let mut flags = risks.clone().flat_map(TokenRisk::flags).collect::<Vec<_>>();
+        flags.sort();
+        flags.dedup();
+        event.risk_flags = Some(flags.join(","));
+        event.fee_on_transfer_bps = risks
+            .clone()
+            .filter_map(|risk| risk.fee_on_transfer_bps)
+            .max();
+        event.honeypot = if risks.clone().any(|risk| risk.honeypot == Some(true)) {
+            Some(true)
+        } else {
+            risks
+                .map(|risk| risk.honeypot)
+                .collect::<Option<Vec<_>>>()
+                .map(|_| false)
+        };
+        event
+    }
+}
+
+/// Scores the tokens of new pairs. The contract code is checked for owner blacklist and mint
+/// functions on creation; the first buy of a token gives its transfer fee (Transfer amounts
+/// against the Swap amounts of the transaction) and an `eth_call` sell of the bought tokens
+/// back to the pair tells honeypots apart.
+pub struct TokenRiskAnalyzer {
+    provider: DynProvider,
+    trusted: HashSet<Address>,
+    observe_blocks: u64,
+    pending: HashMap<Address, PendingPair>,
+}
+
+impl TokenRiskAnalyzer {
+    pub fn new(provider: DynProvider, config: &RiskCfg, weth_address: Address) -> Result<Self> {
+        let mut trusted = config
+            .trusted_tokens
+            .iter()
+            .map(|s| Address::from_str(s))
+            .collect::<Result<HashSet<_>, _>>()?;
+        trusted.insert(weth_address);
+        Ok(Self {
+            provider,
+            trusted,
+            observe_blocks: config.observe_blocks,
+            pending: HashMap::new(),
+        })
+    }
+
+    pub async fn add_pair(&mut self, event: PairCreatedEvent) -> Result<()> {
+        let mut tokens = Vec::new();
+        for token in [event.token0_address, event.token1_address] {
+            if self.trusted.contains(&token) {
+                continue;
+            }
+            let code = self.provider.get_code_at(token).await?;
+            let selectors = push4_selectors(&code);
+            // Renounced ownership leaves owner functions uncallable.
+            let owner_controlled = match RiskToken::new(token, self.provider.clone())
+                .owner()
+                .call()
+                .await
+            {
+                Ok(owner) => owner != Address::ZERO && owner != DEAD_ADDRESS,
+                Err(_) => true,
+            };
+            let has_any = |functions: &[&str]| {
+                functions
+                    .iter()
+                    .any(|function| selectors.contains(&selector(function)))
+            };
+            tokens.push((
+                token,
+                TokenRisk {
+                    blacklist: owner_controlled && has_any(BLACKLIST_FUNCTIONS),
+                    mintable: owner_controlled && has_any(MINT_FUNCTIONS),
+                    ..Default::default()
+                },
+            ));
+        }
+        self.pending.insert(
+            event.pair_address,
+            PendingPair {
+                deadline_block: event.block_number + self.observe_blocks,
+                event,
+                tokens,
+            },
+        );
+        Ok(())
+    }
+
+    /// Checks a Swap log of any pair; only those of pending pairs are analyzed.
+    pub async fn observe_swap(&mut self, log: &Log) -> Result<()> {
+        let Some(pending) = self.pending.get_mut(&log.address()) else {
+            return Ok(());
+        };
+        if pending.is_complete() {
+            return Ok(());
+        }
+        let swap = decode_log::<UniswapV2Pair::Swap>(log)?;
+        let Some(transaction_hash) = log.transaction_hash else {
+            return Ok(());
+        };
+        let receipt = match self
+            .provider
+            .get_transaction_receipt(transaction_hash)
+            .await
+        {
+            Ok(Some(receipt)) => receipt,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                warn!("Skipping swap {} of a new pair: {}", transaction_hash, e);
+                return Ok(());
+            }
+        };
+        let pair_address = log.address();
+        for (token, risk) in pending.tokens.iter_mut() {
+            let (amount_in, amount_out) = if *token == pending.event.token0_address {
+                (swap.amount0In, swap.amount0Out)
+            } else {
+                (swap.amount1In, swap.amount1Out)
+            };
+            if let Some(bps) = fee_on_transfer_bps(
+                receipt.inner.logs(),
+                pair_address,
+                *token,
+                amount_in,
+                amount_out,
+                swap.to,
+            ) {
+                risk.fee_on_transfer_bps = Some(risk.fee_on_transfer_bps.unwrap_or(0).max(bps));
+            }
+            if amount_out > U256::ZERO && risk.honeypot.is_none() {
+                risk.honeypot = simulate_sell(
+                    &self.provider,
+                    *token,
+                    swap.to,
+                    pair_address,
+                    transaction_hash,
+                )
+                .await
+                .map(|sellable| !sellable);
+            }
+        }
+        Ok(())
+    }
+
+    /// Scored pairs that are complete or past their observation window at `block_number`,
+    /// in creation order.
+    pub fn ready(&mut self, block_number: u64) -> Vec<PairCreatedEvent> {
+        let ready = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.is_complete() || pending.deadline_block <= block_number)
+            .map(|(pair_address, _)| *pair_address)
+            .collect::<Vec<_>>();
+        let mut events = ready
+            .into_iter()
+            .filter_map(|pair_address| self.pending.remove(&pair_address))
+            .map(PendingPair::into_event)
+            .collect::<Vec<_>>();
+        events.sort_by_key(|event| (event.block_number, event.log_index));
+        events
+    }
+}
+
+/// Transfer fee of `token` in one swap of `pair`, in basis points. The pair pays `amount_out`
+/// to `to` and receives `amount_in`; a fee token delivers less than that to the recipient, or
+/// takes more than that from the sender. `None` when the transfers can't be matched.
+fn fee_on_transfer_bps(
+    logs: &[Log],
+    pair: Address,
+    token: Address,
+    amount_in: U256,
+    amount_out: U256,
+    to: Address,
+) -> Option<u32> {
+    let transfers = logs
+        .iter()
+        .filter(|log| {
+            log.address() == token && log.topics().first() == Some(&TRANSFER_EVENT_SIGNATURE)
+        })
+        .filter_map(|log| decode_log::<UniswapV2Pair::Transfer>(log).ok())
+        .collect::<Vec<_>>();
+    let sum = |matches: &dyn Fn(&UniswapV2Pair::Transfer) -> bool| {
+        transfers
+            .iter()
+            .filter(|transfer| matches(transfer))
+            .fold(U256::ZERO, |sum, transfer| sum + transfer.value)
+    };
+    if amount_out > U256::ZERO {
+        let received = sum(&|transfer| transfer.from == pair && transfer.to == to);
+        return (!received.is_zero()).then(|| fee_bps(amount_out, received));
+    }
+    if amount_in > U256::ZERO {
+        let sender = transfers.iter().find(|transfer| transfer.to == pair)?.from;
+        let sent = sum(&|transfer| transfer.from == sender);
+        return Some(fee_bps(sent, amount_in));
+    }
+    None
+}
+
+fn fee_bps(gross: U256, net: U256) -> u32 {
+    if gross.is_zero() || net >= gross {
+        return 0;
+    }
+    ((gross - net) * U256::from(BPS) / gross).to::<u32>()
+}
+
+// Sends the buyer's tokens back to the pair, the first step of a sell, in an `eth_call`.
+// `None` when the buyer holds none any more or the node couldn't run the call, so a later
+// buy is tried again.
+async fn simulate_sell(
+    provider: &DynProvider,
+    token: Address,
+    holder: Address,
+    pair: Address,
+    transaction_hash: B256,
+) -> Option<bool> {
+    let contract = RiskToken::new(token, provider.clone());
+    let balance = match contract.balanceOf(holder).call().await {
+        Ok(balance) => balance,
+        Err(e) => {
+            warn!(
+                "Balance of {} bought in {} failed: {}",
+                token, transaction_hash, e
+            );
+            return None;
+        }
+    };
+    if balance.is_zero() {
+        return None;
+    }
+    match contract.transfer(pair, balance).from(holder).call().await {
+        Ok(sellable) => Some(sellable),
+        Err(alloy::contract::Error::TransportError(RpcError::ErrorResp(e)))
+            if is_execution_revert(&e) =>
+        {
+            debug!(
+                "Sell of {} bought in {} reverted: {}",
+                token, transaction_hash, e
+            );
+            Some(false)
+        }
+        Err(e) => {
+            warn!(
+                "Sell of {} bought in {} failed: {}",
+                token, transaction_hash, e
+            );
+            None
+        }
+    }
+}
+
+// Geth and most nodes answer a reverted `eth_call` with code 3, others only say so in the
+// message. Any other error response (rate limits, missing state) says nothing of the token.
+fn is_execution_revert(error: &ErrorPayload) -> bool {
+    error.code == 3 || error.message.contains("execution reverted")
+}
+
+fn selector(function: &str) -> [u8; 4] {
+    keccak256(function)[..4].try_into().unwrap()
+}
+
+// Immediates of PUSH4, which Solidity dispatchers compare the call selector with. Data of
+// the other PUSH opcodes is skipped so it isn't mistaken for code.
+fn push4_selectors(code: &[u8]) -> HashSet<[u8; 4]> {
+    let mut selectors = HashSet::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let opcode = code[pc];
+        if (0x60..=0x7f).contains(&opcode) {
+            let size = (opcode - 0x5f) as usize;
+            if opcode == 0x63 && pc + 5 <= code.len() {
+                selectors.insert(code[pc + 1..pc + 5].try_into().unwrap());
+            }
+            pc += size;
+        }
+        pc += 1;
+    }
+    selectors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(token: Address, from: Address, to: Address, value: u64) -> Log {
+        serde_json::from_value(serde_json::json!({
+            "address": token,
+            "topics": [TRANSFER_EVENT_SIGNATURE, from.into_word(), to.into_word()],
+            "data": format!("0x{:064x}", value),
+            "blockNumber": "0x1",
+            "transactionHash": B256::repeat_byte(0x11),
+            "transactionIndex": "0x0",
+            "logIndex": "0x0"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_token_risk() {
+        let token = address!("0x1111111111111111111111111111111111111111");
+        let pair = address!("0x2222222222222222222222222222222222222222");
+        let buyer = address!("0x3333333333333333333333333333333333333333");
+        let tax_wallet = address!("0x4444444444444444444444444444444444444444");
+
+        // Buy: the pair pays out 1000, the buyer gets 950 and 5% goes to the tax wallet.
+        let logs = [
+            transfer(token, pair, buyer, 950),
+            transfer(token, pair, tax_wallet, 50),
+        ];
+        let bps = fee_on_transfer_bps(&logs, pair, token, U256::ZERO, U256::from(1000), buyer);
+        assert_eq!(bps, Some(500));
+        // Sell: the buyer sends 1000, the pair's balance only grows by 900.
+        let logs = [
+            transfer(token, buyer, pair, 900),
+            transfer(token, buyer, tax_wallet, 100),
+        ];
+        let bps = fee_on_transfer_bps(&logs, pair, token, U256::from(900), U256::ZERO, buyer);
+        assert_eq!(bps, Some(1_000));
+        let logs = [transfer(token, pair, buyer, 1000)];
+        let bps = fee_on_transfer_bps(&logs, pair, token, U256::ZERO, U256::from(1000), buyer);
+        assert_eq!(bps, Some(0));
+
+        // PUSH4 mint(address,uint256) counts, the same bytes inside PUSH32 data don't.
+        let mint = selector("mint(address,uint256)");
+        let blacklist = selector("blacklist(address)");
+        let mut code = vec![0x63];
+        code.extend(mint);
+        code.push(0x7f);
+        code.extend(blacklist);
+        code.extend([0u8; 28]);
+        let selectors = push4_selectors(&code);
+        assert!(selectors.contains(&mint));
+        assert!(!selectors.contains(&blacklist));
+
+        let risk = TokenRisk {
+            mintable: true,
+            fee_on_transfer_bps: Some(500),
+            honeypot: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(risk.score(), MINT_SCORE + FEE_SCORE);
+        assert_eq!(risk.flags(), ["fee_on_transfer", "mintable"]);
+        let honeypot = TokenRisk {
+            honeypot: Some(true),
+            ..risk
+        };
+        assert_eq!(honeypot.score(), 100);
+
+        let error = |code, message: &'static str| ErrorPayload {
+            code,
+            message: message.into(),
+            data: None,
+        };
+        assert!(is_execution_revert(&error(
+            3,
+            "execution reverted: TRANSFER_FAILED"
+        )));
+        assert!(is_execution_revert(&error(-32000, "execution reverted")));
+        assert!(!is_execution_revert(&error(-32005, "rate limit exceeded")));
+        assert!(!is_execution_revert(&error(-32000, "missing trie node")));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::extract_event::UniswapV2TokenPair;
+use crate::transform_event::{
+    SwapDirection, SwapEvent, format_decimal, serialize_decimal, serialize_timestamp,
+    serialize_u256, to_exact_amount,
+};
+use alloy::primitives::{Address, U256};
+use bigdecimal::BigDecimal;
+use eyre::Result;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RouteEvent {
+    pub chain_id: u64,
+    pub event_type: String,
+    pub sender_address: Address,
+    pub receiver_address: Address,
+    pub token_in: Address,
+    pub token_out: Address,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount_in: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount_out: U256,
+    #[serde(serialize_with = "serialize_decimal")]
+    pub token_in_amounts: BigDecimal,
+    #[serde(serialize_with = "serialize_decimal")]
+    pub token_out_amounts: BigDecimal,
+    pub hop_count: usize,
+    pub path: String,
+    pub pairs: String,
+    pub block_number: u64,
+    pub transaction_hash: String,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub block_timestamp: u64,
+}
+
+impl RouteEvent {
+    pub fn to_influx_line(&self) -> String {
+        format!(
+            "route_event,chain_id={},sender_address={},receiver_address={},token_in={},token_out={},transaction_hash={} \
+            amount_in=\"{}\",amount_out=\"{}\",token_in_amounts=\"{}\",token_out_amounts=\"{}\",hop_count={}i,path=\"{}\",pairs=\"{}\",block_number={}i {}",
+            self.chain_id,
+            self.sender_address,
+            self.receiver_address,
+            self.token_in,
+            self.token_out,
+            self.transaction_hash,
+            self.amount_in,
+            self.amount_out,
+            format_decimal(&self.token_in_amounts),
+            format_decimal(&self.token_out_amounts),
+            self.hop_count,
+            self.path,
+            self.pairs,
+            self.block_number,
+            self.block_timestamp
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Hop<'a> {
+    swap: &'a SwapEvent,
+    token_in: Address,
+    token_out: Address,
+    token_in_decimals: u8,
+    token_out_decimals: u8,
+    amount_in: U256,
+    amount_out: U256,
+}
+
+impl<'a> Hop<'a> {
+    fn new(swap: &'a SwapEvent, pair: &UniswapV2TokenPair) -> Option<Self> {
+        let (token_in, token_out) = match swap.direction {
+            SwapDirection::Token0ToToken1 => (&pair.token0, &pair.token1),
+            SwapDirection::Token1ToToken0 => (&pair.token1, &pair.token0),
+            SwapDirection::Unknown => return None,
+        };
+        Some(Self {
+            swap,
+            token_in: token_in.address,
+            token_out: token_out.address,
+            token_in_decimals: token_in.decimals,
+            token_out_decimals: token_out.decimals,
+            amount_in: swap.amount_in,
+            amount_out: swap.amount_out,
+        })
+    }
+
+    // The router sends the output of one hop straight to the next pair.
+    fn feeds(&self, next: &Hop) -> bool {
+        self.swap.receiver_address == next.swap.pair_address && self.token_out == next.token_in
+    }
+}
+
+/// Groups swaps by transaction and chains them into one route per trade.
+/// Swaps on pairs missing from `pairs`, or without a clear direction, are skipped.
+pub fn transform_route_event(
+    swaps: &[SwapEvent],
+    pairs: &HashMap<Address, UniswapV2TokenPair>,
+) -> Result<Vec<RouteEvent>> {
+    let mut tx_order: Vec<&str> = Vec::new();
+    let mut tx_hops: HashMap<&str, Vec<Hop>> = HashMap::new();
+    for swap in swaps {
+        let Some(hop) = pairs
+            .get(&swap.pair_address)
+            .and_then(|pair| Hop::new(swap, pair))
+        else {
+            continue;
+        };
+        let hops = tx_hops
+            .entry(swap.transaction_hash.as_str())
+            .or_insert_with(|| {
+                tx_order.push(swap.transaction_hash.as_str());
+                Vec::new()
+            });
+        hops.push(hop);
+    }
+
+    let mut routes = Vec::new();
+    for transaction_hash in tx_order {
+        let hops = &tx_hops[transaction_hash];
+        let mut used = vec![false; hops.len()];
+        // Start from hops that nothing feeds into, then pick up any leftovers (pure cycles).
+        let starts = (0..hops.len())
+            .filter(|&i| !(0..hops.len()).any(|j| j != i && hops[j].feeds(&hops[i])))
+            .chain(0..hops.len())
+            .collect::<Vec<_>>();
+        for start in starts {
+            if used[start] {
+                continue;
+            }
+            let mut chain = vec![start];
+            used[start] = true;
+            let mut current = start;
+            while let Some(next) =
+                (0..hops.len()).find(|&k| !used[k] && hops[current].feeds(&hops[k]))
+            {
+                used[next] = true;
+                chain.push(next);
+                current = next;
+            }
+            routes.push(build_route(
+                &chain.iter().map(|&i| &hops[i]).collect::<Vec<_>>(),
+            ));
+        }
+    }
+    Ok(routes)
+}
+
+fn build_route(hops: &[&Hop]) -> RouteEvent {
+    let first = hops[0];
+    let last = hops[hops.len() - 1];
+    let path = std::iter::once(first.token_in)
+        .chain(hops.iter().map(|h| h.token_out))
+        .map(|a| a.to_string())
+        .collect::<Vec<_>>()
+        .join(">");
+    let pairs = hops
+        .iter()
+        .map(|h| h.swap.pair_address.to_string())
+        .collect::<Vec<_>>()
+        .join(">");
+
+    RouteEvent {
+        chain_id: first.swap.chain_id,
+        event_type: "Route".to_string(),
+        sender_address: first.swap.tx_sender.unwrap_or(first.swap.caller_address),
+        receiver_address: last.swap.receiver_address,
+        token_in: first.token_in,
+        token_out: last.token_out,
+        amount_in: first.amount_in,
+        amount_out: last.amount_out,
+        token_in_amounts: to_exact_amount(first.amount_in, first.token_in_decimals),
+        token_out_amounts: to_exact_amount(last.amount_out, last.token_out_decimals),
+        hop_count: hops.len(),
+        path,
+        pairs,
+        block_number: first.swap.block_number,
+        transaction_hash: first.swap.transaction_hash.clone(),
+        block_timestamp: first.swap.block_timestamp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{USDC, USDC_WETH, WETH, pair, swap, token, usdc_weth};
+    use alloy::primitives::address;
+    use log::info;
+
+    #[test]
+    fn test_transform_route_event() {
+        let usdt = address!("0xdAC17F958D2ee523a2206206994597C13D831ec7");
+        let weth_usdt = pair(
+            address!("0x0d4a11d5EEaaC28EC3F61d100daF4d40471f1852"),
+            token(WETH, 18, "WETH"),
+            token(usdt, 6, "USDT"),
+        );
+        let user = address!("0x00000000000000000000000000000000000000aa");
+        let pairs = HashMap::from([
+            (USDC_WETH, usdc_weth()),
+            (weth_usdt.pair_address, weth_usdt.clone()),
+        ]);
+
+        // USDC -> WETH -> USDT, second hop listed first as in per-pair batch output
+        let swaps = vec![
+            SwapEvent {
+                receiver_address: user,
+                ..swap(&weth_usdt, true, 0.4, 999.0)
+            },
+            SwapEvent {
+                receiver_address: weth_usdt.pair_address,
+                ..swap(&usdc_weth(), true, 1000.0, 0.4)
+            },
+        ];
+        let routes = transform_route_event(&swaps, &pairs).unwrap();
+        info!("routes: {:#?}", routes);
+        assert_eq!(routes.len(), 1);
+        let route = &routes[0];
+        assert_eq!(route.hop_count, 2);
+        assert_eq!((route.token_in, route.token_out), (USDC, usdt));
+        assert_eq!(
+            (route.amount_in, route.amount_out),
+            (U256::from(1_000_000_000), U256::from(999_000_000))
+        );
+        assert_eq!(route.receiver_address, user);
+        assert_eq!(
+            route.pairs,
+            format!("{}>{}", USDC_WETH, weth_usdt.pair_address)
+        );
+        assert_eq!(route.token_in_amounts, BigDecimal::from(1000));
+        assert!(route.to_influx_line().contains(",hop_count=2i,path=\""));
+    }
+}
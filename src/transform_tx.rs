@@ -0,0 +1,447 @@
+use crate::extract_event::UniswapV2Router::UniswapV2RouterCalls;
+use crate::transform_event::{SwapEvent, serialize_timestamp, serialize_u256};
+use alloy::consensus::Transaction as _;
+use alloy::network::TransactionResponse;
+use alloy::primitives::{Address, B256, U256};
+use alloy::providers::{DynProvider, Provider};
+use alloy::rpc::types::eth::Transaction;
+use alloy::sol_types::SolInterface;
+use eyre::Result;
+use log::{debug, warn};
+use std::collections::HashMap;
+
+/// One decoded UniswapV2Router call.
+///
+/// Swaps fill `amount_in` (exact input, or the maximum input for `*ForExact*`) and
+/// `amount_out_min` (minimum output, or the exact output for `*ForExact*`).
+/// Liquidity calls fill `amount_a`/`amount_b` (desired amounts), `amount_a_min`/`amount_b_min`
+/// and `liquidity`; for `*ETH` variants token B is WETH.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RouterCallEvent {
+    pub chain_id: u64,
+    pub event_type: String,
+    pub function_name: String,
+    pub transaction_hash: String,
+    pub sender: Address,
+    pub router_address: Address,
+    pub path: String,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount_in: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount_out_min: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount_a: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount_b: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount_a_min: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub amount_b_min: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub liquidity: U256,
+    pub to: Address,
+    pub deadline: u64,
+    pub block_number: Option<u64>,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub timestamp: u64,
+}
+
+impl RouterCallEvent {
+    pub fn path_addresses(&self) -> Vec<Address> {
+        self.path
+            .split('>')
+            .filter_map(|s| s.parse().ok())
+            .collect()
+    }
+}
+
+fn join_path(path: &[Address]) -> String {
+    path.iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<_>>()
+        .join(">")
+}
+
+fn to_deadline(deadline: U256) -> u64 {
+    deadline.try_into().unwrap_or(u64::MAX)
+}
+
+/// Decodes `tx` if it calls a swap or liquidity function on a UniswapV2 style router.
+/// Returns `Ok(None)` for contract creations, other selectors and view calls.
+pub fn transform_router_call(
+    tx: &Transaction,
+    chain_id: u64,
+    weth_address: Address,
+    timestamp: u64,
+) -> Result<Option<RouterCallEvent>> {
+    let Some(router_address) = tx.to() else {
+        return Ok(None);
+    };
+    let input = tx.input();
+    if input.len() < 4 || !UniswapV2RouterCalls::valid_selector(input[..4].try_into()?) {
+        return Ok(None);
+    }
+    let call = UniswapV2RouterCalls::abi_decode(input).map_err(|e| {
+        eyre::eyre!(
+            "Failed to decode router calldata in tx {}: {}",
+            tx.tx_hash(),
+            e
+        )
+    })?;
+
+    let mut event = RouterCallEvent {
+        chain_id,
+        event_type: "RouterCall".to_string(),
+        function_name: String::new(),
+        transaction_hash: tx.tx_hash().to_string(),
+        sender: tx.from(),
+        router_address,
+        path: String::new(),
+        amount_in: U256::ZERO,
+        amount_out_min: U256::ZERO,
+        amount_a: U256::ZERO,
+        amount_b: U256::ZERO,
+        amount_a_min: U256::ZERO,
+        amount_b_min: U256::ZERO,
+        liquidity: U256::ZERO,
+        to: Address::ZERO,
+        deadline: 0,
+        block_number: tx.block_number,
+        timestamp,
+    };
+    let value = tx.value();
+
+    use UniswapV2RouterCalls as C;
+    match call {
+        C::swapExactTokensForTokens(c) => {
+            event.function_name = "swapExactTokensForTokens".into();
+            event.path = join_path(&c.path);
+            (event.amount_in, event.amount_out_min) = (c.amountIn, c.amountOutMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::swapExactTokensForTokensSupportingFeeOnTransferTokens(c) => {
+            event.function_name = "swapExactTokensForTokensSupportingFeeOnTransferTokens".into();
+            event.path = join_path(&c.path);
+            (event.amount_in, event.amount_out_min) = (c.amountIn, c.amountOutMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::swapExactTokensForETH(c) => {
+            event.function_name = "swapExactTokensForETH".into();
+            event.path = join_path(&c.path);
+            (event.amount_in, event.amount_out_min) = (c.amountIn, c.amountOutMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::swapExactTokensForETHSupportingFeeOnTransferTokens(c) => {
+            event.function_name = "swapExactTokensForETHSupportingFeeOnTransferTokens".into();
+            event.path = join_path(&c.path);
+            (event.amount_in, event.amount_out_min) = (c.amountIn, c.amountOutMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::swapExactETHForTokens(c) => {
+            event.function_name = "swapExactETHForTokens".into();
+            event.path = join_path(&c.path);
+            (event.amount_in, event.amount_out_min) = (value, c.amountOutMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::swapExactETHForTokensSupportingFeeOnTransferTokens(c) => {
+            event.function_name = "swapExactETHForTokensSupportingFeeOnTransferTokens".into();
+            event.path = join_path(&c.path);
+            (event.amount_in, event.amount_out_min) = (value, c.amountOutMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::swapTokensForExactTokens(c) => {
+            event.function_name = "swapTokensForExactTokens".into();
+            event.path = join_path(&c.path);
+            (event.amount_in, event.amount_out_min) = (c.amountInMax, c.amountOut);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::swapTokensForExactETH(c) => {
+            event.function_name = "swapTokensForExactETH".into();
+            event.path = join_path(&c.path);
+            (event.amount_in, event.amount_out_min) = (c.amountInMax, c.amountOut);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::swapETHForExactTokens(c) => {
+            event.function_name = "swapETHForExactTokens".into();
+            event.path = join_path(&c.path);
+            (event.amount_in, event.amount_out_min) = (value, c.amountOut);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::addLiquidity(c) => {
+            event.function_name = "addLiquidity".into();
+            event.path = join_path(&[c.tokenA, c.tokenB]);
+            (event.amount_a, event.amount_b) = (c.amountADesired, c.amountBDesired);
+            (event.amount_a_min, event.amount_b_min) = (c.amountAMin, c.amountBMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::addLiquidityETH(c) => {
+            event.function_name = "addLiquidityETH".into();
+            event.path = join_path(&[c.token, weth_address]);
+            (event.amount_a, event.amount_b) = (c.amountTokenDesired, value);
+            (event.amount_a_min, event.amount_b_min) = (c.amountTokenMin, c.amountETHMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::removeLiquidity(c) => {
+            event.function_name = "removeLiquidity".into();
+            event.path = join_path(&[c.tokenA, c.tokenB]);
+            event.liquidity = c.liquidity;
+            (event.amount_a_min, event.amount_b_min) = (c.amountAMin, c.amountBMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::removeLiquidityWithPermit(c) => {
+            event.function_name = "removeLiquidityWithPermit".into();
+            event.path = join_path(&[c.tokenA, c.tokenB]);
+            event.liquidity = c.liquidity;
+            (event.amount_a_min, event.amount_b_min) = (c.amountAMin, c.amountBMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::removeLiquidityETH(c) => {
+            event.function_name = "removeLiquidityETH".into();
+            event.path = join_path(&[c.token, weth_address]);
+            event.liquidity = c.liquidity;
+            (event.amount_a_min, event.amount_b_min) = (c.amountTokenMin, c.amountETHMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::removeLiquidityETHSupportingFeeOnTransferTokens(c) => {
+            event.function_name = "removeLiquidityETHSupportingFeeOnTransferTokens".into();
+            event.path = join_path(&[c.token, weth_address]);
+            event.liquidity = c.liquidity;
+            (event.amount_a_min, event.amount_b_min) = (c.amountTokenMin, c.amountETHMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::removeLiquidityETHWithPermit(c) => {
+            event.function_name = "removeLiquidityETHWithPermit".into();
+            event.path = join_path(&[c.token, weth_address]);
+            event.liquidity = c.liquidity;
+            (event.amount_a_min, event.amount_b_min) = (c.amountTokenMin, c.amountETHMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        C::removeLiquidityETHWithPermitSupportingFeeOnTransferTokens(c) => {
+            event.function_name =
+                "removeLiquidityETHWithPermitSupportingFeeOnTransferTokens".into();
+            event.path = join_path(&[c.token, weth_address]);
+            event.liquidity = c.liquidity;
+            (event.amount_a_min, event.amount_b_min) = (c.amountTokenMin, c.amountETHMin);
+            (event.to, event.deadline) = (c.to, to_deadline(c.deadline));
+        }
+        _ => return Ok(None),
+    }
+    Ok(Some(event))
+}
+
+const INTENT_CACHE_SIZE: usize = 10_000;
+
+/// Attaches the trade intent of the originating transaction to Swap events:
+/// EOA sender, full router path, requested minimum output and deadline.
+pub struct SwapIntentEnricher {
+    provider: DynProvider,
+    // tx hash -> (sender, decoded router swap), kept so multi-hop swaps fetch the tx once
+    cache: HashMap<B256, (Address, Option<RouterCallEvent>)>,
+}
+
+impl SwapIntentEnricher {
+    pub fn new(provider: DynProvider) -> Self {
+        Self {
+            provider,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub async fn enrich(&mut self, events: &mut [SwapEvent]) -> Result<()> {
+        for event in events.iter_mut() {
+            let tx_hash: B256 = event.transaction_hash.parse()?;
+            if !self.cache.contains_key(&tx_hash) {
+                if self.cache.len() >= INTENT_CACHE_SIZE {
+                    self.cache.clear();
+                }
+                // The swap is kept without intent; the failure isn't cached so the next swap
+                // of the transaction asks again.
+                let tx = match self.provider.get_transaction_by_hash(tx_hash).await {
+                    Ok(Some(tx)) => tx,
+                    Ok(None) => {
+                        warn!("No swap intent, transaction {} not found", tx_hash);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("No swap intent for transaction {}: {}", tx_hash, e);
+                        continue;
+                    }
+                };
+                let router_call = match transform_router_call(&tx, 0, Address::ZERO, 0) {
+                    Ok(router_call) => router_call.filter(|c| c.function_name.starts_with("swap")),
+                    Err(e) => {
+                        debug!("No router intent for swap: {}", e);
+                        None
+                    }
+                };
+                self.cache.insert(tx_hash, (tx.from(), router_call));
+            }
+            let (sender, router_call) = &self.cache[&tx_hash];
+            apply_swap_intent(event, *sender, router_call.as_ref());
+        }
+        Ok(())
+    }
+}
+
+pub fn apply_swap_intent(
+    event: &mut SwapEvent,
+    sender: Address,
+    router_call: Option<&RouterCallEvent>,
+) {
+    event.tx_sender = Some(sender);
+    if let Some(router_call) = router_call {
+        event.trade_path = Some(router_call.path.clone());
+        event.amount_out_min = Some(router_call.amount_out_min);
+        event.deadline = Some(router_call.deadline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract_event::UniswapV2Router;
+    use alloy::consensus::{Signed, TxEnvelope, TxLegacy};
+    use alloy::primitives::{B256, Bytes, Signature, TxKind, address};
+    use alloy::sol_types::SolCall;
+    use log::info;
+
+    fn router_tx(router: Address, from: Address, input: Vec<u8>, value: U256) -> Transaction {
+        let tx = TxLegacy {
+            to: TxKind::Call(router),
+            value,
+            input: Bytes::from(input),
+            ..Default::default()
+        };
+        let signed = Signed::new_unchecked(tx, Signature::test_signature(), B256::repeat_byte(1));
+        Transaction {
+            inner: alloy::consensus::transaction::Recovered::new_unchecked(
+                TxEnvelope::Legacy(signed),
+                from,
+            ),
+            block_hash: None,
+            block_number: None,
+            transaction_index: None,
+            effective_gas_price: None,
+        }
+    }
+
+    #[test]
+    fn test_transform_router_call() {
+        let router = address!("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D");
+        let sender = address!("0x00000000000000000000000000000000000000aa");
+        let weth = address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+        let usdc = address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+
+        let input = UniswapV2Router::swapExactTokensForTokensCall {
+            amountIn: U256::from(1_000_000u64),
+            amountOutMin: U256::from(300_000_000_000_000u64),
+            path: vec![usdc, weth],
+            to: sender,
+            deadline: U256::from(1751328000u64),
+        }
+        .abi_encode();
+        let event =
+            transform_router_call(&router_tx(router, sender, input, U256::ZERO), 1, weth, 0)
+                .unwrap()
+                .unwrap();
+        info!("router_call: {:#?}", event);
+        assert_eq!(event.function_name, "swapExactTokensForTokens");
+        assert_eq!(event.path_addresses(), vec![usdc, weth]);
+        assert_eq!(event.amount_in, U256::from(1_000_000u64));
+        assert_eq!(event.amount_out_min, U256::from(300_000_000_000_000u64));
+        assert_eq!(event.deadline, 1751328000);
+        assert_eq!(event.sender, sender);
+
+        let input = UniswapV2Router::addLiquidityETHCall {
+            token: usdc,
+            amountTokenDesired: U256::from(5u64),
+            amountTokenMin: U256::from(4u64),
+            amountETHMin: U256::from(3u64),
+            to: sender,
+            deadline: U256::from(1u64),
+        }
+        .abi_encode();
+        let event = transform_router_call(
+            &router_tx(router, sender, input, U256::from(7u64)),
+            1,
+            weth,
+            0,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(event.path_addresses(), vec![usdc, weth]);
+        assert_eq!(
+            (event.amount_a, event.amount_b),
+            (U256::from(5u64), U256::from(7u64))
+        );
+
+        let input = UniswapV2Router::factoryCall {}.abi_encode();
+        assert!(
+            transform_router_call(&router_tx(router, sender, input, U256::ZERO), 1, weth, 0)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_apply_swap_intent() {
+        let router = address!("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D");
+        let sender = address!("0x3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD");
+        let weth = address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+        // swapExactTokensForTokens(2500 USDC -> WETH) calldata as sent to the router.
+        let input = alloy::hex::decode(
+            "38ed1739\
+             000000000000000000000000000000000000000000000000000000009502f900\
+             0000000000000000000000000000000000000000000000000ca94d8fa7454b87\
+             00000000000000000000000000000000000000000000000000000000000000a0\
+             0000000000000000000000003fc91a3afd70395cd496c647d5a6cc9d4b2b7fad\
+             0000000000000000000000000000000000000000000000000000000068632500\
+             0000000000000000000000000000000000000000000000000000000000000002\
+             000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48\
+             000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+        )
+        .unwrap();
+        let router_call =
+            transform_router_call(&router_tx(router, sender, input, U256::ZERO), 1, weth, 0)
+                .unwrap();
+
+        let mut event = SwapEvent::default();
+        apply_swap_intent(&mut event, sender, router_call.as_ref());
+        assert_eq!(event.tx_sender, Some(sender));
+        assert_eq!(
+            event.trade_path.as_deref(),
+            Some(
+                "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48>0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
+            )
+        );
+        assert_eq!(
+            event.amount_out_min,
+            Some(U256::from(912_345_678_901_234_567u64))
+        );
+        assert_eq!(event.deadline, Some(1751328000));
+
+        // Swaps not sent through the router only get the sender.
+        let mut event = SwapEvent::default();
+        apply_swap_intent(&mut event, sender, None);
+        assert_eq!(event.tx_sender, Some(sender));
+        assert!(event.trade_path.is_none() && event.amount_out_min.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_enrich_unreachable_node() {
+        use alloy::providers::ProviderBuilder;
+
+        // Nothing listens on port 1, so every transaction lookup fails.
+        let provider = ProviderBuilder::new()
+            .connect_http("http://127.0.0.1:1".parse().unwrap())
+            .erased();
+        let mut enricher = SwapIntentEnricher::new(provider);
+        let mut events = vec![SwapEvent {
+            transaction_hash: B256::repeat_byte(1).to_string(),
+            ..Default::default()
+        }];
+        enricher.enrich(&mut events).await.unwrap();
+        assert!(events[0].tx_sender.is_none() && events[0].trade_path.is_none());
+        assert!(enricher.cache.is_empty());
+    }
+}
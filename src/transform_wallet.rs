@@ -0,0 +1,405 @@
+use crate::extract_event::UniswapV2TokenPair;
+use crate::transform_event::{SwapEvent, decimal_to_f64, serialize_timestamp};
+use crate::transform_route::{RouteEvent, transform_route_event};
+use alloy::primitives::Address;
+use eyre::Result;
+use std::collections::{HashMap, HashSet};
+
+const DAY_SECS: u64 = 86400;
+
+// Positions below this are treated as closed.
+const DUST: f64 = 1e-12;
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct WalletEvent {
+    pub chain_id: u64,
+    pub event_type: String,
+    pub wallet_address: Address,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub day: u64,
+    pub trade_count: u64,
+    /// Trades without a USD price; they count here but not in volume, positions or PnL.
+    pub unpriced_count: u64,
+    pub volume_usd: f64,
+    pub realized_pnl_usd: f64,
+    pub open_positions: usize,
+    /// Position in the top-traders report, empty in daily rows.
+    pub rank: Option<usize>,
+}
+
+impl WalletEvent {
+    pub fn to_influx_line(&self) -> String {
+        format!(
+            "wallet_activity,chain_id={},wallet_address={} trade_count={}i,unpriced_count={}i,volume_usd={},realized_pnl_usd={},open_positions={}i {}",
+            self.chain_id,
+            self.wallet_address,
+            self.trade_count,
+            self.unpriced_count,
+            self.volume_usd,
+            self.realized_pnl_usd,
+            self.open_positions,
+            self.day
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Position {
+    quantity: f64,
+    cost_usd: f64,
+}
+
+#[derive(Debug, Default)]
+struct WalletDay {
+    trade_count: u64,
+    unpriced_count: u64,
+    volume_usd: f64,
+    realized_pnl_usd: f64,
+}
+
+#[derive(Debug, Default)]
+struct Wallet {
+    positions: HashMap<Address, Position>,
+    bucket: WalletDay,
+}
+
+impl Wallet {
+    fn buy(&mut self, token: Address, quantity: f64, value_usd: f64) {
+        let position = self.positions.entry(token).or_default();
+        position.quantity += quantity;
+        position.cost_usd += value_usd;
+    }
+
+    // Average-cost basis. Tokens held from before the tracked range have no cost, so
+    // selling more than the position realizes nothing on the excess.
+    fn sell(&mut self, token: Address, quantity: f64, value_usd: f64) {
+        let Some(position) = self.positions.get_mut(&token) else {
+            return;
+        };
+        let sold = quantity.min(position.quantity);
+        if sold <= DUST || quantity <= 0.0 {
+            return;
+        }
+        let cost = position.cost_usd * sold / position.quantity;
+        self.bucket.realized_pnl_usd += value_usd * sold / quantity - cost;
+        position.quantity -= sold;
+        position.cost_usd -= cost;
+        if position.quantity <= DUST {
+            self.positions.remove(&token);
+        }
+    }
+}
+
+/// Per-wallet positions, volume and realized PnL from swaps, summarized per UTC day.
+///
+/// Swaps of one transaction are chained into a route first, so a multi-hop trade counts
+/// once. The trader is the transaction sender when known; trades whose sender is a
+/// router fall back to the route's receiver.
+#[derive(Debug)]
+pub struct WalletTracker {
+    chain_id: u64,
+    routers: HashSet<Address>,
+    day: Option<u64>,
+    // Swaps of the transaction being received, settled when the next one starts.
+    pending: Vec<SwapEvent>,
+    wallets: HashMap<Address, Wallet>,
+}
+
+impl WalletTracker {
+    pub fn new(chain_id: u64, routers: HashSet<Address>) -> Self {
+        Self {
+            chain_id,
+            routers,
+            day: None,
+            pending: Vec::new(),
+            wallets: HashMap::new(),
+        }
+    }
+
+    /// Adds a swap and returns the days closed by the transactions settled before it.
+    pub fn add_swap(
+        &mut self,
+        swap: &SwapEvent,
+        pairs: &HashMap<Address, UniswapV2TokenPair>,
+    ) -> Result<Vec<WalletEvent>> {
+        let mut events = Vec::new();
+        if self
+            .pending
+            .first()
+            .is_some_and(|first| first.transaction_hash != swap.transaction_hash)
+        {
+            events.extend(self.settle(pairs)?);
+        }
+        self.pending.push(swap.clone());
+        Ok(events)
+    }
+
+    /// Processes a whole batch in log order and closes the last, partial day.
+    pub fn track_batch(
+        &mut self,
+        swaps: &[SwapEvent],
+        pairs: &HashMap<Address, UniswapV2TokenPair>,
+    ) -> Result<Vec<WalletEvent>> {
+        let mut ordered = swaps.iter().collect::<Vec<_>>();
+        ordered.sort_by_key(|swap| (swap.block_number, swap.log_index));
+        let mut events = Vec::new();
+        for swap in ordered {
+            events.extend(self.add_swap(swap, pairs)?);
+        }
+        events.extend(self.flush(pairs)?);
+        Ok(events)
+    }
+
+    /// Settles the pending transaction and closes the current day.
+    pub fn flush(
+        &mut self,
+        pairs: &HashMap<Address, UniswapV2TokenPair>,
+    ) -> Result<Vec<WalletEvent>> {
+        let mut events = self.settle(pairs)?;
+        events.extend(self.close_day());
+        Ok(events)
+    }
+
+    /// Flushes once `now` is in a later UTC day than the trades seen, for live subscriptions
+    /// where no swap follows the day boundary.
+    pub fn close_expired(
+        &mut self,
+        now: u64,
+        pairs: &HashMap<Address, UniswapV2TokenPair>,
+    ) -> Result<Vec<WalletEvent>> {
+        let last_day = self
+            .pending
+            .last()
+            .map(|swap| swap.block_timestamp - swap.block_timestamp % DAY_SECS)
+            .or(self.day);
+        if last_day.is_none_or(|day| day + DAY_SECS > now) {
+            return Ok(Vec::new());
+        }
+        let events = self.flush(pairs)?;
+        self.day = None;
+        Ok(events)
+    }
+
+    fn settle(&mut self, pairs: &HashMap<Address, UniswapV2TokenPair>) -> Result<Vec<WalletEvent>> {
+        let swaps = std::mem::take(&mut self.pending);
+        let mut usd_prices: HashMap<Address, f64> = HashMap::new();
+        for swap in &swaps {
+            if let Some(pair) = pairs.get(&swap.pair_address) {
+                for (token, price) in [
+                    (pair.token0.address, swap.token0_price_usd),
+                    (pair.token1.address, swap.token1_price_usd),
+                ] {
+                    if let Some(price) = price {
+                        usd_prices.insert(token, price);
+                    }
+                }
+            }
+        }
+
+        let mut events = Vec::new();
+        for route in transform_route_event(&swaps, pairs)? {
+            let day = route.block_timestamp - route.block_timestamp % DAY_SECS;
+            if self.day.is_some_and(|current| current < day) {
+                events.extend(self.close_day());
+            }
+            self.day = Some(day);
+            self.apply_route(&route, &usd_prices);
+        }
+        Ok(events)
+    }
+
+    fn apply_route(&mut self, route: &RouteEvent, usd_prices: &HashMap<Address, f64>) {
+        let wallet_address = if self.routers.contains(&route.sender_address) {
+            route.receiver_address
+        } else {
+            route.sender_address
+        };
+        let wallet = self.wallets.entry(wallet_address).or_default();
+        wallet.bucket.trade_count += 1;
+
+        let token_in_amounts = decimal_to_f64(&route.token_in_amounts);
+        let token_out_amounts = decimal_to_f64(&route.token_out_amounts);
+        let value_in = usd_prices
+            .get(&route.token_in)
+            .map(|price| token_in_amounts * price);
+        let value_out = usd_prices
+            .get(&route.token_out)
+            .map(|price| token_out_amounts * price);
+        let Some(value_usd) = value_in.or(value_out) else {
+            wallet.bucket.unpriced_count += 1;
+            return;
+        };
+        wallet.bucket.volume_usd += value_usd;
+        if route.token_in == route.token_out {
+            // A cycle back to the same token realizes its gain at once.
+            wallet.bucket.realized_pnl_usd += value_out.unwrap_or(value_usd) - value_usd;
+        } else {
+            wallet.sell(route.token_in, token_in_amounts, value_usd);
+            wallet.buy(route.token_out, token_out_amounts, value_usd);
+        }
+    }
+
+    fn close_day(&mut self) -> Vec<WalletEvent> {
+        let Some(day) = self.day else {
+            return Vec::new();
+        };
+        let mut events = self
+            .wallets
+            .iter_mut()
+            .filter(|(_, wallet)| wallet.bucket.trade_count > 0)
+            .map(|(wallet_address, wallet)| {
+                let bucket = std::mem::take(&mut wallet.bucket);
+                WalletEvent {
+                    chain_id: self.chain_id,
+                    event_type: "Wallet".to_string(),
+                    wallet_address: *wallet_address,
+                    day,
+                    trade_count: bucket.trade_count,
+                    unpriced_count: bucket.unpriced_count,
+                    volume_usd: bucket.volume_usd,
+                    realized_pnl_usd: bucket.realized_pnl_usd,
+                    open_positions: wallet.positions.len(),
+                    rank: None,
+                }
+            })
+            .collect::<Vec<_>>();
+        events.sort_by_key(|event| event.wallet_address);
+        events
+    }
+}
+
+/// Daily rows summed per wallet, ordered by realized PnL and cut to the `top` wallets.
+pub fn rank_traders(events: &[WalletEvent], top: usize) -> Vec<WalletEvent> {
+    let mut totals: HashMap<Address, WalletEvent> = HashMap::new();
+    for event in events {
+        let total = totals
+            .entry(event.wallet_address)
+            .or_insert_with(|| WalletEvent {
+                trade_count: 0,
+                unpriced_count: 0,
+                volume_usd: 0.0,
+                realized_pnl_usd: 0.0,
+                ..event.clone()
+            });
+        total.trade_count += event.trade_count;
+        total.unpriced_count += event.unpriced_count;
+        total.volume_usd += event.volume_usd;
+        total.realized_pnl_usd += event.realized_pnl_usd;
+        if event.day >= total.day {
+            total.day = event.day;
+            total.open_positions = event.open_positions;
+        }
+    }
+    let mut ranking = totals.into_values().collect::<Vec<_>>();
+    ranking.sort_by(|a, b| {
+        b.realized_pnl_usd
+            .total_cmp(&a.realized_pnl_usd)
+            .then(b.volume_usd.total_cmp(&a.volume_usd))
+            .then(a.wallet_address.cmp(&b.wallet_address))
+    });
+    ranking.truncate(top);
+    for (i, event) in ranking.iter_mut().enumerate() {
+        event.rank = Some(i + 1);
+    }
+    ranking
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{ROUTER, USDC_WETH, usdc_weth};
+    use alloy::primitives::address;
+
+    // USDC/WETH swap of `usdc` against `weth` at `weth_price` USD.
+    fn swap(
+        tx: &str,
+        sender: Option<Address>,
+        receiver: Address,
+        buy_weth: bool,
+        (usdc, weth, weth_price): (f64, f64, f64),
+        block_timestamp: u64,
+    ) -> SwapEvent {
+        SwapEvent {
+            receiver_address: receiver,
+            token0_price_usd: Some(1.0),
+            token1_price_usd: Some(weth_price),
+            transaction_hash: tx.to_string(),
+            block_timestamp,
+            tx_sender: sender,
+            ..crate::test_util::swap(&usdc_weth(), buy_weth, usdc, weth)
+        }
+    }
+
+    #[test]
+    fn test_wallet_tracker() -> Result<()> {
+        let alice = address!("0x00000000000000000000000000000000000a11ce");
+        let bob = address!("0x0000000000000000000000000000000000000b0b");
+        let pairs = HashMap::from([(USDC_WETH, usdc_weth())]);
+        let mut tracker = WalletTracker::new(1, HashSet::from([ROUTER]));
+
+        // Alice buys 2 WETH at 1000, sells 1 at 1500 the same day and 1 at 800 the next.
+        // Bob has no enriched sender, so the router's receiver is the trader.
+        let events = tracker.track_batch(
+            &[
+                swap("0x01", Some(alice), alice, true, (2000.0, 2.0, 1000.0), 100),
+                swap("0x02", None, bob, true, (1000.0, 1.0, 1000.0), 200),
+                swap(
+                    "0x03",
+                    Some(alice),
+                    alice,
+                    false,
+                    (1500.0, 1.0, 1500.0),
+                    300,
+                ),
+                swap(
+                    "0x04",
+                    Some(alice),
+                    alice,
+                    false,
+                    (800.0, 1.0, 800.0),
+                    DAY_SECS + 1,
+                ),
+            ],
+            &pairs,
+        )?;
+        assert_eq!(events.len(), 3);
+        let alice_day0 = events
+            .iter()
+            .find(|e| e.wallet_address == alice && e.day == 0)
+            .unwrap();
+        assert_eq!(alice_day0.trade_count, 2);
+        assert!((alice_day0.volume_usd - 3500.0).abs() < 1e-6);
+        assert!((alice_day0.realized_pnl_usd - 500.0).abs() < 1e-6);
+        // 1 WETH left plus the USDC received.
+        assert_eq!(alice_day0.open_positions, 2);
+        let bob_day0 = events.iter().find(|e| e.wallet_address == bob).unwrap();
+        assert_eq!(bob_day0.trade_count, 1);
+        assert_eq!(bob_day0.realized_pnl_usd, 0.0);
+        let alice_day1 = events.iter().find(|e| e.day == DAY_SECS).unwrap();
+        assert!((alice_day1.realized_pnl_usd + 200.0).abs() < 1e-6);
+        assert_eq!(alice_day1.open_positions, 1);
+
+        let ranking = rank_traders(&events, 10);
+        assert_eq!(ranking.len(), 2);
+        assert_eq!(ranking[0].wallet_address, alice);
+        assert_eq!(ranking[0].trade_count, 3);
+        assert!((ranking[0].realized_pnl_usd - 300.0).abs() < 1e-6);
+        assert_eq!(ranking[1].rank, Some(2));
+        assert_eq!(rank_traders(&events, 1).len(), 1);
+        assert!(
+            alice_day0
+                .to_influx_line()
+                .starts_with("wallet_activity,chain_id=1,wallet_address=")
+        );
+
+        // Live: a quiet day is closed by the tick once the next day has started.
+        let mut tracker = WalletTracker::new(1, HashSet::from([ROUTER]));
+        let trade = swap("0x05", Some(bob), bob, true, (1000.0, 1.0, 1000.0), 300);
+        assert!(tracker.add_swap(&trade, &pairs)?.is_empty());
+        assert!(tracker.close_expired(DAY_SECS - 1, &pairs)?.is_empty());
+        let events = tracker.close_expired(DAY_SECS, &pairs)?;
+        assert_eq!((events.len(), events[0].day), (1, 0));
+        assert!(tracker.close_expired(DAY_SECS * 2, &pairs)?.is_empty());
+        Ok(())
+    }
+}